@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+use crate::{has_column, ColumnRef, LatLon, TableSchema};
+
+const MAX_RULE_NAME_LENGTH: usize = 64;
+
+/// Which side of a rule's threshold raises the alert
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Comparator {
+    #[default]
+    Above,
+    Below,
+}
+
+impl Comparator {
+    pub const ALL: [Comparator; 2] = [Comparator::Above, Comparator::Below];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Comparator::Above => "above",
+            Comparator::Below => "below",
+        }
+    }
+}
+
+/// Raises an alert once a column has stayed past a threshold for `duration`,
+/// which clears once it comes back past the threshold by `hysteresis`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AlertRule {
+    pub name: String,
+    pub source: ColumnRef,
+    pub comparator: Comparator,
+    pub threshold: f64,
+    pub duration: f64, // seconds
+    pub hysteresis: f64,
+}
+
+impl AlertRule {
+    /// Whether `value` is past the threshold
+    pub fn is_triggered(&self, value: f64) -> bool {
+        match self.comparator {
+            Comparator::Above => value > self.threshold,
+            Comparator::Below => value < self.threshold,
+        }
+    }
+
+    /// Whether `value` is far enough back from the threshold to clear a raised alert
+    pub fn is_cleared(&self, value: f64) -> bool {
+        match self.comparator {
+            Comparator::Above => value < self.threshold - self.hysteresis,
+            Comparator::Below => value > self.threshold + self.hysteresis,
+        }
+    }
+
+    /// Checks the rule can be evaluated against the columns in `schema`
+    pub fn validate(&self, schema: &[TableSchema]) -> Result<(), String> {
+        if self.name.trim().is_empty() || self.name.len() > MAX_RULE_NAME_LENGTH {
+            return Err(format!("alert names must be 1 to {MAX_RULE_NAME_LENGTH} characters"));
+        }
+        if !has_column(schema, &self.source) {
+            return Err(format!("{}.{} can't be watched", self.source.table, self.source.column));
+        }
+        if !self.threshold.is_finite() {
+            return Err(format!("threshold of \"{}\" must be a number", self.name));
+        }
+        if !(self.duration.is_finite() && self.duration >= 0.0) {
+            return Err(format!("duration of \"{}\" can't be negative", self.name));
+        }
+        if !(self.hysteresis.is_finite() && self.hysteresis >= 0.0) {
+            return Err(format!("hysteresis of \"{}\" can't be negative", self.name));
+        }
+        Ok(())
+    }
+}
+
+/// Checks every rule is valid and that their names are unique
+pub fn validate_rules(rules: &[AlertRule], schema: &[TableSchema]) -> Result<(), String> {
+    for (i, rule) in rules.iter().enumerate() {
+        rule.validate(schema)?;
+        if rules[..i].iter().any(|other| other.name == rule.name) {
+            return Err(format!("there is more than one alert called \"{}\"", rule.name));
+        }
+    }
+    Ok(())
+}
+
+/// A period during which a rule's alert was raised
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AlertEvent {
+    pub id: i64,
+    pub rule: String,
+    pub source: ColumnRef,
+    pub comparator: Comparator,
+    pub threshold: f64,
+    pub started_at: f64, // unix seconds
+    pub cleared_at: Option<f64>, // unix seconds, none while the alert is active
+    /// value furthest past the threshold while raised
+    pub peak: f64,
+    /// where the alert was raised, if there was a GPS fix nearby
+    #[serde(default)]
+    pub position: Option<LatLon>,
+}
+
+impl AlertEvent {
+    pub fn is_active(&self) -> bool {
+        self.cleared_at.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(name: &str, comparator: Comparator, threshold: f64, hysteresis: f64) -> AlertRule {
+        AlertRule {
+            name: name.to_string(),
+            source: ColumnRef { table: "thermalprobe_data".to_string(), column: "temperature_celsius".to_string() },
+            comparator,
+            threshold,
+            duration: 0.0,
+            hysteresis,
+        }
+    }
+
+    fn schema() -> Vec<TableSchema> {
+        vec![TableSchema { name: "thermalprobe_data".to_string(), columns: vec!["temperature_celsius".to_string()] }]
+    }
+
+    #[test]
+    fn above_triggers_past_threshold_and_clears_past_hysteresis() {
+        let rule = rule("hot", Comparator::Above, 30.0, 2.0);
+        assert!(!rule.is_triggered(30.0));
+        assert!(rule.is_triggered(30.1));
+        // between the threshold and the hysteresis band the alert neither raises nor clears
+        assert!(!rule.is_triggered(29.0) && !rule.is_cleared(29.0));
+        assert!(!rule.is_cleared(28.0));
+        assert!(rule.is_cleared(27.9));
+    }
+
+    #[test]
+    fn below_triggers_past_threshold_and_clears_past_hysteresis() {
+        let rule = rule("cold", Comparator::Below, -5.0, 1.5);
+        assert!(!rule.is_triggered(-5.0));
+        assert!(rule.is_triggered(-5.1));
+        assert!(!rule.is_triggered(-4.0) && !rule.is_cleared(-4.0));
+        assert!(!rule.is_cleared(-3.5));
+        assert!(rule.is_cleared(-3.4));
+    }
+
+    #[test]
+    fn without_hysteresis_clears_just_past_threshold() {
+        let rule = rule("hot", Comparator::Above, 30.0, 0.0);
+        assert!(!rule.is_cleared(30.0));
+        assert!(rule.is_cleared(29.99));
+    }
+
+    #[test]
+    fn valid_rules_pass() {
+        let rules = [rule("hot", Comparator::Above, 30.0, 2.0), rule("cold", Comparator::Below, 0.0, 0.0)];
+        assert_eq!(validate_rules(&rules, &schema()), Ok(()));
+        assert_eq!(validate_rules(&[], &schema()), Ok(()));
+    }
+
+    #[test]
+    fn duplicate_names_are_refused() {
+        let rules = [rule("hot", Comparator::Above, 30.0, 2.0), rule("hot", Comparator::Below, 0.0, 0.0)];
+        assert!(validate_rules(&rules, &schema()).is_err());
+    }
+
+    #[test]
+    fn invalid_rules_are_refused() {
+        let schema = schema();
+        let invalid = |change: fn(&mut AlertRule)| {
+            let mut rule = rule("hot", Comparator::Above, 30.0, 2.0);
+            change(&mut rule);
+            validate_rules(&[rule], &schema).is_err()
+        };
+        assert!(invalid(|rule| rule.name = " ".to_string()));
+        assert!(invalid(|rule| rule.name = "a".repeat(MAX_RULE_NAME_LENGTH + 1)));
+        assert!(invalid(|rule| rule.source.column = "humidity".to_string()));
+        assert!(invalid(|rule| rule.source.table = "gps_data".to_string()));
+        assert!(invalid(|rule| rule.threshold = f64::NAN));
+        assert!(invalid(|rule| rule.duration = -1.0));
+        assert!(invalid(|rule| rule.hysteresis = -0.5));
+        assert!(invalid(|rule| rule.hysteresis = f64::INFINITY));
+    }
+}
@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+use crate::LatLon;
+
+const MAX_TEXT_LENGTH: usize = 200;
+
+/// A note marking a moment of a run, placed where the GPS last had a fix if it had one nearby
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Annotation {
+    pub id: i64,
+    pub time: f64, // unix seconds
+    pub text: String,
+    pub position: Option<LatLon>,
+}
+
+/// Body of an annotation post, marking `time` or the moment the server receives it
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct NewAnnotation {
+    pub text: String,
+    #[serde(default)]
+    pub time: Option<f64>, // unix seconds
+}
+
+impl NewAnnotation {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.text.trim().is_empty() || self.text.len() > MAX_TEXT_LENGTH {
+            return Err(format!("annotations must be 1 to {MAX_TEXT_LENGTH} characters"));
+        }
+        if self.time.is_some_and(|time| !time.is_finite()) {
+            return Err("annotation time must be a number".to_string());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,377 @@
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+use crate::STANDARD_GRAVITY;
+
+pub const ACCELEROMETER_TABLE: &str = "accelerometer_data";
+/// Columns of the accelerometer's x, y and z axes, in the order of the calibration's arrays
+pub const ACCELEROMETER_COLUMNS: [&str; 3] = ["accelerometer_x", "accelerometer_y", "accelerometer_z"];
+pub const TEMPERATURE_TABLE: &str = "thermalprobe_data";
+pub const TEMPERATURE_COLUMN: &str = "temperature_celsius";
+/// Most points a temperature curve can have
+pub const MAX_CURVE_POINTS: usize = 16;
+const IDENTITY: [[f64; 3]; 3] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+/// Range of seconds of accelerometer readings which can be averaged into one capture
+pub const CAPTURE_SECONDS_RANGE: RangeInclusive<f64> = 0.5..=30.0;
+
+/// Corrections applied by the server to every sensor value it serves, leaving the recorded rows untouched
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Calibration {
+    /// counts up every time the calibration is saved, 0 until it first is
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default)]
+    pub accelerometer: AccelerometerCalibration,
+    #[serde(default)]
+    pub temperature: TemperatureCalibration,
+}
+
+/// Corrects a reading of the three axes to `alignment * (scale * (raw - offset))`, each axis first being
+/// offset and scaled on its own, then the result rotated onto the axes of whatever the sensor is mounted on
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AccelerometerCalibration {
+    /// reading of each axis at rest with no acceleration along it
+    pub offset: [f64; 3],
+    pub scale: [f64; 3],
+    /// rows give each corrected axis as a combination of the scaled sensor axes
+    pub alignment: [[f64; 3]; 3],
+}
+
+/// Corrects a reading to `scale * (raw - offset)`, then maps that through the curve
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TemperatureCalibration {
+    pub offset: f64,
+    pub scale: f64,
+    /// points of reading and reference temperature, by increasing reading, joined by straight lines and
+    /// extended past the ends by the first and last ones. Left empty, readings aren't mapped
+    pub curve: Vec<[f64; 2]>,
+}
+
+/// A previous version of the calibration, as kept in the server's history
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CalibrationRevision {
+    pub replaced_at: u64, // unix seconds
+    pub calibration: Calibration,
+}
+
+/// Uncalibrated accelerometer readings averaged over a few seconds, while calibrating
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AccelerometerCapture {
+    pub samples: usize,
+    pub mean: [f64; 3],
+    /// standard deviation of each axis, which is small while the sensor is held still
+    pub spread: [f64; 3],
+    /// unix time of the newest reading averaged
+    pub end: f64,
+}
+
+/// Ways up the accelerometer is held in while calibrating, each pointing one axis straight up or down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    XUp,
+    XDown,
+    YUp,
+    YDown,
+    ZUp,
+    ZDown,
+}
+
+impl Orientation {
+    /// Order the orientations are captured in, each axis up then down
+    pub const ALL: [Orientation; 6] = [Orientation::XUp, Orientation::XDown, Orientation::YUp, Orientation::YDown, Orientation::ZUp, Orientation::ZDown];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Orientation::XUp => "X axis pointing up",
+            Orientation::XDown => "X axis pointing down",
+            Orientation::YUp => "Y axis pointing up",
+            Orientation::YDown => "Y axis pointing down",
+            Orientation::ZUp => "Z axis pointing up",
+            Orientation::ZDown => "Z axis pointing down",
+        }
+    }
+
+    /// Axis pointing up or down, and 1 if it points up or -1 if down
+    fn axis(&self) -> (usize, f64) {
+        match self {
+            Orientation::XUp => (0, 1.0),
+            Orientation::XDown => (0, -1.0),
+            Orientation::YUp => (1, 1.0),
+            Orientation::YDown => (1, -1.0),
+            Orientation::ZUp => (2, 1.0),
+            Orientation::ZDown => (2, -1.0),
+        }
+    }
+
+    /// Whether `mean` was read in this orientation, its axis reading gravity more than the others and on the right side
+    pub fn matches(&self, mean: [f64; 3]) -> bool {
+        let (axis, sign) = self.axis();
+        mean[axis] * sign > 0.0 && (0..3).all(|other| mean[axis].abs() >= mean[other].abs())
+    }
+}
+
+impl Default for AccelerometerCalibration {
+    fn default() -> Self {
+        Self {
+            offset: [0.0; 3],
+            scale: [1.0; 3],
+            alignment: IDENTITY,
+        }
+    }
+}
+
+impl Default for TemperatureCalibration {
+    fn default() -> Self {
+        Self {
+            offset: 0.0,
+            scale: 1.0,
+            curve: vec![],
+        }
+    }
+}
+
+impl Calibration {
+    /// Checks every correction can be applied, and that the accelerometer's can be undone
+    pub fn validate(&self) -> Result<(), String> {
+        let accelerometer = &self.accelerometer;
+        let mut numbers = accelerometer.offset.iter()
+            .chain(&accelerometer.scale)
+            .chain(accelerometer.alignment.iter().flatten());
+        if numbers.any(|n| !n.is_finite()) {
+            return Err("the accelerometer calibration must only hold finite numbers".to_string());
+        }
+        if accelerometer.scale.contains(&0.0) {
+            return Err("the accelerometer's scales can't be 0".to_string());
+        }
+        if determinant(&accelerometer.alignment).abs() < 1e-9 {
+            return Err("the accelerometer's alignment matrix must be invertible".to_string());
+        }
+
+        let temperature = &self.temperature;
+        if !temperature.offset.is_finite() || !temperature.scale.is_finite() || temperature.curve.iter().flatten().any(|n| !n.is_finite()) {
+            return Err("the temperature calibration must only hold finite numbers".to_string());
+        }
+        if temperature.scale == 0.0 {
+            return Err("the temperature scale can't be 0".to_string());
+        }
+        if temperature.curve.len() == 1 || temperature.curve.len() > MAX_CURVE_POINTS {
+            return Err(format!("the temperature curve needs 2 to {} points, or none", MAX_CURVE_POINTS));
+        }
+        if temperature.curve.windows(2).any(|pair| pair[1][0] <= pair[0][0]) {
+            return Err("the temperature curve's readings must be increasing".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl AccelerometerCalibration {
+    /// Calibration reading standard gravity along each axis held up, and minus it held down,
+    /// from the mean readings captured in each of `Orientation::ALL`.
+    ///
+    /// The offset is the middle of every axis' readings. Reading an axis up and down also shows how much of
+    /// gravity leaks into the other two, so the alignment matrix corrects the axes not being quite square
+    /// as well as the sensor not being mounted square with whatever it's on.
+    pub fn from_orientations(means: &[[f64; 3]; 6]) -> Result<Self, String> {
+        for (orientation, mean) in Orientation::ALL.iter().zip(means) {
+            if !orientation.matches(*mean) {
+                return Err(format!("the readings captured with the {} don't show gravity along it", orientation.as_str()));
+            }
+        }
+
+        let mut offset = [0.0; 3];
+        for mean in means {
+            for axis in 0..3 {
+                offset[axis] += mean[axis] / 6.0;
+            }
+        }
+        // column i is half the change in the readings between axis i pointing up and down
+        let mut half_span = [[0.0; 3]; 3];
+        for i in 0..3 {
+            for axis in 0..3 {
+                half_span[axis][i] = (means[2 * i][axis] - means[2 * i + 1][axis]) / 2.0;
+            }
+        }
+        let inverse = inverse(&half_span).ok_or("the captured readings don't tell the axes apart")?;
+
+        // the whole correction is gravity times that inverse, split into a scale for each axis and the alignment of them
+        let scale = [0, 1, 2].map(|i| STANDARD_GRAVITY * inverse[i][i]);
+        if scale.contains(&0.0) {
+            return Err("the captured readings don't tell the axes apart".to_string());
+        }
+        let alignment = [0, 1, 2].map(|row| [0, 1, 2].map(|column| STANDARD_GRAVITY * inverse[row][column] / scale[column]));
+        Ok(Self { offset, scale, alignment })
+    }
+}
+
+/// Inverse of a matrix, none if it has none
+fn inverse(m: &[[f64; 3]; 3]) -> Option<[[f64; 3]; 3]> {
+    let det = determinant(m);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    // transposed matrix of cofactors, divided by the determinant
+    let cofactor = |row: usize, column: usize| {
+        let (r0, r1) = ((row + 1) % 3, (row + 2) % 3);
+        let (c0, c1) = ((column + 1) % 3, (column + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    Some([0, 1, 2].map(|row| [0, 1, 2].map(|column| cofactor(column, row) / det)))
+}
+
+fn determinant(m: &[[f64; 3]; 3]) -> f64 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Raw readings of a sensor reading `acceleration` through `scale` and `offset`,
+    /// its axes leaking into each other by `skew`
+    fn reading(acceleration: [f64; 3], offset: [f64; 3], scale: [f64; 3], skew: [[f64; 3]; 3]) -> [f64; 3] {
+        [0, 1, 2].map(|axis| offset[axis] + (0..3).map(|i| skew[axis][i] * acceleration[i]).sum::<f64>() / scale[axis])
+    }
+
+    /// Readings in each of `Orientation::ALL`
+    fn orientation_readings(offset: [f64; 3], scale: [f64; 3], skew: [[f64; 3]; 3]) -> [[f64; 3]; 6] {
+        Orientation::ALL.map(|orientation| {
+            let (axis, sign) = orientation.axis();
+            let mut acceleration = [0.0; 3];
+            acceleration[axis] = sign * STANDARD_GRAVITY;
+            reading(acceleration, offset, scale, skew)
+        })
+    }
+
+    fn apply(calibration: &AccelerometerCalibration, raw: [f64; 3]) -> [f64; 3] {
+        let scaled = [0, 1, 2].map(|i| calibration.scale[i] * (raw[i] - calibration.offset[i]));
+        [0, 1, 2].map(|row| (0..3).map(|i| calibration.alignment[row][i] * scaled[i]).sum())
+    }
+
+    fn assert_close(actual: [f64; 3], expected: [f64; 3]) {
+        for axis in 0..3 {
+            assert!((actual[axis] - expected[axis]).abs() < 1e-9, "{:?} isn't {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn orientations_recover_offset_and_scale() {
+        let (offset, scale) = ([0.12, -0.3, 0.05], [1.02, 0.97, 2.0]);
+        let calibration = AccelerometerCalibration::from_orientations(&orientation_readings(offset, scale, IDENTITY)).unwrap();
+        assert_close(calibration.offset, offset);
+        assert_close(calibration.scale, scale);
+        for (row, identity) in calibration.alignment.iter().zip(IDENTITY) {
+            assert_close(*row, identity);
+        }
+        assert_eq!(Calibration { accelerometer: calibration, ..Calibration::default() }.validate(), Ok(()));
+    }
+
+    #[test]
+    fn orientations_correct_skewed_axes() {
+        let (offset, scale) = ([0.2, 0.1, -0.4], [0.9, 1.1, 1.0]);
+        let skew = [[1.0, 0.03, -0.02], [0.01, 1.0, 0.04], [-0.05, 0.02, 1.0]];
+        let calibration = AccelerometerCalibration::from_orientations(&orientation_readings(offset, scale, skew)).unwrap();
+        assert_close(calibration.offset, offset);
+        // any acceleration, not only those calibrated with, reads back as it was
+        let acceleration = [1.5, -3.0, 9.0];
+        assert_close(apply(&calibration, reading(acceleration, offset, scale, skew)), acceleration);
+    }
+
+    #[test]
+    fn swapped_orientations_are_refused() {
+        let mut means = orientation_readings([0.0; 3], [1.0; 3], IDENTITY);
+        means.swap(0, 1);
+        assert!(AccelerometerCalibration::from_orientations(&means).is_err());
+
+        let mut means = orientation_readings([0.0; 3], [1.0; 3], IDENTITY);
+        means.swap(1, 2);
+        assert!(AccelerometerCalibration::from_orientations(&means).is_err());
+    }
+
+    #[test]
+    fn duplicated_orientation_is_refused() {
+        let mut means = orientation_readings([0.0; 3], [1.0; 3], IDENTITY);
+        means[3] = means[2];
+        assert!(AccelerometerCalibration::from_orientations(&means).is_err());
+    }
+
+    #[test]
+    fn readings_not_telling_axes_apart_are_refused() {
+        // every orientation passes on its own, but y and z move together, so can't be told apart
+        let means = [
+            [9.8, 0.0, 0.0], [-9.8, 0.0, 0.0],
+            [0.0, 9.8, 9.8], [0.0, -9.8, -9.8],
+            [0.0, 9.8, 9.8], [0.0, -9.8, -9.8],
+        ];
+        assert!(AccelerometerCalibration::from_orientations(&means).is_err());
+    }
+
+    #[test]
+    fn inverse_of_known_matrices() {
+        assert_eq!(inverse(&IDENTITY), Some(IDENTITY));
+        let m = [[2.0, 0.0, 0.0], [0.0, 0.0, 4.0], [0.0, 0.5, 0.0]];
+        let inverse = inverse(&m).unwrap();
+        for (row, identity) in m.iter().zip(IDENTITY) {
+            let product = [0, 1, 2].map(|column| (0..3).map(|i| row[i] * inverse[i][column]).sum());
+            assert_close(product, identity);
+        }
+    }
+
+    #[test]
+    fn singular_alignment_has_no_inverse() {
+        let singular = [[1.0, 2.0, 3.0], [2.0, 4.0, 6.0], [0.0, 0.0, 1.0]];
+        assert_eq!(inverse(&singular), None);
+        assert_eq!(inverse(&[[0.0; 3]; 3]), None);
+        let calibration = Calibration {
+            accelerometer: AccelerometerCalibration { alignment: singular, ..AccelerometerCalibration::default() },
+            ..Calibration::default()
+        };
+        assert!(calibration.validate().is_err());
+    }
+
+    fn with_temperature(offset: f64, scale: f64, curve: Vec<[f64; 2]>) -> Calibration {
+        Calibration { temperature: TemperatureCalibration { offset, scale, curve }, ..Calibration::default() }
+    }
+
+    #[test]
+    fn default_and_curved_calibrations_are_valid() {
+        assert_eq!(Calibration::default().validate(), Ok(()));
+        assert_eq!(with_temperature(0.5, 1.1, vec![[0.0, 0.0], [50.0, 52.0], [100.0, 101.0]]).validate(), Ok(()));
+    }
+
+    #[test]
+    fn curve_readings_must_increase() {
+        assert!(with_temperature(0.0, 1.0, vec![[10.0, 0.0], [0.0, 5.0]]).validate().is_err());
+        assert!(with_temperature(0.0, 1.0, vec![[0.0, 0.0], [10.0, 5.0], [10.0, 6.0]]).validate().is_err());
+    }
+
+    #[test]
+    fn curve_needs_two_to_max_points() {
+        assert!(with_temperature(0.0, 1.0, vec![[0.0, 0.0]]).validate().is_err());
+        let curve: Vec<[f64; 2]> = (0..=MAX_CURVE_POINTS).map(|i| [i as f64, i as f64]).collect();
+        assert!(with_temperature(0.0, 1.0, curve[..MAX_CURVE_POINTS].to_vec()).validate().is_ok());
+        assert!(with_temperature(0.0, 1.0, curve).validate().is_err());
+    }
+
+    #[test]
+    fn non_finite_numbers_are_refused() {
+        assert!(with_temperature(f64::NAN, 1.0, vec![]).validate().is_err());
+        assert!(with_temperature(0.0, f64::INFINITY, vec![]).validate().is_err());
+        assert!(with_temperature(0.0, 1.0, vec![[0.0, 0.0], [1.0, f64::NAN]]).validate().is_err());
+
+        let mut calibration = Calibration::default();
+        calibration.accelerometer.offset[1] = f64::NAN;
+        assert!(calibration.validate().is_err());
+        let mut calibration = Calibration::default();
+        calibration.accelerometer.alignment[2][0] = f64::NAN;
+        assert!(calibration.validate().is_err());
+    }
+
+    #[test]
+    fn zero_scales_are_refused() {
+        assert!(with_temperature(0.0, 0.0, vec![]).validate().is_err());
+        let mut calibration = Calibration::default();
+        calibration.accelerometer.scale[2] = 0.0;
+        assert!(calibration.validate().is_err());
+    }
+}
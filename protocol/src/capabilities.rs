@@ -0,0 +1,213 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Value of one hardware setting, stored in `Config` and published to the acquisition process as text
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum SettingValue {
+    Toggle(bool),
+    Number(f64),
+    Choice(String),
+}
+
+impl std::fmt::Display for SettingValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SettingValue::Toggle(on) => write!(f, "{}", on),
+            SettingValue::Number(number) => write!(f, "{}", number),
+            SettingValue::Choice(choice) => write!(f, "{}", choice),
+        }
+    }
+}
+
+/// What a setting can be set to, which decides the control it's edited with
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum SettingKind {
+    Toggle,
+    Number {
+        min: f64,
+        max: f64,
+        /// change of the value per pixel dragged
+        step: f64,
+    },
+    Choice(Vec<String>),
+}
+
+/// One setting a sensor's acquisition hardware offers
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SettingDescription {
+    /// name the setting is stored and published under, unique across every sensor
+    pub name: String,
+    pub label: String,
+    pub kind: SettingKind,
+    /// value used until the setting is first saved
+    pub default: SettingValue,
+    /// unit shown after the value, e.g. Hz
+    #[serde(default)]
+    pub unit: String,
+}
+
+/// The settings offered by one sensor, as described by the server
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SensorCapabilities {
+    pub name: String,
+    pub title: String,
+    /// toggle switching the sensor on, its other settings only apply while it is
+    #[serde(default)]
+    pub enabled_by: Option<String>,
+    pub settings: Vec<SettingDescription>,
+}
+
+impl SettingDescription {
+    /// Checks `value` is one this setting can be set to
+    pub fn check(&self, value: &SettingValue) -> Result<(), String> {
+        match (&self.kind, value) {
+            (SettingKind::Toggle, SettingValue::Toggle(_)) => Ok(()),
+            (SettingKind::Number { min, max, .. }, SettingValue::Number(number)) if (*min..=*max).contains(number) => Ok(()),
+            (SettingKind::Number { min, max, .. }, _) => Err(format!("{} must be a number within {} to {}", self.name, min, max)),
+            (SettingKind::Choice(options), SettingValue::Choice(choice)) if options.contains(choice) => Ok(()),
+            (SettingKind::Choice(options), _) => Err(format!("{} must be one of {}", self.name, options.join(", "))),
+            (SettingKind::Toggle, _) => Err(format!("{} must be true or false", self.name)),
+        }
+    }
+}
+
+/// Every setting offered by `sensors`, in order
+pub fn settings(sensors: &[SensorCapabilities]) -> impl Iterator<Item = &SettingDescription> {
+    sensors.iter().flat_map(|sensor| &sensor.settings)
+}
+
+/// Checks a capability description: setting names must be unique, defaults valid and
+/// each sensor only enabled by one of its own toggles
+pub fn validate_capabilities(sensors: &[SensorCapabilities]) -> Result<(), String> {
+    let mut names = std::collections::HashSet::new();
+    for setting in settings(sensors) {
+        if !names.insert(&setting.name) {
+            return Err(format!("{} is described more than once", setting.name));
+        }
+        if let SettingKind::Number { min, max, step } = setting.kind {
+            if !(min <= max && step > 0.0) {
+                return Err(format!("{} must have a range from low to high and a positive step", setting.name));
+            }
+        }
+        setting.check(&setting.default)?;
+    }
+    for sensor in sensors {
+        if let Some(toggle) = &sensor.enabled_by {
+            let described = sensor.settings.iter().any(|s| &s.name == toggle && s.kind == SettingKind::Toggle);
+            if !described {
+                return Err(format!("{} must be enabled by one of its own toggles, not {}", sensor.name, toggle));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks every value in `hardware` is for a setting `sensors` offer, and is one it can be set to
+pub fn validate_hardware(hardware: &BTreeMap<String, SettingValue>, sensors: &[SensorCapabilities]) -> Result<(), String> {
+    for (name, value) in hardware {
+        let setting = settings(sensors)
+            .find(|setting| &setting.name == name)
+            .ok_or_else(|| format!("{} isn't a setting of any sensor", name))?;
+        setting.check(value)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setting(name: &str, kind: SettingKind, default: SettingValue) -> SettingDescription {
+        SettingDescription { name: name.to_string(), label: name.to_string(), kind, default, unit: String::new() }
+    }
+
+    fn accelerometer() -> Vec<SensorCapabilities> {
+        vec![SensorCapabilities {
+            name: "accelerometer".to_string(),
+            title: "Accelerometer".to_string(),
+            enabled_by: Some("accel_enabled".to_string()),
+            settings: vec![
+                setting("accel_enabled", SettingKind::Toggle, SettingValue::Toggle(true)),
+                setting("accel_cutoff", SettingKind::Number { min: 0.1, max: 200.0, step: 0.1 }, SettingValue::Number(10.0)),
+                setting("accel_range", SettingKind::Choice(vec!["2".to_string(), "4".to_string()]), SettingValue::Choice("4".to_string())),
+            ],
+        }]
+    }
+
+    fn hardware(name: &str, value: SettingValue) -> BTreeMap<String, SettingValue> {
+        [(name.to_string(), value)].into_iter().collect()
+    }
+
+    #[test]
+    fn valid_capabilities_and_hardware_pass() {
+        let sensors = accelerometer();
+        assert_eq!(validate_capabilities(&sensors), Ok(()));
+        assert_eq!(validate_hardware(&hardware("accel_enabled", SettingValue::Toggle(false)), &sensors), Ok(()));
+        assert_eq!(validate_hardware(&hardware("accel_cutoff", SettingValue::Number(200.0)), &sensors), Ok(()));
+        assert_eq!(validate_hardware(&hardware("accel_range", SettingValue::Choice("2".to_string())), &sensors), Ok(()));
+        assert_eq!(validate_hardware(&BTreeMap::new(), &sensors), Ok(()));
+    }
+
+    #[test]
+    fn hardware_with_unknown_name_is_refused() {
+        assert!(validate_hardware(&hardware("accel_enabeld", SettingValue::Toggle(true)), &accelerometer()).is_err());
+    }
+
+    #[test]
+    fn hardware_of_wrong_kind_is_refused() {
+        let sensors = accelerometer();
+        assert!(validate_hardware(&hardware("accel_enabled", SettingValue::Number(1.0)), &sensors).is_err());
+        assert!(validate_hardware(&hardware("accel_cutoff", SettingValue::Choice("10".to_string())), &sensors).is_err());
+        assert!(validate_hardware(&hardware("accel_range", SettingValue::Number(2.0)), &sensors).is_err());
+    }
+
+    #[test]
+    fn hardware_out_of_range_or_unknown_choice_is_refused() {
+        let sensors = accelerometer();
+        assert!(validate_hardware(&hardware("accel_cutoff", SettingValue::Number(0.0)), &sensors).is_err());
+        assert!(validate_hardware(&hardware("accel_cutoff", SettingValue::Number(f64::NAN)), &sensors).is_err());
+        assert!(validate_hardware(&hardware("accel_range", SettingValue::Choice("16".to_string())), &sensors).is_err());
+    }
+
+    #[test]
+    fn capabilities_with_duplicate_names_are_refused() {
+        let mut sensors = accelerometer();
+        let duplicate = sensors[0].settings[1].clone();
+        sensors[0].settings.push(duplicate);
+        assert!(validate_capabilities(&sensors).is_err());
+    }
+
+    #[test]
+    fn capabilities_with_invalid_defaults_are_refused() {
+        let mut sensors = accelerometer();
+        sensors[0].settings[0].default = SettingValue::Number(1.0);
+        assert!(validate_capabilities(&sensors).is_err());
+
+        let mut sensors = accelerometer();
+        sensors[0].settings[1].default = SettingValue::Number(500.0);
+        assert!(validate_capabilities(&sensors).is_err());
+
+        let mut sensors = accelerometer();
+        sensors[0].settings[2].default = SettingValue::Choice("8".to_string());
+        assert!(validate_capabilities(&sensors).is_err());
+    }
+
+    #[test]
+    fn capabilities_with_bad_range_or_toggle_are_refused() {
+        let mut sensors = accelerometer();
+        sensors[0].settings[1].kind = SettingKind::Number { min: 10.0, max: 1.0, step: 0.1 };
+        assert!(validate_capabilities(&sensors).is_err());
+
+        let mut sensors = accelerometer();
+        sensors[0].settings[1].kind = SettingKind::Number { min: 0.1, max: 200.0, step: 0.0 };
+        assert!(validate_capabilities(&sensors).is_err());
+
+        // a sensor can only be enabled by one of its own toggles
+        let mut sensors = accelerometer();
+        sensors[0].enabled_by = Some("accel_cutoff".to_string());
+        assert!(validate_capabilities(&sensors).is_err());
+        sensors[0].enabled_by = Some("gps_enabled".to_string());
+        assert!(validate_capabilities(&sensors).is_err());
+    }
+}
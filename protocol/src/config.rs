@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use crate::{settings, validate_hardware, CoordinateFormat, SensorCapabilities, SettingValue, Units};
+
+pub const REFRESH_TIME_RANGE: RangeInclusive<f32> = 0.1..=120.0;
+/// Hardware settings which older servers stored alongside the other fields of the config, rather than in `hardware`
+pub const LEGACY_HARDWARE_SETTINGS: [&str; 4] = ["temp_enabled", "temp_sensitivity", "accel_enabled", "accel_sensitivity"];
+
+/// Sensor and display settings, edited on the config panel and stored by the server
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub refresh_time: f32, // seconds
+    /// how positions are shown in the log and on the map
+    #[serde(default)]
+    pub coordinate_format: CoordinateFormat,
+    /// units values are shown in across the UI
+    #[serde(default)]
+    pub units: Units,
+    /// values of the settings described by the server's `SensorCapabilities`, by name, those never saved being left out
+    #[serde(default)]
+    pub hardware: BTreeMap<String, SettingValue>,
+}
+
+/// A previous version of the config, as kept in the server's history
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ConfigRevision {
+    pub replaced_at: u64, // unix seconds
+    #[serde(alias = "settings")]
+    pub config: Config,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            refresh_time: 1.0,
+            coordinate_format: CoordinateFormat::default(),
+            units: Units::default(),
+            hardware: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Moves the `LEGACY_HARDWARE_SETTINGS` of a config stored by an older server into `hardware`,
+    /// so it deserializes like a current one. A current config is left as it is
+    pub fn migrate(config: &mut serde_json::Value) {
+        let Some(fields) = config.as_object_mut() else {
+            return;
+        };
+        let legacy: Vec<(String, serde_json::Value)> = LEGACY_HARDWARE_SETTINGS.iter()
+            .filter_map(|name| fields.remove(*name).map(|value| (name.to_string(), value)))
+            .collect();
+        if legacy.is_empty() {
+            return;
+        }
+        let hardware = fields.entry("hardware").or_insert_with(|| serde_json::Value::Object(Default::default()));
+        if let Some(hardware) = hardware.as_object_mut() {
+            for (name, value) in legacy {
+                hardware.entry(name).or_insert(value);
+            }
+        }
+    }
+
+    /// Checks every value is within the range accepted by the server, and every hardware setting is one `sensors` offer
+    pub fn validate(&self, sensors: &[SensorCapabilities]) -> Result<(), String> {
+        if !REFRESH_TIME_RANGE.contains(&self.refresh_time) {
+            return Err(format!("refresh_time must be within {:?}", REFRESH_TIME_RANGE));
+        }
+        validate_hardware(&self.hardware, sensors)
+    }
+
+    /// Value of the hardware setting called `name`, its default if it was never saved
+    pub fn hardware_value(&self, name: &str, sensors: &[SensorCapabilities]) -> Option<SettingValue> {
+        self.hardware.get(name).cloned()
+            .or_else(|| settings(sensors).find(|setting| setting.name == name).map(|setting| setting.default.clone()))
+    }
+
+    /// Every setting offered by `sensors` as name and value pairs, to be acted on by the acquisition hardware
+    pub fn hardware_settings(&self, sensors: &[SensorCapabilities]) -> Vec<(String, String)> {
+        settings(sensors)
+            .map(|setting| {
+                let value = self.hardware.get(&setting.name).unwrap_or(&setting.default);
+                (setting.name.clone(), value.to_string())
+            })
+            .collect()
+    }
+}
+
+/// Publication state of one hardware setting, as reported by the acquisition process
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SettingStatus {
+    pub name: String,
+    pub value: String,
+    pub requested_at: String,
+    pub applied_value: Option<String>,
+    pub applied_at: Option<String>,
+}
+
+impl SettingStatus {
+    /// Whether the acquisition process has applied the latest requested value
+    pub fn is_applied(&self) -> bool {
+        self.applied_value.as_deref() == Some(self.value.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `settings.json` as stored before hardware settings were described by the sensors
+    const LEGACY_SETTINGS: &str = r#"{"temp_enabled":false,"temp_sensitivity":42.5,"accel_enabled":true,"accel_sensitivity":57.8,"refresh_time":9.3}"#;
+
+    #[test]
+    fn legacy_settings_migrate_into_hardware() {
+        let mut json: serde_json::Value = serde_json::from_str(LEGACY_SETTINGS).unwrap();
+        Config::migrate(&mut json);
+        let config: Config = serde_json::from_value(json).unwrap();
+
+        assert_eq!(config.refresh_time, 9.3);
+        assert_eq!(config.units, Units::default());
+        assert_eq!(config.hardware.get("temp_enabled"), Some(&SettingValue::Toggle(false)));
+        assert_eq!(config.hardware.get("temp_sensitivity"), Some(&SettingValue::Number(42.5)));
+        assert_eq!(config.hardware.get("accel_enabled"), Some(&SettingValue::Toggle(true)));
+        assert_eq!(config.hardware.get("accel_sensitivity"), Some(&SettingValue::Number(57.8)));
+
+        // stored again, it reads back the same without migrating
+        let stored = serde_json::to_string(&config).unwrap();
+        assert_eq!(serde_json::from_str::<Config>(&stored).unwrap(), config);
+    }
+
+    #[test]
+    fn migrating_leaves_current_config_alone() {
+        let config = Config {
+            hardware: [("temp_enabled".to_string(), SettingValue::Toggle(false))].into_iter().collect(),
+            ..Config::default()
+        };
+        let mut json = serde_json::to_value(&config).unwrap();
+        Config::migrate(&mut json);
+        assert_eq!(serde_json::from_value::<Config>(json).unwrap(), config);
+    }
+
+    #[test]
+    fn unknown_fields_are_refused() {
+        assert!(serde_json::from_str::<Config>(LEGACY_SETTINGS).is_err());
+        assert!(serde_json::from_str::<Config>(r#"{"refresh_time":1.0,"refresh_tme":2.0}"#).is_err());
+    }
+}
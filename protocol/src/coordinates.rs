@@ -0,0 +1,205 @@
+use serde::{Deserialize, Serialize};
+use crate::LatLon;
+
+/// Semi-major axis of the WGS 84 ellipsoid, which GPS positions are given on, in metres
+const WGS84_A: f64 = 6378137.0;
+/// Flattening of the WGS 84 ellipsoid
+const WGS84_F: f64 = 1.0 / 298.257223563;
+/// Scale factor on the central meridian of every UTM zone
+const UTM_K0: f64 = 0.9996;
+const FALSE_EASTING: f64 = 500000.0;
+/// Added to the northing of southern hemisphere positions, so it is never negative
+const FALSE_NORTHING: f64 = 10000000.0;
+/// Latitude bands of 8° from 80°S, the last one stretching to 84°N
+const LATITUDE_BANDS: &str = "CDEFGHJKLMNPQRSTUVWX";
+/// Letters of the columns of 100 km squares, repeating every three zones
+const MGRS_COLUMNS: [&str; 3] = ["ABCDEFGH", "JKLMNPQR", "STUVWXYZ"];
+/// Letters of the rows of 100 km squares, starting 5 letters on in even zones
+const MGRS_ROWS: &str = "ABCDEFGHJKLMNPQRSTUV";
+
+/// How positions are shown
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum CoordinateFormat {
+    /// degrees with a decimal fraction, e.g. 44.591900, -123.267050
+    #[default]
+    Decimal,
+    /// degrees, minutes and seconds, e.g. 44°35'30.8"N 123°16'01.4"W
+    Dms,
+    /// zone, band, easting and northing in metres, e.g. 10T 478803 4937651
+    Utm,
+    /// military grid reference to the metre, e.g. 10T DQ 78803 37651
+    Mgrs,
+}
+
+impl CoordinateFormat {
+    pub const ALL: [CoordinateFormat; 4] = [CoordinateFormat::Decimal, CoordinateFormat::Dms, CoordinateFormat::Utm, CoordinateFormat::Mgrs];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CoordinateFormat::Decimal => "Decimal degrees",
+            CoordinateFormat::Dms => "Degrees, minutes, seconds",
+            CoordinateFormat::Utm => "UTM",
+            CoordinateFormat::Mgrs => "MGRS",
+        }
+    }
+
+    /// Shows `position` in this format. Positions outside UTM's 80°S to 84°N fall back to decimal degrees
+    pub fn format(&self, position: LatLon) -> String {
+        let grid = match self {
+            CoordinateFormat::Decimal => None,
+            CoordinateFormat::Dms => return format_dms(position),
+            CoordinateFormat::Utm => Utm::from_lat_lon(position).map(|utm| utm.to_string()),
+            CoordinateFormat::Mgrs => Utm::from_lat_lon(position).map(|utm| utm.to_mgrs()),
+        };
+        grid.unwrap_or_else(|| format!("{:.6}, {:.6}", position[0], position[1]))
+    }
+}
+
+/// Degrees, minutes and seconds to a tenth of a second, with the hemisphere
+pub fn format_dms([lat, lon]: LatLon) -> String {
+    let dms = |degrees: f64, positive: char, negative: char| {
+        let hemisphere = if degrees < 0.0 { negative } else { positive };
+        // rounding the whole value first keeps 59.96" from showing as 60.0"
+        let tenths = (degrees.abs() * 36000.0).round() as u64;
+        format!("{}°{:02}'{:02}.{}\"{}", tenths / 36000, tenths / 600 % 60, tenths / 10 % 60, tenths % 10, hemisphere)
+    };
+    format!("{} {}", dms(lat, 'N', 'S'), dms(lon, 'E', 'W'))
+}
+
+/// A position on the Universal Transverse Mercator grid
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Utm {
+    pub zone: u8,
+    /// latitude band letter, from C in the south to X in the north
+    pub band: char,
+    pub easting: f64, // metres
+    pub northing: f64, // metres
+}
+
+impl Utm {
+    /// Projects a WGS 84 position onto its UTM zone, none outside the grid's 80°S to 84°N
+    pub fn from_lat_lon([lat, lon]: LatLon) -> Option<Self> {
+        if !(-80.0..=84.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+            return None;
+        }
+        let band = LATITUDE_BANDS.chars().nth((((lat + 80.0) / 8.0) as usize).min(19))?;
+        let zone = zone(lon, band);
+
+        // Snyder's series for the transverse Mercator projection of an ellipsoid
+        let e2 = WGS84_F * (2.0 - WGS84_F);
+        let ep2 = e2 / (1.0 - e2);
+        let (e4, e6) = (e2 * e2, e2 * e2 * e2);
+        let central_meridian = (zone as f64 - 1.0) * 6.0 - 180.0 + 3.0;
+        let phi = lat.to_radians();
+        let (sin, cos, tan) = (phi.sin(), phi.cos(), phi.tan());
+
+        let n = WGS84_A / (1.0 - e2 * sin * sin).sqrt();
+        let t = tan * tan;
+        let c = ep2 * cos * cos;
+        let a = cos * (lon - central_meridian).to_radians();
+        let m = WGS84_A * ((1.0 - e2 / 4.0 - 3.0 * e4 / 64.0 - 5.0 * e6 / 256.0) * phi
+            - (3.0 * e2 / 8.0 + 3.0 * e4 / 32.0 + 45.0 * e6 / 1024.0) * (2.0 * phi).sin()
+            + (15.0 * e4 / 256.0 + 45.0 * e6 / 1024.0) * (4.0 * phi).sin()
+            - (35.0 * e6 / 3072.0) * (6.0 * phi).sin());
+
+        let easting = UTM_K0 * n * (a
+            + (1.0 - t + c) * a.powi(3) / 6.0
+            + (5.0 - 18.0 * t + t * t + 72.0 * c - 58.0 * ep2) * a.powi(5) / 120.0)
+            + FALSE_EASTING;
+        let mut northing = UTM_K0 * (m + n * tan * (a * a / 2.0
+            + (5.0 - t + 9.0 * c + 4.0 * c * c) * a.powi(4) / 24.0
+            + (61.0 - 58.0 * t + t * t + 600.0 * c - 330.0 * ep2) * a.powi(6) / 720.0));
+        if lat < 0.0 {
+            northing += FALSE_NORTHING;
+        }
+
+        Some(Self { zone, band, easting, northing })
+    }
+
+    /// Military grid reference of the position to the metre: zone and band, 100 km square, then easting and northing within it
+    pub fn to_mgrs(&self) -> String {
+        let set = (self.zone as usize - 1) % 3;
+        let column = (self.easting / 100000.0).floor() as usize;
+        let row = (self.northing / 100000.0).floor() as usize + if self.zone % 2 == 1 { 0 } else { 5 };
+        let column = MGRS_COLUMNS[set].chars().nth(column.saturating_sub(1).min(7)).unwrap_or('?');
+        let row = MGRS_ROWS.chars().nth(row % MGRS_ROWS.len()).unwrap_or('?');
+        let metres = |value: f64| value.floor() as u64 % 100000;
+        format!("{}{} {}{} {:05} {:05}", self.zone, self.band, column, row, metres(self.easting), metres(self.northing))
+    }
+}
+
+impl std::fmt::Display for Utm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{} {:.0} {:.0}", self.zone, self.band, self.easting.floor(), self.northing.floor())
+    }
+}
+
+/// UTM zone of a position, which are 6° wide except around south west Norway and Svalbard
+fn zone(lon: f64, band: char) -> u8 {
+    let zone = ((((lon + 180.0) / 6.0).floor() as i64).clamp(0, 59) + 1) as u8;
+    match band {
+        'V' if (3.0..12.0).contains(&lon) => 32,
+        'X' if (0.0..9.0).contains(&lon) => 31,
+        'X' if (9.0..21.0).contains(&lon) => 33,
+        'X' if (21.0..33.0).contains(&lon) => 35,
+        'X' if (33.0..42.0).contains(&lon) => 37,
+        _ => zone,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_utm(position: LatLon, zone: u8, band: char, easting: f64, northing: f64) {
+        let utm = Utm::from_lat_lon(position).unwrap();
+        assert_eq!((utm.zone, utm.band), (zone, band), "zone of {:?}", position);
+        assert!((utm.easting - easting).abs() < 1.0, "easting of {:?} was {}", position, utm.easting);
+        assert!((utm.northing - northing).abs() < 1.0, "northing of {:?} was {}", position, utm.northing);
+    }
+
+    #[test]
+    fn utm_of_known_positions() {
+        assert_utm([0.0, 0.0], 31, 'N', 166021.0, 0.0);
+        assert_utm([0.0, 3.0], 31, 'N', 500000.0, 0.0);
+        assert_utm([40.71435, -74.00597], 18, 'T', 583960.0, 4507523.0);
+        assert_utm([50.77535, 6.08389], 32, 'U', 294409.0, 5628898.0);
+        // the southern hemisphere mirrors the northern one about the equator
+        assert_utm([-40.71435, -74.00597], 18, 'G', 583960.0, FALSE_NORTHING - 4507523.0);
+    }
+
+    #[test]
+    fn utm_zone_exceptions() {
+        assert_eq!(Utm::from_lat_lon([60.0, 4.0]).unwrap().zone, 32);
+        assert_eq!(Utm::from_lat_lon([78.0, 10.0]).unwrap().zone, 33);
+        assert_eq!(Utm::from_lat_lon([78.0, 8.0]).unwrap().zone, 31);
+        assert_eq!(Utm::from_lat_lon([10.0, 180.0]).unwrap().zone, 60);
+    }
+
+    #[test]
+    fn utm_only_covers_80s_to_84n() {
+        assert!(Utm::from_lat_lon([84.5, 0.0]).is_none());
+        assert!(Utm::from_lat_lon([-80.5, 0.0]).is_none());
+        assert_eq!(CoordinateFormat::Utm.format([85.0, 1.5]), "85.000000, 1.500000");
+    }
+
+    #[test]
+    fn mgrs_of_known_positions() {
+        assert_eq!(CoordinateFormat::Mgrs.format([0.0, 0.0]), "31N AA 66021 00000");
+        // references are truncated rather than rounded, and this easting is 583959.96
+        assert_eq!(CoordinateFormat::Mgrs.format([40.71435, -74.00597]), "18T WL 83959 07523");
+    }
+
+    #[test]
+    fn dms_rounds_and_names_hemispheres() {
+        assert_eq!(format_dms([44.5919, -123.26705]), "44°35'30.8\"N 123°16'01.4\"W");
+        assert_eq!(format_dms([-0.5, 0.0]), "0°30'00.0\"S 0°00'00.0\"E");
+        // 59.99 seconds rounds up into the next minute
+        assert_eq!(format_dms([1.0 + 59.99 / 3600.0, 0.0]), "1°01'00.0\"N 0°00'00.0\"E");
+    }
+
+    #[test]
+    fn decimal_keeps_six_places() {
+        assert_eq!(CoordinateFormat::Decimal.format([44.5919, -123.26705]), "44.591900, -123.267050");
+    }
+}
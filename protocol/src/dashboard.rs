@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+pub const TILE_HEIGHT_RANGE: RangeInclusive<f32> = 100.0..=800.0;
+pub const MAX_SERIES: usize = 8;
+const MAX_NAME_LENGTH: usize = 64;
+
+/// A plottable column of a sensor table
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct ColumnRef {
+    pub table: String,
+    pub column: String,
+}
+
+/// A sensor table and its plottable columns
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TableSchema {
+    pub name: String,
+    pub columns: Vec<String>,
+}
+
+/// Side of a graph whose y axis a series is measured against
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum YAxis {
+    #[default]
+    Left,
+    Right,
+}
+
+/// A column drawn as one line of a graph
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Series {
+    pub source: ColumnRef,
+    pub color: [u8; 3], // sRGB
+    #[serde(default)]
+    pub axis: YAxis,
+}
+
+/// One graph on the home panel, overlaying up to `MAX_SERIES` columns
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Tile {
+    pub title: String,
+    pub series: Vec<Series>,
+    pub height: f32, // points
+}
+
+/// A named arrangement of graphs on the home panel, saved per user
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Dashboard {
+    pub name: String,
+    pub tiles: Vec<Tile>,
+}
+
+impl Dashboard {
+    /// Checks the dashboard can be stored, and that every series plots a column in `schema`
+    pub fn validate(&self, schema: &[TableSchema]) -> Result<(), String> {
+        validate_name(&self.name)?;
+        for tile in &self.tiles {
+            if !TILE_HEIGHT_RANGE.contains(&tile.height) {
+                return Err(format!("height of \"{}\" must be within {:?}", tile.title, TILE_HEIGHT_RANGE));
+            }
+            if tile.series.is_empty() || tile.series.len() > MAX_SERIES {
+                return Err(format!("\"{}\" must plot 1 to {} columns", tile.title, MAX_SERIES));
+            }
+            for series in &tile.series {
+                if !has_column(schema, &series.source) {
+                    return Err(format!("{}.{} can't be plotted", series.source.table, series.source.column));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Every column plotted on the dashboard, without repeats
+    pub fn sources(&self) -> Vec<&ColumnRef> {
+        let mut sources: Vec<&ColumnRef> = vec![];
+        for series in self.tiles.iter().flat_map(|tile| &tile.series) {
+            if !sources.contains(&&series.source) {
+                sources.push(&series.source);
+            }
+        }
+        sources
+    }
+}
+
+/// Whether `source` is one of the plottable columns in `schema`
+pub fn has_column(schema: &[TableSchema], source: &ColumnRef) -> bool {
+    schema.iter().any(|table| table.name == source.table && table.columns.contains(&source.column))
+}
+
+/// Checks a user or dashboard name is safe to use in a path
+pub fn validate_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("\"{name}\" must be 1 to {MAX_NAME_LENGTH} letters, digits, '-' or '_'"))
+    }
+}
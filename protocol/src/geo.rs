@@ -0,0 +1,73 @@
+use crate::LatLon;
+
+/// Mean radius of the earth in metres
+pub const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// Great circle distance between two positions in metres
+pub fn distance([lat1, lon1]: LatLon, [lat2, lon2]: LatLon) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (lon2 - lon1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+/// Initial bearing from `from` towards `to`, in degrees clockwise from north
+pub fn bearing(from: LatLon, to: LatLon) -> f64 {
+    let (lat1, lat2) = (from[0].to_radians(), to[0].to_radians());
+    let d_lon = (to[1] - from[1]).to_radians();
+    let y = d_lon.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * d_lon.cos();
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LONDON: LatLon = [51.5074, -0.1278];
+    const PARIS: LatLon = [48.8566, 2.3522];
+    const NEW_YORK: LatLon = [40.7128, -74.0060];
+    const LOS_ANGELES: LatLon = [34.0522, -118.2437];
+
+    fn assert_near(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() <= tolerance, "{} isn't within {} of {}", actual, tolerance, expected);
+    }
+
+    #[test]
+    fn distance_between_cities() {
+        assert_near(distance(LONDON, PARIS), 343_560.0, 500.0);
+        assert_near(distance(NEW_YORK, LOS_ANGELES), 3_935_700.0, 500.0);
+        assert_eq!(distance(LONDON, PARIS), distance(PARIS, LONDON));
+    }
+
+    #[test]
+    fn distance_along_equator_and_meridian() {
+        let degree = EARTH_RADIUS * std::f64::consts::PI / 180.0;
+        assert_near(distance([0.0, 0.0], [0.0, 1.0]), degree, 1e-6);
+        assert_near(distance([0.0, 0.0], [90.0, 0.0]), 90.0 * degree, 1e-6);
+        // the short way across the antimeridian
+        assert_near(distance([0.0, 179.5], [0.0, -179.5]), degree, 1e-6);
+    }
+
+    #[test]
+    fn distance_to_itself_is_zero() {
+        assert_eq!(distance(LONDON, LONDON), 0.0);
+        assert_eq!(distance([0.0, 0.0], [0.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn bearing_between_cities() {
+        assert_near(bearing(LONDON, PARIS), 148.1, 0.1);
+        assert_near(bearing(NEW_YORK, LOS_ANGELES), 273.7, 0.1);
+    }
+
+    #[test]
+    fn bearing_of_compass_points() {
+        assert_near(bearing([0.0, 0.0], [1.0, 0.0]), 0.0, 1e-9);
+        assert_near(bearing([0.0, 0.0], [0.0, 1.0]), 90.0, 1e-9);
+        assert_near(bearing([0.0, 0.0], [-1.0, 0.0]), 180.0, 1e-9);
+        assert_near(bearing([0.0, 0.0], [0.0, -1.0]), 270.0, 1e-9);
+        assert_near(bearing([0.0, 179.5], [0.0, -179.5]), 90.0, 1e-9);
+    }
+}
@@ -0,0 +1,210 @@
+use serde::{Deserialize, Serialize};
+use crate::{distance, LatLon, TableSchema};
+
+const MAX_GEOFENCE_NAME_LENGTH: usize = 64;
+const MAX_POLYGON_POINTS: usize = 100;
+
+/// Table alert rules name to watch a geofence, with one column per geofence
+/// reading 1 while the GPS is inside it and 0 while outside. The name is reserved,
+/// a sensor table recorded under it is never offered for plotting or watching
+pub const GEOFENCE_TABLE: &str = "geofences";
+
+/// Area covered by a geofence
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum Shape {
+    Circle {
+        center: LatLon,
+        radius: f64, // metres
+    },
+    /// corners in order, the last one joining back to the first
+    Polygon(Vec<LatLon>),
+}
+
+/// A named zone whose entry and exit are logged by the server
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Geofence {
+    pub name: String,
+    pub shape: Shape,
+}
+
+impl Geofence {
+    /// Whether `position` lies within the geofence. A polygon's south and west edges count as inside it
+    /// and its north and east edges as outside, so a position on an edge shared by two is only inside one
+    pub fn contains(&self, position: LatLon) -> bool {
+        match &self.shape {
+            Shape::Circle { center, radius } => distance(*center, position) <= *radius,
+            Shape::Polygon(corners) => polygon_contains(corners, position),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() || self.name.len() > MAX_GEOFENCE_NAME_LENGTH {
+            return Err(format!("geofence names must be 1 to {MAX_GEOFENCE_NAME_LENGTH} characters"));
+        }
+        let valid = |[lat, lon]: LatLon| (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon);
+        match &self.shape {
+            Shape::Circle { center, radius } => {
+                if !valid(*center) {
+                    return Err(format!("centre of \"{}\" isn't a position", self.name));
+                }
+                if !(radius.is_finite() && *radius > 0.0) {
+                    return Err(format!("radius of \"{}\" must be more than 0", self.name));
+                }
+            },
+            Shape::Polygon(corners) => {
+                if !(3..=MAX_POLYGON_POINTS).contains(&corners.len()) {
+                    return Err(format!("\"{}\" must have 3 to {MAX_POLYGON_POINTS} corners", self.name));
+                }
+                if !corners.iter().all(|&corner| valid(corner)) {
+                    return Err(format!("a corner of \"{}\" isn't a position", self.name));
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Whether `position` lies within the polygon of `corners`, each joined to the next the short way round,
+/// so a polygon can straddle the antimeridian
+fn polygon_contains(corners: &[LatLon], position: LatLon) -> bool {
+    if corners.len() < 3 {
+        return false;
+    }
+    let mut unwrapped: Vec<LatLon> = Vec::with_capacity(corners.len());
+    for &[lat, lon] in corners {
+        let lon = match unwrapped.last() {
+            Some(&[_, previous]) => previous + (lon - previous + 180.0).rem_euclid(360.0) - 180.0,
+            None => lon,
+        };
+        unwrapped.push([lat, lon]);
+    }
+
+    // unwrapped longitudes can run past ±180, where the position is a turn of the earth away
+    [-360.0, 0.0, 360.0].iter().any(|turn| {
+        // count the edges a ray running east from the position crosses
+        let [lat, lon] = [position[0], position[1] + turn];
+        let mut inside = false;
+        for (i, &[lat1, lon1]) in unwrapped.iter().enumerate() {
+            let [lat2, lon2] = unwrapped[(i + 1) % unwrapped.len()];
+            if (lat1 > lat) != (lat2 > lat) && lon < lon1 + (lat - lat1) / (lat2 - lat1) * (lon2 - lon1) {
+                inside = !inside;
+            }
+        }
+        inside
+    })
+}
+
+/// Checks every geofence is valid and that their names are unique
+pub fn validate_geofences(geofences: &[Geofence]) -> Result<(), String> {
+    for (i, geofence) in geofences.iter().enumerate() {
+        geofence.validate()?;
+        if geofences[..i].iter().any(|other| other.name == geofence.name) {
+            return Err(format!("there is more than one geofence called \"{}\"", geofence.name));
+        }
+    }
+    Ok(())
+}
+
+/// The columns alert rules can watch for `geofences`
+pub fn geofence_schema(geofences: &[Geofence]) -> TableSchema {
+    TableSchema {
+        name: GEOFENCE_TABLE.to_string(),
+        columns: geofences.iter().map(|geofence| geofence.name.clone()).collect(),
+    }
+}
+
+/// Which way the GPS crossed a geofence's edge
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Crossing {
+    Entered,
+    Left,
+}
+
+impl Crossing {
+    pub const ALL: [Crossing; 2] = [Crossing::Entered, Crossing::Left];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Crossing::Entered => "entered",
+            Crossing::Left => "left",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn polygon(corners: &[LatLon]) -> Geofence {
+        Geofence { name: "zone".to_string(), shape: Shape::Polygon(corners.to_vec()) }
+    }
+
+    fn circle(center: LatLon, radius: f64) -> Geofence {
+        Geofence { name: "zone".to_string(), shape: Shape::Circle { center, radius } }
+    }
+
+    const SQUARE: [LatLon; 4] = [[44.0, -123.0], [44.0, -122.0], [45.0, -122.0], [45.0, -123.0]];
+
+    #[test]
+    fn polygon_inside_and_outside() {
+        let square = polygon(&SQUARE);
+        assert!(square.contains([44.5, -122.5]));
+        assert!(!square.contains([45.5, -122.5]));
+        assert!(!square.contains([44.5, -121.5]));
+        assert!(!square.contains([43.0, -124.0]));
+
+        // a concave polygon doesn't contain its notch
+        let notched = polygon(&[[0.0, 0.0], [0.0, 3.0], [3.0, 3.0], [1.5, 1.5], [3.0, 0.0]]);
+        assert!(notched.contains([1.0, 1.5]));
+        assert!(!notched.contains([2.5, 1.5]));
+    }
+
+    #[test]
+    fn polygon_edges_and_corners() {
+        let square = polygon(&SQUARE);
+        assert!(square.contains([44.0, -122.5]));
+        assert!(square.contains([44.5, -123.0]));
+        assert!(!square.contains([45.0, -122.5]));
+        assert!(!square.contains([44.5, -122.0]));
+        assert!(square.contains([44.0, -123.0]));
+        assert!(!square.contains([45.0, -122.0]));
+    }
+
+    #[test]
+    fn polygon_across_antimeridian() {
+        let straddling = polygon(&[[-1.0, 179.0], [-1.0, -179.0], [1.0, -179.0], [1.0, 179.0]]);
+        assert!(straddling.contains([0.0, 179.5]));
+        assert!(straddling.contains([0.0, -179.5]));
+        assert!(straddling.contains([0.0, 180.0]));
+        assert!(!straddling.contains([0.0, 0.0]));
+        assert!(!straddling.contains([0.0, 178.0]));
+        assert!(!straddling.contains([0.0, -178.0]));
+    }
+
+    #[test]
+    fn degenerate_polygons_contain_nothing() {
+        assert!(!polygon(&[]).contains([0.0, 0.0]));
+        assert!(!polygon(&[[0.0, 0.0]]).contains([0.0, 0.0]));
+        assert!(!polygon(&[[0.0, 0.0], [1.0, 1.0]]).contains([0.5, 0.5]));
+        assert!(polygon(&[[0.0, 0.0], [1.0, 1.0]]).validate().is_err());
+        // corners all in a line enclose no area
+        assert!(!polygon(&[[0.0, 0.0], [1.0, 1.0], [2.0, 2.0]]).contains([1.0, 1.0]));
+    }
+
+    #[test]
+    fn circle_inside_and_outside() {
+        // a thousandth of a degree of latitude is about 111 metres
+        let zone = circle([44.5, -123.0], 200.0);
+        assert!(zone.contains([44.5, -123.0]));
+        assert!(zone.contains([44.501, -123.0]));
+        assert!(!zone.contains([44.502, -123.0]));
+        assert!(!zone.contains([-44.5, -123.0]));
+    }
+
+    #[test]
+    fn circle_across_antimeridian() {
+        let zone = circle([0.0, 179.999], 500.0);
+        assert!(zone.contains([0.0, -179.999]));
+        assert!(!zone.contains([0.0, -179.99]));
+    }
+}
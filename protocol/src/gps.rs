@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use crate::{LatLon, PlotPoint};
+
+/// Fix types of `gps_data`, trimmed and in lower case, recorded while the receiver has no valid fix
+pub const INVALID_FIX_TYPES: [&str; 3] = ["", "invalid", "no fix"];
+
+/// How good a GPS fix is, following the fix qualities of NMEA GGA sentences, worst first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub enum FixQuality {
+    Invalid,
+    /// dead reckoning
+    Estimated,
+    Gps,
+    Differential,
+    FloatRtk,
+    Rtk,
+}
+
+impl FixQuality {
+    pub const ALL: [FixQuality; 6] = [FixQuality::Invalid, FixQuality::Estimated, FixQuality::Gps,
+        FixQuality::Differential, FixQuality::FloatRtk, FixQuality::Rtk];
+
+    /// Reads the `fix_type` column of `gps_data`, counting any type it doesn't know as a plain GPS fix
+    pub fn from_fix_type(fix_type: &str) -> Self {
+        let fix_type = fix_type.trim().to_ascii_lowercase();
+        if INVALID_FIX_TYPES.contains(&fix_type.as_str()) {
+            return FixQuality::Invalid;
+        }
+        match fix_type.as_str() {
+            "estimated" | "dead reckoning" => FixQuality::Estimated,
+            "dgps" | "differential" => FixQuality::Differential,
+            "float rtk" | "rtk float" => FixQuality::FloatRtk,
+            "rtk" | "rtk fixed" => FixQuality::Rtk,
+            _ => FixQuality::Gps,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FixQuality::Invalid => "Invalid",
+            FixQuality::Estimated => "Estimated",
+            FixQuality::Gps => "GPS",
+            FixQuality::Differential => "DGPS",
+            FixQuality::FloatRtk => "Float RTK",
+            FixQuality::Rtk => "RTK",
+        }
+    }
+
+    /// Height of the quality on a plot, from 0 for an invalid fix upwards
+    pub fn level(&self) -> f64 {
+        Self::ALL.iter().position(|quality| quality == self).unwrap_or_default() as f64
+    }
+
+    /// The quality plotted at `level`, if one is
+    pub fn from_level(level: f64) -> Option<Self> {
+        if level.fract() != 0.0 || level < 0.0 {
+            return None;
+        }
+        Self::ALL.get(level as usize).copied()
+    }
+}
+
+/// State of the GPS receiver as of its newest row, valid or not, which tells bad data from no data
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct GpsStatus {
+    /// unix time of the newest row
+    pub time: f64,
+    /// fix type of the newest row as recorded, e.g. "GPS" or "Invalid"
+    pub fix_type: String,
+    pub quality: FixQuality,
+    /// unix time of the newest valid fix, none if there has never been one
+    pub last_fix: Option<f64>,
+    pub altitude: Option<f64>, // metres above mean sea level
+    pub geoid_separation: Option<f64>, // metres
+    /// none unless `gps_data` has an `hdop` column
+    pub hdop: Option<f64>,
+    /// satellites in use, none unless `gps_data` has a `satellites` column
+    pub satellites: Option<u32>,
+    /// unix time and `FixQuality::level` of every row in the minutes before the newest, oldest first
+    pub history: Vec<PlotPoint>,
+}
+
+/// Totals of a drive, from the valid fixes of a time range or of the current session
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct TripSummary {
+    pub start: f64, // unix seconds
+    pub end: f64, // unix seconds
+    pub distance: f64, // metres
+    /// seconds spent moving, leaving out stops and gaps in the fixes
+    pub moving_time: f64,
+    pub max_speed: f64, // speed over ground, as reported by the receiver
+    /// speed over ground averaged over the moving time
+    pub average_speed: f64,
+    pub elevation_gain: f64, // metres
+    pub elevation_loss: f64, // metres
+    /// south west and north east corners of the area covered
+    pub bounds: [LatLon; 2],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fix_types_without_a_fix_are_invalid() {
+        for fix_type in ["", "  ", "Invalid", "INVALID", "No Fix", " no fix "] {
+            assert_eq!(FixQuality::from_fix_type(fix_type), FixQuality::Invalid, "{:?}", fix_type);
+        }
+        assert_eq!(FixQuality::from_fix_type("DGPS"), FixQuality::Differential);
+        assert_eq!(FixQuality::from_fix_type("3D"), FixQuality::Gps);
+    }
+
+    #[test]
+    fn levels_round_trip() {
+        for quality in FixQuality::ALL {
+            assert_eq!(FixQuality::from_level(quality.level()), Some(quality));
+        }
+        assert_eq!(FixQuality::from_level(0.5), None);
+        assert_eq!(FixQuality::from_level(-1.0), None);
+    }
+}
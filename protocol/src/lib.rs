@@ -0,0 +1,102 @@
+//! Types shared by the `ui` client and the `server`, describing every request and response body
+//! exchanged between the two. Changing a type here forces both sides to agree on the change.
+
+pub mod routes;
+mod alerts;
+mod annotations;
+mod calibration;
+mod capabilities;
+mod config;
+mod coordinates;
+mod dashboard;
+mod geo;
+mod geofences;
+mod gps;
+mod units;
+
+pub use alerts::*;
+pub use annotations::*;
+pub use calibration::*;
+pub use capabilities::*;
+pub use config::*;
+pub use coordinates::*;
+pub use dashboard::*;
+pub use geo::*;
+pub use geofences::*;
+pub use gps::*;
+pub use units::*;
+
+use serde::{Deserialize, Serialize};
+
+/// Number of columns in a row of the full data tables
+pub const MAX_WIDTH: usize = 12;
+
+/// One row of a full data table, unused trailing columns are left empty
+pub type Row = [String; MAX_WIDTH];
+
+/// An `[x, y]` point on a plot
+pub type PlotPoint = [f64; 2];
+
+/// A `[latitude, longitude]` pair in degrees
+pub type LatLon = [f64; 2];
+
+/// One valid fix of a GPS track
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct TrackPoint {
+    pub time: f64, // unix seconds
+    pub position: LatLon,
+    pub speed: f64, // speed over ground, as reported by the receiver
+    pub altitude: f64, // metres
+}
+
+/// The latest valid GPS fix, with where it is heading and how precise it is
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Fix {
+    pub point: TrackPoint,
+    /// degrees clockwise from north, none until the receiver has moved far enough to tell
+    pub heading: Option<f64>,
+    /// radius of the position's uncertainty in metres, none unless the receiver reports HDOP
+    pub accuracy: Option<f64>,
+}
+
+/// Zoom levels of the map tiles the server has stored locally
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TileSet {
+    pub min_zoom: u8,
+    pub max_zoom: u8,
+}
+
+/// Body of an `/update` post, sent when recording is started or stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RecordingUpdate {
+    #[serde(rename = "isRecording")]
+    pub is_recording: bool,
+}
+
+/// The tables which can be requested in full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum DataTable {
+    Acceleration,
+    Gps,
+    Temperature,
+    /// entries and exits of the geofences
+    Geofences,
+}
+
+impl DataTable {
+    pub const ALL: [DataTable; 4] = [DataTable::Acceleration, DataTable::Gps, DataTable::Temperature, DataTable::Geofences];
+
+    /// Name used for this table in request paths
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DataTable::Acceleration => "acceleration",
+            DataTable::Gps => "gps",
+            DataTable::Temperature => "temperature",
+            DataTable::Geofences => "geofences",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|table| table.as_str() == name)
+    }
+}
@@ -0,0 +1,115 @@
+//! Paths of every endpoint the client requests, relative to the server's base address.
+//! The server's route attributes must match these. Names from the database or the user
+//! are percent-encoded, as they can hold characters with a meaning in URLs.
+
+use crate::DataTable;
+
+pub const SETTINGS: &str = "/req/settings";
+pub const SETTINGS_STATUS: &str = "/req/settings/status";
+/// Settings offered by each sensor, responds with `Vec<SensorCapabilities>`
+pub const CAPABILITIES: &str = "/req/capabilities";
+/// Calibration applied to the sensor values served, responds with `Calibration`
+pub const CALIBRATION: &str = "/req/calibration";
+/// Previous versions of the calibration, oldest first, responds with `Vec<CalibrationRevision>`
+pub const CALIBRATION_HISTORY: &str = "/req/calibration/history";
+pub const SCHEMA: &str = "/req/schema";
+/// GPS track of the current session, responds with `Vec<TrackPoint>`
+pub const GPS_TRACK: &str = "/req/gps/track";
+/// Latest GPS fix, responds with `Option<Fix>`
+pub const GPS_FIX: &str = "/req/gps/fix";
+/// Trip summary of the current session, responds with `Option<TripSummary>`
+pub const TRIP_SUMMARY: &str = "/req/gps/summary";
+/// Fix quality of the GPS receiver's newest rows, valid or not, responds with `Option<GpsStatus>`
+pub const GPS_STATUS: &str = "/req/gps/status";
+/// Zoom levels of the local map tiles, responds with `Option<TileSet>`
+pub const TILES: &str = "/req/tiles";
+pub const ALERT_RULES: &str = "/req/alerts/rules";
+pub const ALERT_EVENTS: &str = "/req/alerts/events";
+pub const GEOFENCES: &str = "/req/geofences";
+pub const ANNOTATIONS: &str = "/req/annotations";
+pub const UPDATE: &str = "/update";
+pub const UPDATE_SETTINGS: &str = "/update/settings";
+/// Stores the posted `Calibration` as a new version, responds with the `Calibration` as stored
+pub const UPDATE_CALIBRATION: &str = "/update/calibration";
+pub const UPDATE_ALERT_RULES: &str = "/update/alerts/rules";
+pub const UPDATE_GEOFENCES: &str = "/update/geofences";
+pub const UPDATE_ANNOTATION: &str = "/update/annotation";
+
+/// Latest points of `column` in `table`, responds with `Vec<PlotPoint>` of unix time and value
+pub fn data_latest(column: &str, table: &str) -> String {
+    format!("/req/data/latest/{}/{}", segment(column), segment(table))
+}
+
+/// Points of `column` in `table` between the unix times `from` and `to`, averaged down to at most `points`.
+/// Responds with `Vec<PlotPoint>` of unix time and value
+pub fn data_range(column: &str, table: &str, from: f64, to: f64, points: usize) -> String {
+    format!("/req/data/range/{}/{}?from={from}&to={to}&points={points}", segment(column), segment(table))
+}
+
+/// Uncalibrated accelerometer readings of the last `seconds` recorded averaged together, responds with `Option<AccelerometerCapture>`
+pub fn calibration_capture(seconds: f64) -> String {
+    format!("/req/calibration/capture?seconds={seconds}")
+}
+
+/// One local map tile in the XYZ scheme, responds with the image
+pub fn tile(zoom: u8, x: u32, y: u32) -> String {
+    format!("/tiles/{zoom}/{x}/{y}")
+}
+
+/// GPS track between the unix times `from` and `to`, responds with `Vec<TrackPoint>`
+pub fn gps_track_range(from: f64, to: f64) -> String {
+    format!("{GPS_TRACK}?from={from}&to={to}")
+}
+
+/// Trip summary between the unix times `from` and `to`, responds with `Option<TripSummary>`
+pub fn trip_summary_range(from: f64, to: f64) -> String {
+    format!("{TRIP_SUMMARY}?from={from}&to={to}")
+}
+
+/// Latest rows of `table`, responds with `Vec<Row>`
+pub fn data_full(table: DataTable) -> String {
+    format!("/req/data/full/{}", table.as_str())
+}
+
+/// Names of the dashboards saved by `user`, responds with `Vec<String>`
+pub fn dashboards(user: &str) -> String {
+    format!("/req/dashboards/{}", segment(user))
+}
+
+/// A saved dashboard, responds with `Dashboard`. Also takes a DELETE to remove it
+pub fn dashboard(user: &str, name: &str) -> String {
+    format!("/req/dashboard/{}/{}", segment(user), segment(name))
+}
+
+/// Saves the posted `Dashboard` for `user`, responds with the `Dashboard` as stored
+pub fn update_dashboard(user: &str) -> String {
+    format!("/update/dashboard/{}", segment(user))
+}
+
+/// `name` percent-encoded as one segment of a path, leaving only the characters which never need it
+fn segment(name: &str) -> String {
+    name.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_names_are_left_as_they_are() {
+        assert_eq!(data_latest("temperature_celsius", "thermalprobe_data"), "/req/data/latest/temperature_celsius/thermalprobe_data");
+        assert_eq!(dashboard("default", "drive-1.2"), "/req/dashboard/default/drive-1.2");
+    }
+
+    #[test]
+    fn names_are_encoded_as_one_segment() {
+        assert_eq!(data_latest("speed #2?", "gps/data"), "/req/data/latest/speed%20%232%3F/gps%2Fdata");
+        assert_eq!(data_range("50%", "t", 1.0, 2.0, 3), "/req/data/range/50%25/t?from=1&to=2&points=3");
+        assert_eq!(dashboards("zoë"), "/req/dashboards/zo%C3%AB");
+    }
+}
@@ -0,0 +1,337 @@
+use serde::{Deserialize, Serialize};
+use crate::{ColumnRef, ACCELEROMETER_COLUMNS};
+
+/// Standard gravity, in metres per second squared
+pub const STANDARD_GRAVITY: f64 = 9.80665;
+const METRES_PER_FOOT: f64 = 0.3048;
+const METRES_PER_MILE: f64 = 1609.344;
+const METRES_PER_NAUTICAL_MILE: f64 = 1852.0;
+
+/// Kinds of value recorded by the sensors, each stored in one unit and shown in the unit chosen in `Units`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quantity {
+    /// stored in degrees Celsius
+    Temperature,
+    /// stored in metres per second squared
+    Acceleration,
+    /// stored in knots, as NMEA receivers report speed over ground
+    Speed,
+    /// stored in metres
+    Length,
+}
+
+impl Quantity {
+    /// What the values of `source` measure, none for columns without a unit. Only these column names have one:
+    ///
+    /// * `Temperature`: `temperature_celsius`, or any other ending in `_celsius`
+    /// * `Acceleration`: `accelerometer_x`, `accelerometer_y` and `accelerometer_z`
+    /// * `Speed`: `speed_over_ground`
+    /// * `Length`: `altitude` and `geoid_separation`
+    ///
+    /// Others are shown as recorded, even when named like one of these, such as `speed_setting` or `accelerometer_range`
+    pub fn of(source: &ColumnRef) -> Option<Self> {
+        let column = source.column.as_str();
+        if column.ends_with("_celsius") {
+            Some(Quantity::Temperature)
+        } else if ACCELEROMETER_COLUMNS.contains(&column) {
+            Some(Quantity::Acceleration)
+        } else if column == "speed_over_ground" {
+            Some(Quantity::Speed)
+        } else if ["altitude", "geoid_separation"].contains(&column) {
+            Some(Quantity::Length)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum TemperatureUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+    Kelvin,
+}
+
+impl TemperatureUnit {
+    pub const ALL: [TemperatureUnit; 3] = [TemperatureUnit::Celsius, TemperatureUnit::Fahrenheit, TemperatureUnit::Kelvin];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TemperatureUnit::Celsius => "°C",
+            TemperatureUnit::Fahrenheit => "°F",
+            TemperatureUnit::Kelvin => "K",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum AccelerationUnit {
+    #[default]
+    MetresPerSecondSquared,
+    /// multiples of standard gravity
+    G,
+}
+
+impl AccelerationUnit {
+    pub const ALL: [AccelerationUnit; 2] = [AccelerationUnit::MetresPerSecondSquared, AccelerationUnit::G];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccelerationUnit::MetresPerSecondSquared => "m/s²",
+            AccelerationUnit::G => "g",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum SpeedUnit {
+    #[default]
+    Knots,
+    KilometresPerHour,
+    MilesPerHour,
+    MetresPerSecond,
+}
+
+impl SpeedUnit {
+    pub const ALL: [SpeedUnit; 4] = [SpeedUnit::Knots, SpeedUnit::KilometresPerHour, SpeedUnit::MilesPerHour, SpeedUnit::MetresPerSecond];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpeedUnit::Knots => "kn",
+            SpeedUnit::KilometresPerHour => "km/h",
+            SpeedUnit::MilesPerHour => "mph",
+            SpeedUnit::MetresPerSecond => "m/s",
+        }
+    }
+}
+
+/// Units of distances and heights
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum LengthSystem {
+    /// metres and kilometres
+    #[default]
+    Metric,
+    /// feet and miles
+    Imperial,
+}
+
+impl LengthSystem {
+    pub const ALL: [LengthSystem; 2] = [LengthSystem::Metric, LengthSystem::Imperial];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LengthSystem::Metric => "Metric",
+            LengthSystem::Imperial => "Imperial",
+        }
+    }
+}
+
+/// Units values are shown in across the UI, the server always stores and serves them in the units of `Quantity`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Units {
+    pub temperature: TemperatureUnit,
+    pub acceleration: AccelerationUnit,
+    pub speed: SpeedUnit,
+    pub length: LengthSystem,
+}
+
+impl Units {
+    /// A stored value of `quantity` in the chosen unit
+    pub fn convert(&self, quantity: Quantity, value: f64) -> f64 {
+        value * self.scale(quantity) + self.offset(quantity)
+    }
+
+    /// A value of `quantity` in the chosen unit back in its stored unit
+    pub fn to_stored(&self, quantity: Quantity, value: f64) -> f64 {
+        (value - self.offset(quantity)) / self.scale(quantity)
+    }
+
+    /// A stored difference between two values of `quantity`, such as a hysteresis, in the chosen unit
+    pub fn convert_difference(&self, quantity: Quantity, difference: f64) -> f64 {
+        difference * self.scale(quantity)
+    }
+
+    /// A difference between two values of `quantity` in the chosen unit back in its stored unit
+    pub fn difference_to_stored(&self, quantity: Quantity, difference: f64) -> f64 {
+        difference / self.scale(quantity)
+    }
+
+    /// Symbol of the chosen unit of `quantity`
+    pub fn symbol(&self, quantity: Quantity) -> &'static str {
+        match quantity {
+            Quantity::Temperature => self.temperature.as_str(),
+            Quantity::Acceleration => self.acceleration.as_str(),
+            Quantity::Speed => self.speed.as_str(),
+            Quantity::Length => match self.length {
+                LengthSystem::Metric => "m",
+                LengthSystem::Imperial => "ft",
+            },
+        }
+    }
+
+    /// A stored value of `source` in the chosen unit, unchanged if it has no unit
+    pub fn convert_column(&self, source: &ColumnRef, value: f64) -> f64 {
+        Quantity::of(source).map_or(value, |quantity| self.convert(quantity, value))
+    }
+
+    /// Name of `source` followed by its unit, if it has one
+    pub fn label(&self, source: &ColumnRef) -> String {
+        match Quantity::of(source) {
+            Some(quantity) => format!("{} ({})", source.column, self.symbol(quantity)),
+            None => source.column.clone(),
+        }
+    }
+
+    /// Space and symbol to put after values of `source`, empty if it has no unit
+    pub fn suffix(&self, source: &ColumnRef) -> String {
+        Quantity::of(source).map_or(String::new(), |quantity| format!(" {}", self.symbol(quantity)))
+    }
+
+    /// A distance in metres, in metres or feet up to a kilometre or mile and in those beyond
+    pub fn format_distance(&self, metres: f64) -> String {
+        match self.length {
+            LengthSystem::Metric if metres < 1000.0 => format!("{:.0} m", metres),
+            LengthSystem::Metric => format!("{:.2} km", metres / 1000.0),
+            LengthSystem::Imperial if metres < METRES_PER_MILE => format!("{:.0} ft", metres / METRES_PER_FOOT),
+            LengthSystem::Imperial => format!("{:.2} mi", metres / METRES_PER_MILE),
+        }
+    }
+
+    /// Multiplier from the stored unit of `quantity` to the chosen one
+    fn scale(&self, quantity: Quantity) -> f64 {
+        match quantity {
+            Quantity::Temperature => match self.temperature {
+                TemperatureUnit::Celsius | TemperatureUnit::Kelvin => 1.0,
+                TemperatureUnit::Fahrenheit => 1.8,
+            },
+            Quantity::Acceleration => match self.acceleration {
+                AccelerationUnit::MetresPerSecondSquared => 1.0,
+                AccelerationUnit::G => 1.0 / STANDARD_GRAVITY,
+            },
+            Quantity::Speed => match self.speed {
+                SpeedUnit::Knots => 1.0,
+                SpeedUnit::KilometresPerHour => METRES_PER_NAUTICAL_MILE / 1000.0,
+                SpeedUnit::MilesPerHour => METRES_PER_NAUTICAL_MILE / METRES_PER_MILE,
+                SpeedUnit::MetresPerSecond => METRES_PER_NAUTICAL_MILE / 3600.0,
+            },
+            Quantity::Length => match self.length {
+                LengthSystem::Metric => 1.0,
+                LengthSystem::Imperial => 1.0 / METRES_PER_FOOT,
+            },
+        }
+    }
+
+    /// Added after scaling from the stored unit of `quantity` to the chosen one
+    fn offset(&self, quantity: Quantity) -> f64 {
+        match (quantity, self.temperature) {
+            (Quantity::Temperature, TemperatureUnit::Fahrenheit) => 32.0,
+            (Quantity::Temperature, TemperatureUnit::Kelvin) => 273.15,
+            _ => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str) -> ColumnRef {
+        ColumnRef { table: "any_data".to_string(), column: name.to_string() }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} isn't {}", actual, expected);
+    }
+
+    /// Every combination of the units on offer
+    fn every_unit() -> Vec<Units> {
+        let mut all = vec![];
+        for temperature in TemperatureUnit::ALL {
+            for acceleration in AccelerationUnit::ALL {
+                for speed in SpeedUnit::ALL {
+                    for length in LengthSystem::ALL {
+                        all.push(Units { temperature, acceleration, speed, length });
+                    }
+                }
+            }
+        }
+        all
+    }
+
+    const QUANTITIES: [Quantity; 4] = [Quantity::Temperature, Quantity::Acceleration, Quantity::Speed, Quantity::Length];
+
+    #[test]
+    fn known_conversions() {
+        let units = Units {
+            temperature: TemperatureUnit::Fahrenheit,
+            acceleration: AccelerationUnit::G,
+            speed: SpeedUnit::KilometresPerHour,
+            length: LengthSystem::Imperial,
+        };
+        assert_close(units.convert(Quantity::Temperature, 100.0), 212.0);
+        assert_close(units.convert(Quantity::Temperature, -40.0), -40.0);
+        assert_close(units.convert(Quantity::Acceleration, STANDARD_GRAVITY), 1.0);
+        assert_close(units.convert(Quantity::Speed, 10.0), 18.52);
+        assert_close(units.convert(Quantity::Length, 0.3048), 1.0);
+
+        let units = Units { temperature: TemperatureUnit::Kelvin, speed: SpeedUnit::MilesPerHour, ..Units::default() };
+        assert_close(units.convert(Quantity::Temperature, 0.0), 273.15);
+        assert_close(units.convert(Quantity::Speed, 1.0), 1852.0 / 1609.344);
+        let units = Units { speed: SpeedUnit::MetresPerSecond, ..Units::default() };
+        assert_close(units.convert(Quantity::Speed, 3600.0 / 1852.0), 1.0);
+    }
+
+    #[test]
+    fn default_units_are_the_stored_ones() {
+        for quantity in QUANTITIES {
+            assert_eq!(Units::default().convert(quantity, 12.5), 12.5);
+        }
+    }
+
+    #[test]
+    fn values_round_trip_through_every_unit() {
+        for units in every_unit() {
+            for quantity in QUANTITIES {
+                for value in [-40.0, 0.0, 1.0, 21.7, 9.80665, 1234.5] {
+                    assert_close(units.to_stored(quantity, units.convert(quantity, value)), value);
+                    assert_close(units.convert(quantity, units.to_stored(quantity, value)), value);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn differences_round_trip_and_ignore_offsets() {
+        for units in every_unit() {
+            for quantity in QUANTITIES {
+                let difference = units.convert_difference(quantity, 2.5);
+                assert_close(units.difference_to_stored(quantity, difference), 2.5);
+                // a difference is the change between two converted values, whatever their offset
+                assert_close(units.convert(quantity, 12.5) - units.convert(quantity, 10.0), difference);
+            }
+        }
+    }
+
+    #[test]
+    fn quantities_of_known_columns() {
+        assert_eq!(Quantity::of(&column("temperature_celsius")), Some(Quantity::Temperature));
+        assert_eq!(Quantity::of(&column("ambient_celsius")), Some(Quantity::Temperature));
+        for axis in ACCELEROMETER_COLUMNS {
+            assert_eq!(Quantity::of(&column(axis)), Some(Quantity::Acceleration));
+        }
+        assert_eq!(Quantity::of(&column("speed_over_ground")), Some(Quantity::Speed));
+        assert_eq!(Quantity::of(&column("altitude")), Some(Quantity::Length));
+        assert_eq!(Quantity::of(&column("geoid_separation")), Some(Quantity::Length));
+    }
+
+    #[test]
+    fn similarly_named_columns_have_no_unit() {
+        for name in ["speed_setting", "speed_over_ground_valid", "accelerometer_range", "temperature_setpoint", "altitude_source", "latitude", "id"] {
+            assert_eq!(Quantity::of(&column(name)), None, "{}", name);
+        }
+        let units = Units { speed: SpeedUnit::KilometresPerHour, ..Units::default() };
+        assert_eq!(units.convert_column(&column("speed_setting"), 10.0), 10.0);
+        assert_eq!(units.label(&column("speed_setting")), "speed_setting");
+    }
+}
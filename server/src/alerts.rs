@@ -0,0 +1,183 @@
+use protocol::{validate_rules, AlertEvent, AlertRule, ColumnRef, Comparator, PlotPoint, TableSchema, GEOFENCE_TABLE};
+use rocket::tokio::time::{interval, Duration};
+use sqlx::sqlite::SqlitePool;
+use std::fs;
+use std::path::Path;
+use crate::{geofences, now, sql_parsing};
+use crate::storage::write_atomic;
+
+const RULES_PATH: &str = "alert_rules.json";
+const EVALUATION_PERIOD: Duration = Duration::from_secs(1);
+
+/// Reads the stored alert rules, none if they've never been saved
+pub fn load() -> Result<Vec<AlertRule>, String> {
+    let content = match fs::read_to_string(RULES_PATH) {
+        Ok(c) => c,
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(why) => return Err(format!("couldn't read alert rules: {why}")),
+    };
+    serde_json::from_str(&content).map_err(|why| format!("couldn't parse alert rules: {why}"))
+}
+
+/// Validates and stores a new set of alert rules, replacing the previous one
+///
+/// returns: the rules as they are now stored
+pub fn store(rules: &[AlertRule], schema: &[TableSchema]) -> Result<Vec<AlertRule>, String> {
+    validate_rules(rules, schema)?;
+    let json = serde_json::to_string_pretty(rules)
+        .map_err(|why| format!("couldn't serialize alert rules: {why}"))?;
+    write_atomic(Path::new(RULES_PATH), &json)?;
+    load()
+}
+
+/// Evaluates the stored rules against new sensor data every `EVALUATION_PERIOD`, for as long as the server runs,
+/// through one connection to the database kept open throughout
+pub async fn watch() {
+    let mut states: Vec<RuleState> = vec![];
+    let mut pool: Option<SqlitePool> = None;
+    let mut timer = interval(EVALUATION_PERIOD);
+    loop {
+        timer.tick().await;
+        if pool.is_none() {
+            pool = sql_parsing::connect().await
+                .map_err(|why| println!("couldn't evaluate alerts: {}", why))
+                .ok();
+        }
+        let Some(pool) = &pool else {
+            continue;
+        };
+        if let Err(why) = evaluate(pool, &mut states).await {
+            println!("couldn't evaluate alerts: {}", why);
+        }
+    }
+}
+
+/// Progress of one rule through the sensor data
+struct RuleState {
+    rule: AlertRule,
+    /// unix time of the last row evaluated, none until the newest row when the rule was added is known
+    last_time: Option<f64>,
+    /// when the values went past the threshold, while they stay there
+    since: Option<f64>,
+    /// id and peak of the event raised, while it is active
+    active: Option<(i64, f64)>,
+    /// why the rule couldn't be evaluated, while it still can't
+    error: Option<String>,
+}
+
+impl RuleState {
+    /// Whether `event` was raised by this state's rule as it is now
+    fn raised(&self, event: &AlertEvent) -> bool {
+        event.rule == self.rule.name
+            && event.source == self.rule.source
+            && event.comparator == self.rule.comparator
+            && event.threshold == self.rule.threshold
+    }
+
+    /// Evaluates the rows recorded since the last evaluation, raising and clearing the rule's alert.
+    /// `tables` are the sensor tables, as listed by `sql_parsing::sensor_tables`
+    async fn evaluate(&mut self, pool: &SqlitePool, tables: &[(TableSchema, &'static str)]) -> Result<(), Box<dyn std::error::Error>> {
+        let last_time = match self.last_time {
+            Some(time) => time,
+            // only rows recorded after the rule was added are evaluated
+            None => latest_time(pool, tables, &self.rule.source).await?.unwrap_or(f64::MIN),
+        };
+        self.last_time = Some(last_time);
+        let rows = data_since(pool, tables, &self.rule.source, last_time).await?;
+        let mut peak_changed = false;
+        for [time, value] in rows {
+            match &mut self.active {
+                Some((id, peak)) => {
+                    if self.rule.is_cleared(value) {
+                        sql_parsing::update_alert(pool, *id, *peak, Some(time)).await?;
+                        self.active = None;
+                        self.since = None;
+                        peak_changed = false;
+                    } else if further(self.rule.comparator, value, *peak) {
+                        *peak = value;
+                        peak_changed = true;
+                    }
+                },
+                None if self.rule.is_triggered(value) => {
+                    let since = *self.since.get_or_insert(time);
+                    if time - since >= self.rule.duration {
+                        let id = sql_parsing::open_alert(pool, &self.rule, since, value).await?;
+                        self.active = Some((id, value));
+                    }
+                },
+                None => self.since = None,
+            }
+            // only once the row's event is stored, so a row whose event couldn't be is evaluated again
+            self.last_time = Some(time);
+        }
+        if let (Some((id, peak)), true) = (self.active, peak_changed) {
+            sql_parsing::update_alert(pool, id, peak, None).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Brings the rule states in line with the stored rules, then evaluates each of them
+async fn evaluate(pool: &SqlitePool, states: &mut Vec<RuleState>) -> Result<(), Box<dyn std::error::Error>> {
+    let rules = load()?;
+    states.retain(|state| rules.contains(&state.rule));
+    for rule in rules {
+        if !states.iter().any(|state| state.rule == rule) {
+            states.push(RuleState { rule, last_time: None, since: None, active: None, error: None });
+        }
+    }
+
+    // events left active by a previous run of the server are picked up by their rule,
+    // unless it has since been changed or removed, in which case they are cleared now
+    let active = sql_parsing::active_alerts(pool).await?;
+    for event in active {
+        if states.iter().any(|state| state.active.is_some_and(|(id, _)| id == event.id)) {
+            continue;
+        }
+        match states.iter_mut().find(|state| state.active.is_none() && state.raised(&event)) {
+            Some(state) => state.active = Some((event.id, event.peak)),
+            None => sql_parsing::update_alert(pool, event.id, event.peak, Some(now())).await?,
+        }
+    }
+
+    // the tables are listed once for every rule, as doing so takes a query per table
+    let tables = sql_parsing::sensor_tables(pool).await?;
+    // a rule which can't be evaluated, say because its table was dropped, doesn't hold up the others
+    for state in states.iter_mut() {
+        match state.evaluate(pool, &tables).await.map_err(|why| why.to_string()) {
+            Ok(()) => state.error = None,
+            Err(why) if state.error.as_ref() != Some(&why) => {
+                println!("couldn't evaluate alert \"{}\": {}", state.rule.name, why);
+                state.error = Some(why);
+            },
+            Err(_) => {},
+        }
+    }
+    Ok(())
+}
+
+/// Unix time of the newest value of `source`, which for a geofence is that of the newest fix
+async fn latest_time(pool: &SqlitePool, tables: &[(TableSchema, &'static str)], source: &ColumnRef) -> Result<Option<f64>, Box<dyn std::error::Error>> {
+    if source.table == GEOFENCE_TABLE {
+        sql_parsing::latest_fix_time(pool, tables).await
+    } else {
+        sql_parsing::latest_time(pool, tables, source).await
+    }
+}
+
+/// Values of `source` after the unix time `after`, which for a geofence is whether each fix was inside it
+async fn data_since(pool: &SqlitePool, tables: &[(TableSchema, &'static str)], source: &ColumnRef, after: f64) -> Result<Vec<PlotPoint>, Box<dyn std::error::Error>> {
+    if source.table == GEOFENCE_TABLE {
+        geofences::inside_since(pool, tables, &source.column, after).await
+    } else {
+        sql_parsing::data_since(pool, tables, source, after).await
+    }
+}
+
+/// Whether `value` is further past the threshold than `peak`
+fn further(comparator: Comparator, value: f64, peak: f64) -> bool {
+    match comparator {
+        Comparator::Above => value > peak,
+        Comparator::Below => value < peak,
+    }
+}
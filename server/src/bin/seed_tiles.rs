@@ -0,0 +1,107 @@
+//! Downloads the map tiles covering an area into `tiles/`, for the server to serve where there's no connectivity
+//!
+//! usage: seed_tiles <south> <west> <north> <east> <min zoom> <max zoom> [tile url]
+//!
+//! The tile url defaults to OpenStreetMap's and may contain `{z}`, `{x}` and `{y}`.
+//! Tiles already downloaded are skipped, so an interrupted run can be resumed.
+
+use std::f64::consts::PI;
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+use std::thread::sleep;
+use std::time::Duration;
+
+/// Same layout the server reads tiles from
+const TILES_DIR: &str = "tiles";
+const DEFAULT_URL: &str = "https://tile.openstreetmap.org/{z}/{x}/{y}.png";
+/// Tile servers ask bulk downloads to identify themselves and go easy
+const USER_AGENT: &str = "PDA_UI seed_tiles";
+const REQUEST_DELAY: Duration = Duration::from_millis(100);
+/// Most tiles downloaded by one run, so a typo in the area doesn't try to fetch the world
+const MAX_TILES: u64 = 50_000;
+const MAX_ZOOM: u8 = 19;
+
+fn main() {
+    if let Err(why) = run(std::env::args().skip(1).collect()) {
+        eprintln!("{why}");
+        std::process::exit(1);
+    }
+}
+
+fn run(args: Vec<String>) -> Result<(), String> {
+    if !(6..=7).contains(&args.len()) {
+        return Err("usage: seed_tiles <south> <west> <north> <east> <min zoom> <max zoom> [tile url]".to_string());
+    }
+    let number = |i: usize| args[i].parse::<f64>().map_err(|why| format!("\"{}\" isn't a number: {why}", args[i]));
+    let zoom = |i: usize| match args[i].parse::<u8>() {
+        Ok(zoom) if zoom <= MAX_ZOOM => Ok(zoom),
+        _ => Err(format!("zoom levels go from 0 to {MAX_ZOOM}, not \"{}\"", args[i])),
+    };
+    let (south, west, north, east) = (number(0)?, number(1)?, number(2)?, number(3)?);
+    let (min_zoom, max_zoom) = (zoom(4)?, zoom(5)?);
+    let url = args.get(6).map_or(DEFAULT_URL, |url| url.as_str());
+    if !(south < north && west < east) {
+        return Err("the area must be given as south, west, north, east".to_string());
+    }
+    if min_zoom > max_zoom {
+        return Err("the min zoom can't be more than the max zoom".to_string());
+    }
+
+    let ranges: Vec<(u8, [u32; 2], [u32; 2])> = (min_zoom..=max_zoom)
+        .map(|z| (z, [tile_x(west, z), tile_x(east, z)], [tile_y(north, z), tile_y(south, z)]))
+        .collect();
+    let total: u64 = ranges.iter()
+        .map(|(_, [x0, x1], [y0, y1])| (x1 - x0 + 1) as u64 * (y1 - y0 + 1) as u64)
+        .sum();
+    if total > MAX_TILES {
+        return Err(format!("that area is {total} tiles, more than {MAX_TILES}; pick a smaller area or fewer zoom levels"));
+    }
+
+    let mut done = 0;
+    for (z, [x0, x1], [y0, y1]) in ranges {
+        for x in x0..=x1 {
+            for y in y0..=y1 {
+                done += 1;
+                let path: PathBuf = [TILES_DIR, &z.to_string(), &x.to_string(), &format!("{y}.png")].iter().collect();
+                if path.exists() {
+                    continue;
+                }
+                let image = download(&url.replace("{z}", &z.to_string()).replace("{x}", &x.to_string()).replace("{y}", &y.to_string()))?;
+                fs::create_dir_all(path.parent().unwrap())
+                    .and_then(|_| fs::write(&path, image))
+                    .map_err(|why| format!("couldn't write {}: {why}", path.display()))?;
+                println!("{done}/{total} {}", path.display());
+                sleep(REQUEST_DELAY);
+            }
+        }
+    }
+    println!("{total} tiles in {TILES_DIR}/");
+    Ok(())
+}
+
+fn download(url: &str) -> Result<Vec<u8>, String> {
+    let response = ureq::get(url)
+        .set("User-Agent", USER_AGENT)
+        .call()
+        .map_err(|why| format!("couldn't download {url}: {why}"))?;
+    let mut image = vec![];
+    response.into_reader()
+        .read_to_end(&mut image)
+        .map_err(|why| format!("couldn't download {url}: {why}"))?;
+    Ok(image)
+}
+
+/// Column of the tile containing `lon` at `zoom`
+fn tile_x(lon: f64, zoom: u8) -> u32 {
+    let tiles = 1u32 << zoom;
+    (((lon + 180.0) / 360.0 * tiles as f64) as u32).min(tiles - 1)
+}
+
+/// Row of the tile containing `lat` at `zoom`, counted from the top of the map
+fn tile_y(lat: f64, zoom: u8) -> u32 {
+    let tiles = 1u32 << zoom;
+    let lat = lat.clamp(-85.0511, 85.0511).to_radians();
+    let y = (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / PI) / 2.0;
+    ((y * tiles as f64) as u32).min(tiles - 1)
+}
@@ -0,0 +1,187 @@
+use protocol::{AccelerometerCalibration, Calibration, CalibrationRevision, TemperatureCalibration, ACCELEROMETER_COLUMNS, ACCELEROMETER_TABLE, TEMPERATURE_COLUMN, TEMPERATURE_TABLE};
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use crate::now;
+use crate::sql_parsing::quote_identifier;
+use crate::storage::{append_line, read_lines, write_atomic};
+
+const CALIBRATION_PATH: &str = "calibration.json";
+const HISTORY_PATH: &str = "calibration_history.jsonl";
+
+/// Held while a calibration is stored, so concurrent saves each get their own version
+static STORING: Mutex<()> = Mutex::new(());
+
+/// Parses a calibration sent by a client, rejecting unknown shapes and corrections that can't be applied
+pub fn parse(json: &str) -> Result<Calibration, String> {
+    let calibration: Calibration = serde_json::from_str(json)
+        .map_err(|why| format!("invalid calibration: {why}"))?;
+    calibration.validate()?;
+    Ok(calibration)
+}
+
+/// Reads the stored calibration, which leaves every value as recorded until one has been saved
+pub fn load() -> Result<Calibration, String> {
+    let content = match fs::read_to_string(CALIBRATION_PATH) {
+        Ok(c) => c,
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => return Ok(Calibration::default()),
+        Err(why) => return Err(format!("couldn't read calibration: {why}")),
+    };
+    serde_json::from_str(&content).map_err(|why| format!("couldn't parse calibration: {why}"))
+}
+
+/// Validates and stores a new version of the calibration, archiving the previous one.
+/// The version sent by the client is ignored, the stored one counts up from the previous version
+///
+/// returns: the calibration as it is now stored
+pub fn store(mut calibration: Calibration) -> Result<Calibration, String> {
+    calibration.validate()?;
+    let _storing = STORING.lock().unwrap_or_else(PoisonError::into_inner);
+
+    let previous = load()?;
+    calibration.version = previous.version;
+    if previous == calibration {
+        return Ok(previous);
+    }
+    calibration.version = previous.version + 1;
+    if previous.version > 0 {
+        archive(previous)?;
+    }
+
+    let json = serde_json::to_string_pretty(&calibration)
+        .map_err(|why| format!("couldn't serialize calibration: {why}"))?;
+    write_atomic(Path::new(CALIBRATION_PATH), &json)?;
+
+    load()
+}
+
+/// Reads every archived version of the calibration, oldest first
+pub fn history() -> Result<Vec<CalibrationRevision>, String> {
+    read_lines(Path::new(HISTORY_PATH))?
+        .into_iter()
+        .map(|revision| serde_json::from_value(revision)
+            .map_err(|why| format!("couldn't parse calibration history: {why}")))
+        .collect()
+}
+
+fn archive(calibration: Calibration) -> Result<(), String> {
+    append_line(Path::new(HISTORY_PATH), &CalibrationRevision { replaced_at: now() as u64, calibration })
+}
+
+/// SQL expression for the calibrated value of `column` in `table`, which is the plain value for columns without a calibration.
+/// Being computed by SQLite, the calibration applies before values are averaged
+pub fn value_expression(calibration: &Calibration, column: &str, table: &str) -> String {
+    let raw = |column: &str| format!("CAST({} AS REAL)", quote_identifier(column));
+    let axis = ACCELEROMETER_COLUMNS.iter().position(|axis| *axis == column);
+    match (table, axis) {
+        (ACCELEROMETER_TABLE, Some(axis)) if calibration.accelerometer != AccelerometerCalibration::default() => {
+            let accelerometer = &calibration.accelerometer;
+            let terms: Vec<String> = ACCELEROMETER_COLUMNS.iter().enumerate()
+                .filter(|(i, _)| accelerometer.alignment[axis][*i] != 0.0)
+                .map(|(i, column)| format!("{:?} * ({} - {:?})",
+                    accelerometer.alignment[axis][i] * accelerometer.scale[i], raw(column), accelerometer.offset[i]))
+                .collect();
+            format!("({})", terms.join(" + "))
+        },
+        (TEMPERATURE_TABLE, _) if column == TEMPERATURE_COLUMN && calibration.temperature != TemperatureCalibration::default() => {
+            let temperature = &calibration.temperature;
+            let linear = format!("({:?} * ({} - {:?}))", temperature.scale, raw(column), temperature.offset);
+            curve_expression(&temperature.curve, &linear)
+        },
+        _ => raw(column),
+    }
+}
+
+/// SQL expression mapping `value` through the straight lines joining the points of `curve`
+fn curve_expression(curve: &[[f64; 2]], value: &str) -> String {
+    let segments: Vec<String> = curve.windows(2)
+        .map(|pair| {
+            let ([x0, y0], [x1, y1]) = (pair[0], pair[1]);
+            format!("{:?} + ({} - {:?}) * {:?}", y0, value, x0, (y1 - y0) / (x1 - x0))
+        })
+        .collect();
+    match segments.split_last() {
+        None => value.to_string(),
+        // CASE needs a WHEN, which a single segment has no use for
+        Some((last, [])) => format!("({})", last),
+        Some((last, segments)) => {
+            // each segment covers readings up to its end, the first one also those before it
+            let cases: String = segments.iter().zip(&curve[1..])
+                .map(|(segment, [x, _])| format!("WHEN {} <= {:?} THEN {} ", value, x, segment))
+                .collect();
+            format!("(CASE {}ELSE {} END)", cases, last)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEMPERATURE: &str = "CAST(\"temperature_celsius\" AS REAL)";
+
+    fn temperature(offset: f64, scale: f64, curve: Vec<[f64; 2]>) -> Calibration {
+        Calibration { temperature: TemperatureCalibration { offset, scale, curve }, ..Calibration::default() }
+    }
+
+    #[test]
+    fn default_calibration_serves_raw_columns() {
+        let calibration = Calibration::default();
+        assert_eq!(value_expression(&calibration, TEMPERATURE_COLUMN, TEMPERATURE_TABLE), TEMPERATURE);
+        for column in ACCELEROMETER_COLUMNS {
+            assert_eq!(value_expression(&calibration, column, ACCELEROMETER_TABLE), format!("CAST(\"{}\" AS REAL)", column));
+        }
+    }
+
+    #[test]
+    fn uncalibrated_columns_are_served_raw() {
+        let calibration = temperature(1.0, 2.0, vec![]);
+        assert_eq!(value_expression(&calibration, "humidity", TEMPERATURE_TABLE), "CAST(\"humidity\" AS REAL)");
+        // the same column name in another table isn't the thermal probe's
+        assert_eq!(value_expression(&calibration, TEMPERATURE_COLUMN, "other_data"), TEMPERATURE);
+    }
+
+    #[test]
+    fn temperature_offset_and_scale() {
+        assert_eq!(value_expression(&temperature(0.5, 1.25, vec![]), TEMPERATURE_COLUMN, TEMPERATURE_TABLE),
+            format!("(1.25 * ({} - 0.5))", TEMPERATURE));
+    }
+
+    #[test]
+    fn two_point_curve_is_one_line() {
+        let expression = value_expression(&temperature(0.0, 1.0, vec![[0.0, 0.0], [100.0, 110.0]]), TEMPERATURE_COLUMN, TEMPERATURE_TABLE);
+        let linear = format!("(1.0 * ({} - 0.0))", TEMPERATURE);
+        assert_eq!(expression, format!("(0.0 + ({linear} - 0.0) * 1.1)"));
+    }
+
+    #[test]
+    fn curve_is_piecewise_linear() {
+        let curve = [[0.0, 0.0], [10.0, 20.0], [20.0, 25.0]];
+        assert_eq!(curve_expression(&curve, "v"),
+            "(CASE WHEN v <= 10.0 THEN 0.0 + (v - 0.0) * 2.0 ELSE 20.0 + (v - 10.0) * 0.5 END)");
+    }
+
+    #[test]
+    fn accelerometer_combines_aligned_axes() {
+        let calibration = Calibration {
+            accelerometer: AccelerometerCalibration {
+                offset: [0.0, 0.0, 0.1],
+                scale: [2.0, 1.0, 1.0],
+                alignment: [[0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+            },
+            ..Calibration::default()
+        };
+        assert_eq!(value_expression(&calibration, "accelerometer_x", ACCELEROMETER_TABLE),
+            "(1.0 * (CAST(\"accelerometer_y\" AS REAL) - 0.0))");
+        assert_eq!(value_expression(&calibration, "accelerometer_y", ACCELEROMETER_TABLE),
+            "(2.0 * (CAST(\"accelerometer_x\" AS REAL) - 0.0))");
+        assert_eq!(value_expression(&calibration, "accelerometer_z", ACCELEROMETER_TABLE),
+            "(1.0 * (CAST(\"accelerometer_z\" AS REAL) - 0.1))");
+    }
+
+    #[test]
+    fn column_names_are_quoted() {
+        let expression = value_expression(&Calibration::default(), "x\" AS REAL); DROP TABLE gps_data; --", "other_data");
+        assert_eq!(expression, "CAST(\"x\"\" AS REAL); DROP TABLE gps_data; --\" AS REAL)");
+    }
+}
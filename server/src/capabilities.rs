@@ -0,0 +1,78 @@
+use protocol::{validate_capabilities, SensorCapabilities, SettingDescription, SettingKind, SettingValue};
+use std::fs;
+
+/// Description of the sensors' settings, written for the hardware in use. The built in one is used without it
+const CAPABILITIES_PATH: &str = "capabilities.json";
+
+/// Reads the settings each sensor offers, from `capabilities.json` if there is one
+pub fn load() -> Result<Vec<SensorCapabilities>, String> {
+    let sensors = match fs::read_to_string(CAPABILITIES_PATH) {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|why| format!("couldn't parse capabilities: {why}"))?,
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => builtin(),
+        Err(why) => return Err(format!("couldn't read capabilities: {why}")),
+    };
+    validate_capabilities(&sensors).map_err(|why| format!("invalid capabilities: {why}"))?;
+    Ok(sensors)
+}
+
+/// Settings of the accelerometer, thermal probe and GPS receiver the acquisition process ships with
+fn builtin() -> Vec<SensorCapabilities> {
+    let toggle = |name: &str, label: &str| SettingDescription {
+        name: name.to_string(),
+        label: label.to_string(),
+        kind: SettingKind::Toggle,
+        default: SettingValue::Toggle(true),
+        unit: String::new(),
+    };
+    let number = |name: &str, label: &str, [min, max, step]: [f64; 3], default: f64, unit: &str| SettingDescription {
+        name: name.to_string(),
+        label: label.to_string(),
+        kind: SettingKind::Number { min, max, step },
+        default: SettingValue::Number(default),
+        unit: unit.to_string(),
+    };
+    let choice = |name: &str, label: &str, options: &[&str], default: &str, unit: &str| SettingDescription {
+        name: name.to_string(),
+        label: label.to_string(),
+        kind: SettingKind::Choice(options.iter().map(|option| option.to_string()).collect()),
+        default: SettingValue::Choice(default.to_string()),
+        unit: unit.to_string(),
+    };
+
+    vec![
+        SensorCapabilities {
+            name: "temperature".to_string(),
+            title: "Temperature Sensor".to_string(),
+            enabled_by: Some("temp_enabled".to_string()),
+            settings: vec![
+                toggle("temp_enabled", "Enabled"),
+                number("temp_sensitivity", "Sensitivity", [0.0, 120.0, 0.1], 8.0, ""),
+                number("temp_sample_rate", "Sample rate", [0.1, 10.0, 0.1], 1.0, "Hz"),
+                choice("temp_averaging", "Averaged samples", &["1", "4", "8", "16"], "1", ""),
+            ],
+        },
+        SensorCapabilities {
+            name: "accelerometer".to_string(),
+            title: "Accelerometer".to_string(),
+            enabled_by: Some("accel_enabled".to_string()),
+            settings: vec![
+                toggle("accel_enabled", "Enabled"),
+                number("accel_sensitivity", "Sensitivity", [0.0, 120.0, 0.1], 8.0, ""),
+                choice("accel_sample_rate", "Sample rate", &["25", "50", "100", "200", "400"], "100", "Hz"),
+                choice("accel_range", "Measurement range", &["2", "4", "8", "16"], "4", "g"),
+                choice("accel_filter", "Filter", &["off", "low pass", "high pass"], "off", ""),
+                number("accel_filter_cutoff", "Filter cutoff", [0.1, 200.0, 0.1], 10.0, "Hz"),
+            ],
+        },
+        SensorCapabilities {
+            name: "gps".to_string(),
+            title: "GPS".to_string(),
+            enabled_by: Some("gps_enabled".to_string()),
+            settings: vec![
+                toggle("gps_enabled", "Enabled"),
+                choice("gps_update_rate", "Update rate", &["1", "2", "5", "10"], "1", "Hz"),
+            ],
+        },
+    ]
+}
@@ -0,0 +1,73 @@
+use protocol::{validate_name, Dashboard, TableSchema};
+use std::fs;
+use std::path::PathBuf;
+use crate::storage::write_atomic;
+
+const DASHBOARDS_DIR: &str = "dashboards";
+
+/// Names of every dashboard saved by `user`, sorted
+pub fn list(user: &str) -> Result<Vec<String>, String> {
+    let entries = match fs::read_dir(user_dir(user)?) {
+        Ok(e) => e,
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(why) => return Err(format!("couldn't read dashboards: {why}")),
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            match path.extension() {
+                Some(ext) if ext == "json" => path.file_stem().map(|s| s.to_string_lossy().into_owned()),
+                _ => None,
+            }
+        })
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Reads the dashboard `name` saved by `user`, none if there is no such dashboard
+pub fn load(user: &str, name: &str) -> Result<Option<Dashboard>, String> {
+    let content = match fs::read_to_string(dashboard_path(user, name)?) {
+        Ok(c) => c,
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(why) => return Err(format!("couldn't read dashboard {name}: {why}")),
+    };
+    serde_json::from_str(&content).map(Some).map_err(|why| format!("couldn't parse dashboard {name}: {why}"))
+}
+
+/// Validates `dashboard` against the sensor tables and stores it, replacing any with the same name
+///
+/// returns: the dashboard as it is now stored
+pub fn store(user: &str, dashboard: &Dashboard, schema: &[TableSchema]) -> Result<Dashboard, String> {
+    dashboard.validate(schema)?;
+    fs::create_dir_all(user_dir(user)?)
+        .map_err(|why| format!("couldn't create dashboards: {why}"))?;
+
+    let json = serde_json::to_string_pretty(dashboard)
+        .map_err(|why| format!("couldn't serialize dashboard: {why}"))?;
+    write_atomic(&dashboard_path(user, &dashboard.name)?, &json)?;
+
+    load(user, &dashboard.name)?.ok_or_else(|| format!("couldn't find dashboard {} once stored", dashboard.name))
+}
+
+/// Deletes the dashboard `name` saved by `user`
+///
+/// returns: whether there was such a dashboard
+pub fn delete(user: &str, name: &str) -> Result<bool, String> {
+    match fs::remove_file(dashboard_path(user, name)?) {
+        Ok(()) => Ok(true),
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(why) => Err(format!("couldn't delete dashboard {name}: {why}")),
+    }
+}
+
+fn user_dir(user: &str) -> Result<PathBuf, String> {
+    validate_name(user)?;
+    Ok(PathBuf::from(DASHBOARDS_DIR).join(user))
+}
+
+fn dashboard_path(user: &str, name: &str) -> Result<PathBuf, String> {
+    validate_name(name)?;
+    Ok(user_dir(user)?.join(format!("{name}.json")))
+}
@@ -0,0 +1,112 @@
+use protocol::{validate_geofences, Crossing, Geofence, PlotPoint, TableSchema};
+use rocket::tokio::time::{interval, Duration};
+use sqlx::sqlite::SqlitePool;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use crate::sql_parsing;
+use crate::storage::write_atomic;
+
+const GEOFENCES_PATH: &str = "geofences.json";
+const EVALUATION_PERIOD: Duration = Duration::from_secs(1);
+
+/// Reads the stored geofences, none if they've never been saved
+pub fn load() -> Result<Vec<Geofence>, String> {
+    let content = match fs::read_to_string(GEOFENCES_PATH) {
+        Ok(c) => c,
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(why) => return Err(format!("couldn't read geofences: {why}")),
+    };
+    serde_json::from_str(&content).map_err(|why| format!("couldn't parse geofences: {why}"))
+}
+
+/// Validates and stores a new set of geofences, replacing the previous one
+///
+/// returns: the geofences as they are now stored
+pub fn store(geofences: &[Geofence]) -> Result<Vec<Geofence>, String> {
+    validate_geofences(geofences)?;
+    let json = serde_json::to_string_pretty(geofences)
+        .map_err(|why| format!("couldn't serialize geofences: {why}"))?;
+    write_atomic(Path::new(GEOFENCES_PATH), &json)?;
+    load()
+}
+
+/// Logs every entry into and exit from the stored geofences as new fixes arrive, for as long as the server runs,
+/// through one connection to the database kept open throughout
+pub async fn watch() {
+    let mut tracker: Option<Tracker> = None;
+    let mut pool: Option<SqlitePool> = None;
+    let mut timer = interval(EVALUATION_PERIOD);
+    loop {
+        timer.tick().await;
+        if pool.is_none() {
+            pool = sql_parsing::connect().await
+                .map_err(|why| println!("couldn't evaluate geofences: {}", why))
+                .ok();
+        }
+        let Some(pool) = &pool else {
+            continue;
+        };
+        if let Err(why) = evaluate(pool, &mut tracker).await {
+            println!("couldn't evaluate geofences: {}", why);
+        }
+    }
+}
+
+/// Progress of the geofence evaluation through the GPS data
+struct Tracker {
+    /// unix time of the last fix evaluated
+    last_time: f64,
+    /// whether the GPS was inside each geofence as of `last_time`
+    inside: HashMap<String, bool>,
+}
+
+async fn evaluate(pool: &SqlitePool, tracker: &mut Option<Tracker>) -> Result<(), Box<dyn std::error::Error>> {
+    let geofences = load()?;
+    let tables = sql_parsing::sensor_tables(pool).await?;
+    // fixes recorded while the server was down aren't evaluated, only whatever comes next
+    let tracker = match tracker {
+        Some(tracker) => tracker,
+        None => {
+            let last_time = sql_parsing::latest_fix_time(pool, &tables).await?.unwrap_or(f64::MIN);
+            tracker.insert(Tracker { last_time, inside: HashMap::new() })
+        },
+    };
+
+    // each geofence starts where its last crossing left it, or outside if it has never been crossed
+    tracker.inside.retain(|name, _| geofences.iter().any(|geofence| &geofence.name == name));
+    if geofences.iter().any(|geofence| !tracker.inside.contains_key(&geofence.name)) {
+        let crossings = sql_parsing::last_crossings(pool).await?;
+        for geofence in &geofences {
+            let inside = crossings.iter().any(|(name, crossing)| name == &geofence.name && *crossing == Crossing::Entered);
+            tracker.inside.entry(geofence.name.clone()).or_insert(inside);
+        }
+    }
+
+    let fixes = sql_parsing::fixes_since(pool, &tables, tracker.last_time).await?;
+    for (time, position) in fixes {
+        for geofence in &geofences {
+            let inside = geofence.contains(position);
+            if tracker.inside.get(&geofence.name) != Some(&inside) {
+                let crossing = if inside { Crossing::Entered } else { Crossing::Left };
+                sql_parsing::add_geofence_event(pool, &geofence.name, crossing, time, position).await?;
+                // only once the crossing is logged, so one which couldn't be is logged on the next evaluation
+                tracker.inside.insert(geofence.name.clone(), inside);
+            }
+        }
+        tracker.last_time = time;
+    }
+    Ok(())
+}
+
+/// Whether the GPS was inside the geofence `name` at each fix after the unix time `after`, as 1 or 0,
+/// for alert rules to watch. A geofence which doesn't exist is never entered.
+pub async fn inside_since(pool: &SqlitePool, tables: &[(TableSchema, &'static str)], name: &str, after: f64) -> Result<Vec<PlotPoint>, Box<dyn std::error::Error>> {
+    let Some(geofence) = load()?.into_iter().find(|geofence| geofence.name == name) else {
+        return Ok(vec![]);
+    };
+    let fixes = sql_parsing::fixes_since(pool, tables, after).await?;
+    Ok(fixes.into_iter()
+        .map(|(time, position)| [time, if geofence.contains(position) { 1.0 } else { 0.0 }])
+        .collect())
+}
@@ -1,11 +1,25 @@
 #[macro_use] extern crate rocket;
 
 mod sql_parsing;
+mod settings;
+mod calibration;
+mod capabilities;
+mod dashboards;
+mod alerts;
+mod geofences;
+mod storage;
+mod tiles;
+mod trips;
 
+use rocket::fairing::AdHoc;
 use rocket::fs::NamedFile;
+use rocket::http::{ContentType, Status};
+use rocket::response::status;
+use std::fmt::Display;
 use std::path::{Path, PathBuf};
-use std::fs;
-use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::Serialize;
+use protocol::{validate_name, AlertRule, Dashboard, DataTable, Geofence, NewAnnotation, RecordingUpdate};
 
 #[get("/")]
 async fn index() -> Result<NamedFile, std::io::Error> {
@@ -17,74 +31,294 @@ async fn files(file: PathBuf) -> Option<NamedFile> {
     NamedFile::open(Path::new("").join(file)).await.ok()
 }
 
+/// A JSON body on success, or an error status with a message for the client to show
+type Response = Result<String, status::Custom<String>>;
+
+/// Serializes `content` as the response, failures are reported as internal server errors
+fn respond<T: Serialize, E: Display>(content: Result<T, E>) -> Response {
+    match content {
+        Ok(c) => serde_json::to_string(&c).map_err(|why| {
+            status::Custom(Status::InternalServerError, format!("could not serialize: {}", why))
+        }),
+        Err(why) => {
+            println!("invalid content: {}", why);
+            Err(status::Custom(Status::InternalServerError, format!("invalid content: {}", why)))
+        },
+    }
+}
+
+fn bad_request(why: impl Display) -> status::Custom<String> {
+    status::Custom(Status::BadRequest, why.to_string())
+}
+
+/// Current unix time in seconds
+fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default()
+}
+
 #[get("/req/settings")]
-async fn req_settings() -> Result<String, std::io::Error> {
-    fs::read_to_string("settings.json")
+async fn req_settings() -> Response {
+    respond(settings::load())
 }
 
-#[get("/req/data/latest/gps")]
-async fn req_data_latest_gps() -> Result<String, String> {
-    let content = sql_parsing::latest_gps_latlon().await;
+#[get("/req/settings/history")]
+async fn req_settings_history() -> Response {
+    respond(settings::history())
+}
 
-    match content {
-        Ok(c) => match serde_json::to_string(&c) {
-            Ok(s) => Ok(s),
-            Err(why) => Err(format!("could not deserialize: {}", why)),
-        },
-        Err(why) => Err(format!("invalid content: {}", why)),
+#[get("/req/settings/status")]
+async fn req_settings_status() -> Response {
+    respond(sql_parsing::settings_status().await)
+}
+
+/// Settings offered by each sensor, which the config page builds its controls from
+#[get("/req/capabilities")]
+async fn req_capabilities() -> Response {
+    respond(capabilities::load())
+}
+
+#[get("/req/calibration")]
+async fn req_calibration() -> Response {
+    respond(calibration::load())
+}
+
+#[get("/req/calibration/history")]
+async fn req_calibration_history() -> Response {
+    respond(calibration::history())
+}
+
+/// Averages the raw accelerometer readings of the last `seconds` recorded, for calibrating it
+#[get("/req/calibration/capture?<seconds>")]
+async fn req_calibration_capture(seconds: f64) -> Response {
+    if !protocol::CAPTURE_SECONDS_RANGE.contains(&seconds) {
+        return Err(bad_request(format!("capture must last within {:?} seconds", protocol::CAPTURE_SECONDS_RANGE)));
     }
+    respond(sql_parsing::accelerometer_capture(seconds).await)
 }
 
-#[get("/req/data/latest/<column>/<table>")]
-async fn req_data_latest(column: &str, table: &str) -> Result<String, String> {
-    let content = sql_parsing::latest_data(column, table).await;
-    match content {
-        Ok(c) => match serde_json::to_string(&c) {
-            Ok(s) => Ok(s),
-            Err(why) => Err(format!("could not deserialize: {}", why)),
-        },
+/// Validates and stores a new version of the calibration, which applies to every value served from then on
+///
+/// Responds with the calibration as stored
+#[post("/update/calibration", format = "application/json", data = "<value>")]
+async fn update_calibration(value: &str) -> Response {
+    let new_calibration = calibration::parse(value).map_err(bad_request)?;
+    respond(calibration::store(new_calibration))
+}
+
+#[get("/req/schema")]
+async fn req_schema() -> Response {
+    respond(sql_parsing::schema().await)
+}
+
+#[get("/req/dashboards/<user>")]
+async fn req_dashboards(user: &str) -> Response {
+    validate_name(user).map_err(bad_request)?;
+    respond(dashboards::list(user))
+}
+
+#[get("/req/dashboard/<user>/<name>")]
+async fn req_dashboard(user: &str, name: &str) -> Response {
+    validate_name(user).and(validate_name(name)).map_err(bad_request)?;
+    match dashboards::load(user, name) {
+        Ok(None) => Err(status::Custom(Status::NotFound, format!("there is no dashboard {}", name))),
+        loaded => respond(loaded),
+    }
+}
+
+#[delete("/req/dashboard/<user>/<name>")]
+async fn delete_dashboard(user: &str, name: &str) -> Response {
+    validate_name(user).and(validate_name(name)).map_err(bad_request)?;
+    match dashboards::delete(user, name) {
+        Ok(false) => Err(status::Custom(Status::NotFound, format!("there is no dashboard {}", name))),
+        deleted => respond(deleted.map(|_| ())),
+    }
+}
+
+/// Validates and stores a dashboard for `user`, responding with the dashboard as stored
+#[post("/update/dashboard/<user>", format = "application/json", data = "<value>")]
+async fn update_dashboard(user: &str, value: &str) -> Response {
+    validate_name(user).map_err(bad_request)?;
+    let dashboard: Dashboard = serde_json::from_str(value)
+        .map_err(|why| bad_request(format!("invalid dashboard: {}", why)))?;
+    let schema = sql_parsing::schema().await
+        .map_err(|why| status::Custom(Status::InternalServerError, format!("invalid content: {}", why)))?;
+    dashboard.validate(&schema).map_err(bad_request)?;
+    respond(dashboards::store(user, &dashboard, &schema))
+}
+
+#[get("/req/alerts/rules")]
+async fn req_alert_rules() -> Response {
+    respond(alerts::load())
+}
+
+#[get("/req/alerts/events")]
+async fn req_alert_events() -> Response {
+    respond(sql_parsing::alert_events().await)
+}
+
+/// Validates and stores a new set of alert rules, responding with the rules as stored
+#[post("/update/alerts/rules", format = "application/json", data = "<value>")]
+async fn update_alert_rules(value: &str) -> Response {
+    let rules: Vec<AlertRule> = serde_json::from_str(value)
+        .map_err(|why| bad_request(format!("invalid alert rules: {}", why)))?;
+    let mut schema = sql_parsing::schema().await
+        .map_err(|why| status::Custom(Status::InternalServerError, format!("invalid content: {}", why)))?;
+    // rules can also watch whether the GPS is inside a geofence
+    let geofences = geofences::load()
+        .map_err(|why| status::Custom(Status::InternalServerError, format!("invalid content: {}", why)))?;
+    schema.push(protocol::geofence_schema(&geofences));
+    protocol::validate_rules(&rules, &schema).map_err(bad_request)?;
+    respond(alerts::store(&rules, &schema))
+}
+
+#[get("/req/geofences")]
+async fn req_geofences() -> Response {
+    respond(geofences::load())
+}
+
+/// Validates and stores a new set of geofences, responding with the geofences as stored
+#[post("/update/geofences", format = "application/json", data = "<value>")]
+async fn update_geofences(value: &str) -> Response {
+    let geofences: Vec<Geofence> = serde_json::from_str(value)
+        .map_err(|why| bad_request(format!("invalid geofences: {}", why)))?;
+    protocol::validate_geofences(&geofences).map_err(bad_request)?;
+    respond(geofences::store(&geofences))
+}
+
+#[get("/req/annotations")]
+async fn req_annotations() -> Response {
+    respond(sql_parsing::annotations().await)
+}
+
+#[delete("/req/annotation/<id>")]
+async fn delete_annotation(id: i64) -> Response {
+    match sql_parsing::delete_annotation(id).await {
+        Ok(false) => Err(status::Custom(Status::NotFound, format!("there is no annotation {}", id))),
+        deleted => respond(deleted.map(|_| ())),
+    }
+}
+
+/// Stores an annotation, responding with it as stored
+#[post("/update/annotation", format = "application/json", data = "<value>")]
+async fn update_annotation(value: &str) -> Response {
+    let annotation: NewAnnotation = serde_json::from_str(value)
+        .map_err(|why| bad_request(format!("invalid annotation: {}", why)))?;
+    annotation.validate().map_err(bad_request)?;
+    let time = annotation.time.unwrap_or_else(now);
+    respond(sql_parsing::add_annotation(&annotation, time).await)
+}
+
+#[get("/req/tiles")]
+async fn req_tiles() -> Response {
+    respond(tiles::available().await)
+}
+
+/// Responds with a map tile stored on the server, for the map to work without connectivity
+#[get("/tiles/<zoom>/<x>/<y>")]
+async fn tile(zoom: u8, x: u32, y: u32) -> Result<(ContentType, Vec<u8>), status::Custom<String>> {
+    match tiles::load(zoom, x, y).await {
+        Ok(Some(tile)) => Ok(tile),
+        Ok(None) => Err(status::Custom(Status::NotFound, format!("there is no tile {zoom}/{x}/{y}"))),
         Err(why) => {
-            print!("invalid content: {}", why);
-            Err(format!("invalid content: {}", why))
+            println!("couldn't load tile: {}", why);
+            Err(status::Custom(Status::InternalServerError, format!("couldn't load tile: {}", why)))
         },
     }
 }
 
+#[get("/req/gps/fix")]
+async fn req_gps_fix() -> Response {
+    respond(sql_parsing::latest_fix().await)
+}
+
+#[get("/req/gps/status")]
+async fn req_gps_status() -> Response {
+    respond(sql_parsing::gps_status().await)
+}
+
+/// Responds with the GPS track between `from` and `to` if both are given, or with the current session's
+#[get("/req/gps/track?<from>&<to>")]
+async fn req_gps_track(from: Option<f64>, to: Option<f64>) -> Response {
+    respond(sql_parsing::gps_track(track_range(from, to)?).await)
+}
+
+/// Responds with the trip summary between `from` and `to` if both are given, or with the current session's
+#[get("/req/gps/summary?<from>&<to>")]
+async fn req_trip_summary(from: Option<f64>, to: Option<f64>) -> Response {
+    respond(trips::summary(track_range(from, to)?).await)
+}
+
+/// The time range of a GPS request, none for the current session when neither end is given
+fn track_range(from: Option<f64>, to: Option<f64>) -> Result<Option<(f64, f64)>, status::Custom<String>> {
+    match (from, to) {
+        (Some(from), Some(to)) if from.is_finite() && to.is_finite() && from < to => Ok(Some((from, to))),
+        (None, None) => Ok(None),
+        _ => Err(bad_request(format!("invalid time range: {:?} to {:?}", from, to))),
+    }
+}
+
+#[get("/req/data/latest/gps")]
+async fn req_data_latest_gps() -> Response {
+    respond(sql_parsing::latest_gps_latlon().await)
+}
+
+#[get("/req/data/latest/<column>/<table>")]
+async fn req_data_latest(column: &str, table: &str) -> Response {
+    respond(sql_parsing::latest_data(column, table).await)
+}
+
+#[get("/req/data/range/<column>/<table>?<from>&<to>&<points>")]
+async fn req_data_range(column: &str, table: &str, from: f64, to: f64, points: usize) -> Response {
+    if !(from.is_finite() && to.is_finite() && from < to) {
+        return Err(bad_request(format!("invalid time range: {} to {}", from, to)));
+    }
+    respond(sql_parsing::range_data(column, table, from, to, points).await)
+}
+
 #[get("/req/data/full/<param>")]
-async fn req_data_full(param: &str) -> Result<String, String> {
-    let content = match param {
-        "acceleration" => sql_parsing::full_acceleration().await,
-        "gps" => sql_parsing::full_gps().await,
-        "temperature" => sql_parsing::full_temperature().await,
-        // todo: more data types
-        &_ => Err("invalid data type for req_data_full".into()),
+async fn req_data_full(param: &str) -> Response {
+    let content = match DataTable::from_name(param) {
+        Some(DataTable::Acceleration) => sql_parsing::full_acceleration().await,
+        Some(DataTable::Gps) => sql_parsing::full_gps().await,
+        Some(DataTable::Temperature) => sql_parsing::full_temperature().await,
+        Some(DataTable::Geofences) => sql_parsing::full_geofence_events().await,
+        None => return Err(bad_request(format!("invalid data type for req_data_full: {}", param))),
     };
-    match content {
-        Ok(c) => match serde_json::to_string(&c) {
-            Ok(s) => Ok(s),
-            Err(why) => Err(format!("could not deserialize: {}", why)),
-        },
-        Err(why) => Err(format!("invalid content: {}", why)),
-    }
+    respond(content)
 }
 
 #[post("/update", format = "application/json", data = "<value>")]
-async fn update(value: &str) -> Option<&str> {
+async fn update(value: &str) -> Response {
     println!("{}", value);
-    Some(value)
+    let recording: RecordingUpdate = serde_json::from_str(value)
+        .map_err(|why| bad_request(format!("invalid update: {}", why)))?;
+    respond(Ok::<_, String>(recording))
 }
 
+/// Validates and stores new settings, then publishes them to the acquisition process
+///
+/// Responds with the settings as stored, their publication state is reported by `req_settings_status`.
+/// Settings stored but not published would never be applied, which is reported as an error
 #[post("/update/settings", format = "application/json", data = "<value>")]
-async fn update_settings(value: &str) -> Result<&str, String> {
-    println!("{}", value);
-    let mut file = match fs::File::create("settings.json") {
-        Err(why) => return Err(format!("couldn't create settings: {why}")),
-        Ok(file) => file,
+async fn update_settings(value: &str) -> Response {
+    let new_settings = settings::parse(value).map_err(bad_request)?;
+    let config = match settings::store(new_settings) {
+        Ok(config) => config,
+        stored => return respond(stored),
+    };
+    let published = match capabilities::load() {
+        Ok(sensors) => sql_parsing::publish_settings(&config, &sensors).await.map_err(|why| why.to_string()),
+        Err(why) => Err(why),
     };
-    if let Err(why) = file.write_all(value.as_bytes()) {
-        return Err(format!("couldn't write to settings: {why}"))
+    if let Err(why) = published {
+        println!("couldn't publish settings: {}", why);
+        return Err(status::Custom(Status::InternalServerError,
+            format!("settings were saved, but couldn't be published to the acquisition process: {}", why)));
     }
-    Ok(value)
+    respond(Ok::<_, String>(config))
 }
 
 #[launch]
@@ -96,7 +330,27 @@ fn rocket() -> _ {
         .mount("/", routes![update])
         .mount("/", routes![update_settings])
         .mount("/", routes![req_settings])
+        .mount("/", routes![req_settings_history])
+        .mount("/", routes![req_settings_status])
+        .mount("/", routes![req_capabilities])
+        .mount("/", routes![req_calibration, req_calibration_history, req_calibration_capture, update_calibration])
+        .mount("/", routes![req_schema])
+        .mount("/", routes![req_dashboards, req_dashboard, delete_dashboard])
+        .mount("/", routes![update_dashboard])
         .mount("/", routes![req_data_latest])
+        .mount("/", routes![req_data_range])
         .mount("/", routes![req_data_full])
         .mount("/", routes![req_data_latest_gps])
+        .mount("/", routes![req_gps_track, req_trip_summary])
+        .mount("/", routes![req_gps_fix, req_gps_status])
+        .mount("/", routes![req_tiles, tile])
+        .mount("/", routes![req_alert_rules, req_alert_events, update_alert_rules])
+        .mount("/", routes![req_annotations, delete_annotation, update_annotation])
+        .mount("/", routes![req_geofences, update_geofences])
+        .attach(AdHoc::on_liftoff("Alert evaluation", |_| Box::pin(async {
+            rocket::tokio::spawn(alerts::watch());
+        })))
+        .attach(AdHoc::on_liftoff("Geofence evaluation", |_| Box::pin(async {
+            rocket::tokio::spawn(geofences::watch());
+        })))
 }
@@ -0,0 +1,88 @@
+use protocol::{Config, ConfigRevision};
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use crate::{capabilities, now};
+use crate::storage::{append_line, read_lines, write_atomic};
+
+const SETTINGS_PATH: &str = "settings.json";
+const HISTORY_PATH: &str = "settings_history.jsonl";
+
+/// Held while settings are stored, so concurrent saves each archive the version they replace
+static STORING: Mutex<()> = Mutex::new(());
+
+/// Parses a config sent by a client, rejecting unknown shapes, out of range values and settings no sensor offers
+pub fn parse(json: &str) -> Result<Config, String> {
+    let config: Config = serde_json::from_str(json)
+        .map_err(|why| format!("invalid settings: {why}"))?;
+    config.validate(&capabilities::load()?)?;
+    Ok(config)
+}
+
+/// Reads the stored settings, falling back to defaults when none have been saved yet
+///
+/// Older versions of the server stored the settings as a JSON encoded string, and the hardware settings
+/// alongside the other fields, which are both still accepted
+pub fn load() -> Result<Config, String> {
+    let content = match fs::read_to_string(SETTINGS_PATH) {
+        Ok(c) => c,
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(why) => return Err(format!("couldn't read settings: {why}")),
+    };
+    let mut json: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|why| format!("couldn't parse settings: {why}"))?;
+    if let Some(inner) = json.as_str() {
+        json = serde_json::from_str(inner).map_err(|why| format!("couldn't parse settings: {why}"))?;
+    }
+    Config::migrate(&mut json);
+    serde_json::from_value(json).map_err(|why| format!("couldn't parse settings: {why}"))
+}
+
+/// Validates and stores new settings, archiving the previous version, or setting the stored file aside if it can't be read
+///
+/// returns: the settings as they are now stored
+pub fn store(settings: Config) -> Result<Config, String> {
+    settings.validate(&capabilities::load()?)?;
+    let _storing = STORING.lock().unwrap_or_else(PoisonError::into_inner);
+
+    match load() {
+        Ok(previous) if previous == settings => return Ok(previous),
+        Ok(previous) => archive(previous)?,
+        Err(why) => set_aside(&why)?,
+    }
+
+    let json = serde_json::to_string_pretty(&settings)
+        .map_err(|why| format!("couldn't serialize settings: {why}"))?;
+    write_atomic(Path::new(SETTINGS_PATH), &json)?;
+
+    load()
+}
+
+/// Reads every archived version of the settings, oldest first
+pub fn history() -> Result<Vec<ConfigRevision>, String> {
+    read_lines(Path::new(HISTORY_PATH))?
+        .into_iter()
+        .map(|mut revision| {
+            // revisions archived by older versions hold their config under `settings`
+            for field in ["config", "settings"] {
+                if let Some(config) = revision.get_mut(field) {
+                    Config::migrate(config);
+                }
+            }
+            serde_json::from_value(revision).map_err(|why| format!("couldn't parse settings history: {why}"))
+        })
+        .collect()
+}
+
+fn archive(settings: Config) -> Result<(), String> {
+    append_line(Path::new(HISTORY_PATH), &ConfigRevision { replaced_at: now() as u64, config: settings })
+}
+
+/// Renames the stored settings out of the way as `settings.json.<unix time>.unreadable`,
+/// as settings which can't be read can't be archived either and would otherwise be lost
+fn set_aside(why: &str) -> Result<(), String> {
+    let kept = format!("{SETTINGS_PATH}.{}.unreadable", now() as u64);
+    fs::rename(SETTINGS_PATH, &kept).map_err(|error| format!("{why}, and couldn't set them aside: {error}"))?;
+    println!("{why}, so they were kept as {kept}");
+    Ok(())
+}
@@ -1,9 +1,80 @@
-use sqlx::{sqlite::SqlitePool, Row};
+use sqlx::{sqlite::SqlitePool, Row as _};
 
 use lazy_static::lazy_static;
 use dirs;
-
-const MAX_WIDTH: usize = 12;
+use protocol::{bearing, distance, has_column, AccelerometerCapture, AlertEvent, AlertRule, Annotation, ColumnRef, Comparator, Config, Crossing, Fix, FixQuality, GpsStatus, LatLon, NewAnnotation, PlotPoint, Row, SensorCapabilities, SettingStatus, TableSchema, TrackPoint, ACCELEROMETER_COLUMNS, ACCELEROMETER_TABLE, GEOFENCE_TABLE, INVALID_FIX_TYPES, TEMPERATURE_COLUMN, TEMPERATURE_TABLE};
+use crate::calibration;
+
+/// Control table shared with the acquisition process. The server writes `value` and `requested_at`
+/// whenever a setting changes; the acquisition process applies it and then sets `applied_value` and `applied_at`.
+const SENSOR_SETTINGS_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS sensor_settings (
+    name TEXT PRIMARY KEY,
+    value TEXT NOT NULL,
+    requested_at TEXT NOT NULL,
+    applied_value TEXT,
+    applied_at TEXT
+)";
+
+/// Periods during which an alert rule was raised, written by the server's alert evaluation
+const ALERT_EVENTS_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS alert_events (
+    id INTEGER PRIMARY KEY,
+    rule TEXT NOT NULL,
+    table_name TEXT NOT NULL,
+    column_name TEXT NOT NULL,
+    comparator TEXT NOT NULL,
+    threshold REAL NOT NULL,
+    started_at REAL NOT NULL,
+    cleared_at REAL,
+    peak REAL NOT NULL,
+    latitude REAL,
+    longitude REAL
+)";
+
+/// Notes marking moments of a run, added from the home panel
+const ANNOTATIONS_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS annotations (
+    id INTEGER PRIMARY KEY,
+    time REAL NOT NULL,
+    text TEXT NOT NULL,
+    latitude REAL,
+    longitude REAL
+)";
+
+/// Times the GPS entered or left a geofence, written by the server's geofence evaluation
+const GEOFENCE_EVENTS_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS geofence_events (
+    id INTEGER PRIMARY KEY,
+    geofence TEXT NOT NULL,
+    crossing TEXT NOT NULL,
+    time REAL NOT NULL,
+    latitude REAL NOT NULL,
+    longitude REAL NOT NULL
+)";
+
+/// Most annotations returned at once
+const MAX_ANNOTATIONS: i64 = 1000;
+/// Longest time between an annotation and the GPS fix it is placed at, in seconds
+const MAX_FIX_GAP: f64 = 30.0;
+
+/// Most points returned for a GPS track, longer tracks are thinned out evenly
+const MAX_TRACK_POINTS: usize = 5000;
+/// Longest gap between two fixes of the same session, in seconds
+const SESSION_GAP: f64 = 300.0;
+
+/// Fixes searched back through for one far enough from the latest to give a heading
+const HEADING_FIXES: i64 = 30;
+/// Distance the receiver must have moved for its heading to be known, in metres
+const MIN_HEADING_DISTANCE: f64 = 3.0;
+/// Typical error of a fix at an HDOP of 1, in metres
+const HDOP_ERROR: f64 = 5.0;
+/// Time before the newest GPS row whose fix quality is sent with the GPS status, in seconds
+const STATUS_HISTORY: f64 = 600.0;
+
+/// Most alert events returned at once
+const MAX_ALERT_EVENTS: i64 = 100;
+/// Most rows evaluated against an alert rule at once
+const MAX_ALERT_ROWS: i64 = 10000;
+
+/// Tables written by the server rather than the sensors, which are never offered for plotting
+const SERVER_TABLES: [&str; 4] = ["sensor_settings", "alert_events", "annotations", "geofence_events"];
 
 lazy_static! {
     static ref SQLITE_DATABASE_PATH: String = {
@@ -12,17 +83,30 @@ lazy_static! {
         path.push("sensor_data.db");
         path.to_string_lossy().into_owned()
     };
+
+    /// SQL condition holding for the rows of `gps_data` with a valid fix, which `FixQuality::from_fix_type` agrees with
+    static ref VALID_FIX: String = {
+        let types: Vec<String> = INVALID_FIX_TYPES.iter().map(|fix_type| format!("'{fix_type}'")).collect();
+        format!("lower(trim(fix_type)) NOT IN ({})", types.join(", "))
+    };
+}
+
+/// Connects to the sensor database, for work which queries it repeatedly and keeps its connections open
+pub async fn connect() -> Result<SqlitePool, sqlx::Error> {
+    SqlitePool::connect(SQLITE_DATABASE_PATH.as_str()).await
 }
 
-/// Gets acceleration data and packs it into a vector of arrays of id, time, x, y, z
-pub async fn full_acceleration() -> Result<Vec<[String; MAX_WIDTH]>, Box<dyn std::error::Error>> {
+/// Gets calibrated acceleration data and packs it into a vector of arrays of id, time, x, y, z
+pub async fn full_acceleration() -> Result<Vec<Row>, Box<dyn std::error::Error>> {
     let pool = SqlitePool::connect(SQLITE_DATABASE_PATH.as_str()).await?;
-    let qry: &str = "SELECT id, timestamp, accelerometer_x, accelerometer_y, accelerometer_z FROM accelerometer_data WHERE id IN (SELECT id FROM accelerometer_data ORDER BY id DESC LIMIT 1000)";
-    let acceleration = sqlx::query(qry).fetch_all(&pool).await?;
+    let calibration = calibration::load()?;
+    let [x, y, z] = ACCELEROMETER_COLUMNS.map(|column| calibration::value_expression(&calibration, column, ACCELEROMETER_TABLE));
+    let qry = format!("SELECT id, timestamp, {x}, {y}, {z} FROM accelerometer_data WHERE id IN (SELECT id FROM accelerometer_data ORDER BY id DESC LIMIT 1000)");
+    let acceleration = sqlx::query(&qry).fetch_all(&pool).await?;
 
-    let mut accel: Vec<[String; MAX_WIDTH]> = Default::default();
+    let mut accel: Vec<Row> = Default::default();
     for row in acceleration {
-        let mut array: [String; MAX_WIDTH] = Default::default();
+        let mut array: Row = Default::default();
 
         let id = row.get::<i32, usize>(0).to_string();
         array[0] = id;
@@ -45,14 +129,14 @@ pub async fn full_acceleration() -> Result<Vec<[String; MAX_WIDTH]>, Box<dyn std
     Ok(accel)
 }
 
-pub async fn full_gps() -> Result<Vec<[String; MAX_WIDTH]>, Box<dyn std::error::Error>> {
+pub async fn full_gps() -> Result<Vec<Row>, Box<dyn std::error::Error>> {
     let pool = SqlitePool::connect(SQLITE_DATABASE_PATH.as_str()).await?;
     let qry: &str = "SELECT fix_type, fix_time, fix_date, latitude, longitude, altitude, speed_over_ground, geoid_separation FROM gps_data WHERE fix_time IN (SELECT fix_time FROM gps_data ORDER BY fix_time DESC LIMIT 1000)";
     let acceleration = sqlx::query(qry).fetch_all(&pool).await?;
 
-    let mut gps: Vec<[String; MAX_WIDTH]> = Default::default();
+    let mut gps: Vec<Row> = Default::default();
     for row in acceleration {
-        let mut array: [String; MAX_WIDTH] = Default::default();
+        let mut array: Row = Default::default();
         
         let fix_type = row.get::<String, usize>(0);
         array[0] = fix_type;
@@ -84,14 +168,16 @@ pub async fn full_gps() -> Result<Vec<[String; MAX_WIDTH]>, Box<dyn std::error::
     Ok(gps)
 }
 
-pub async fn full_temperature() -> Result<Vec<[String; MAX_WIDTH]>, Box<dyn std::error::Error>> {
+/// Gets calibrated temperature data and packs it into a vector of arrays of id, timestamp, temperature
+pub async fn full_temperature() -> Result<Vec<Row>, Box<dyn std::error::Error>> {
     let pool = SqlitePool::connect(SQLITE_DATABASE_PATH.as_str()).await?;
-    let qry: &str = "SELECT * FROM thermalprobe_data WHERE id IN (SELECT id FROM thermalprobe_data ORDER BY id DESC LIMIT 1000)";
-    let temperature = sqlx::query(qry).fetch_all(&pool).await?;
+    let celsius = calibration::value_expression(&calibration::load()?, TEMPERATURE_COLUMN, TEMPERATURE_TABLE);
+    let qry = format!("SELECT id, timestamp, {celsius} FROM thermalprobe_data WHERE id IN (SELECT id FROM thermalprobe_data ORDER BY id DESC LIMIT 1000)");
+    let temperature = sqlx::query(&qry).fetch_all(&pool).await?;
 
-    let mut temp: Vec<[String; MAX_WIDTH]> = Default::default();
+    let mut temp: Vec<Row> = Default::default();
     for row in temperature {
-        let mut array: [String; MAX_WIDTH] = Default::default();
+        let mut array: Row = Default::default();
 
         let id = row.get::<i32, usize>(0).to_string();
         array[0] = id;
@@ -108,12 +194,32 @@ pub async fn full_temperature() -> Result<Vec<[String; MAX_WIDTH]>, Box<dyn std:
     Ok(temp)
 }
 
-pub async fn latest_gps_latlon() -> Result<Vec<[f64; 2]>, Box<dyn std::error::Error>> {
+/// Gets the latest 1000 geofence events as rows of id, time, geofence, crossing, latitude, longitude
+pub async fn full_geofence_events() -> Result<Vec<Row>, Box<dyn std::error::Error>> {
     let pool = SqlitePool::connect(SQLITE_DATABASE_PATH.as_str()).await?;
-    let qry: &str = "SELECT latitude, longitude FROM gps_data WHERE fix_time IN (SELECT fix_time FROM gps_data ORDER BY fix_time DESC LIMIT 50) AND (fix_type != 'Invalid')";
-    let gps_data = sqlx::query(qry).fetch_all(&pool).await?;
+    sqlx::query(GEOFENCE_EVENTS_SCHEMA).execute(&pool).await?;
+    let qry: &str = "SELECT id, datetime(time, 'unixepoch'), geofence, crossing, latitude, longitude FROM geofence_events
+        WHERE id IN (SELECT id FROM geofence_events ORDER BY id DESC LIMIT 1000)";
+    let events = sqlx::query(qry).fetch_all(&pool).await?;
+
+    Ok(events.iter().map(|row| {
+        let mut array: Row = Default::default();
+        array[0] = row.get::<i64, usize>(0).to_string();
+        array[1] = row.get::<String, usize>(1);
+        array[2] = row.get::<String, usize>(2);
+        array[3] = row.get::<String, usize>(3);
+        array[4] = row.get::<f64, usize>(4).to_string();
+        array[5] = row.get::<f64, usize>(5).to_string();
+        array
+    }).collect())
+}
+
+pub async fn latest_gps_latlon() -> Result<Vec<LatLon>, Box<dyn std::error::Error>> {
+    let pool = SqlitePool::connect(SQLITE_DATABASE_PATH.as_str()).await?;
+    let qry = format!("SELECT latitude, longitude FROM gps_data WHERE fix_time IN (SELECT fix_time FROM gps_data ORDER BY fix_time DESC LIMIT 50) AND ({})", *VALID_FIX);
+    let gps_data = sqlx::query(&qry).fetch_all(&pool).await?;
     
-    let mut gps: Vec<[f64; 2]> = vec![];
+    let mut gps: Vec<LatLon> = vec![];
     for row in gps_data {
         let lat: f64 = row.get(0);
         let lon: f64 = row.get(1);
@@ -123,18 +229,549 @@ pub async fn latest_gps_latlon() -> Result<Vec<[f64; 2]>, Box<dyn std::error::Er
     Ok(gps)
 }
 
-pub async fn latest_data(column: &str, table: &str) -> Result<Vec<[f64; 2]>, Box<dyn std::error::Error>> {
+/// Gets the valid fixes between the unix times `from` and `to`, or those of the current session,
+/// thinned out to at most `MAX_TRACK_POINTS`
+pub async fn gps_track(range: Option<(f64, f64)>) -> Result<Vec<TrackPoint>, Box<dyn std::error::Error>> {
+    let points = track_points(range).await?;
+
+    let stride = points.len().div_ceil(MAX_TRACK_POINTS).max(1);
+    let last = points.len().saturating_sub(1);
+    Ok(points.into_iter()
+        .enumerate()
+        // thin out long tracks, always keeping where they end
+        .filter(|(i, _)| i % stride == 0 || *i == last)
+        .map(|(_, point)| point)
+        .collect())
+}
+
+/// Gets every valid fix between the unix times `from` and `to`, or those of the current session,
+/// which started after the last gap of more than `SESSION_GAP` between fixes
+pub async fn track_points(range: Option<(f64, f64)>) -> Result<Vec<TrackPoint>, Box<dyn std::error::Error>> {
+    let pool = SqlitePool::connect(SQLITE_DATABASE_PATH.as_str()).await?;
+    let fix_time = plottable_time(&pool, "latitude", "gps_data").await?;
+    let fixes = format!("SELECT {fix_time} AS t, latitude, longitude, speed_over_ground, altitude FROM gps_data
+        WHERE {valid_fix} AND latitude IS NOT NULL AND longitude IS NOT NULL", valid_fix = *VALID_FIX);
+
+    let (from, to) = match range {
+        Some(range) => range,
+        None => {
+            let qry = format!("SELECT COALESCE(MAX(CASE WHEN t - previous > ? THEN t END), MIN(t)) FROM (
+                    SELECT t, LAG(t) OVER (ORDER BY t) AS previous FROM ({fixes})
+                )");
+            let start: Option<f64> = sqlx::query(&qry).bind(SESSION_GAP).fetch_one(&pool).await?.get(0);
+            match start {
+                Some(start) => (start, f64::MAX),
+                None => return Ok(vec![]),
+            }
+        },
+    };
+
+    let qry = format!("SELECT t, latitude, longitude, speed_over_ground, altitude FROM ({fixes}) WHERE t BETWEEN ? AND ? ORDER BY t");
+    let rows = sqlx::query(&qry).bind(from).bind(to).fetch_all(&pool).await?;
+
+    Ok(rows.iter()
+        .map(|row| TrackPoint {
+            time: row.get(0),
+            position: [row.get(1), row.get(2)],
+            speed: row.get::<Option<f64>, usize>(3).unwrap_or_default(),
+            altitude: row.get::<Option<f64>, usize>(4).unwrap_or_default(),
+        })
+        .collect())
+}
+
+/// Gets the latest valid fix, heading from the last fix far enough away from it,
+/// and accurate to its HDOP if `gps_data` has an `hdop` column
+pub async fn latest_fix() -> Result<Option<Fix>, Box<dyn std::error::Error>> {
+    let pool = SqlitePool::connect(SQLITE_DATABASE_PATH.as_str()).await?;
+    let fix_time = plottable_time(&pool, "latitude", "gps_data").await?;
+    let hdop = if has_gps_column(&pool, "hdop").await? { "CAST(hdop AS REAL)" } else { "NULL" };
+
+    let qry = format!("SELECT {fix_time} AS t, latitude, longitude, speed_over_ground, altitude, {hdop} FROM gps_data
+        WHERE {valid_fix} AND latitude IS NOT NULL AND longitude IS NOT NULL
+        ORDER BY t DESC LIMIT ?", valid_fix = *VALID_FIX);
+    let rows = sqlx::query(&qry).bind(HEADING_FIXES).fetch_all(&pool).await?;
+    let Some(latest) = rows.first() else {
+        return Ok(None);
+    };
+
+    let point = TrackPoint {
+        time: latest.get(0),
+        position: [latest.get(1), latest.get(2)],
+        speed: latest.get::<Option<f64>, usize>(3).unwrap_or_default(),
+        altitude: latest.get::<Option<f64>, usize>(4).unwrap_or_default(),
+    };
+    let heading = rows.iter()
+        .map(|row| [row.get(1), row.get(2)])
+        .find(|&position| distance(position, point.position) >= MIN_HEADING_DISTANCE)
+        .map(|position| bearing(position, point.position));
+    let accuracy = latest.get::<Option<f64>, usize>(5).map(|hdop| hdop * HDOP_ERROR);
+    Ok(Some(Fix { point, heading, accuracy }))
+}
+
+/// Gets the fix type, altitude and precision of the newest GPS row whether it's valid or not,
+/// along with the fix quality of every row in the `STATUS_HISTORY` before it
+pub async fn gps_status() -> Result<Option<GpsStatus>, Box<dyn std::error::Error>> {
+    let pool = SqlitePool::connect(SQLITE_DATABASE_PATH.as_str()).await?;
+    let tables = sensor_tables(&pool).await?;
+    let fix_time = table_time(&tables, "latitude", "gps_data")?;
+    let hdop = if has_gps_column(&pool, "hdop").await? { "CAST(hdop AS REAL)" } else { "NULL" };
+    let satellites = if has_gps_column(&pool, "satellites").await? { "CAST(satellites AS INTEGER)" } else { "NULL" };
+
+    let rows = format!("SELECT {fix_time} AS t, fix_type, altitude, geoid_separation, {hdop} AS hdop, {satellites} AS satellites FROM gps_data");
+    let qry = format!("SELECT t, fix_type, altitude, geoid_separation, hdop, satellites FROM ({rows})
+        WHERE t IS NOT NULL ORDER BY t DESC LIMIT 1");
+    let Some(latest) = sqlx::query(&qry).fetch_optional(&pool).await? else {
+        return Ok(None);
+    };
+    let time: f64 = latest.get(0);
+    let fix_type = latest.get::<Option<String>, usize>(1).unwrap_or_default();
+
+    let qry = format!("SELECT t, fix_type FROM ({rows}) WHERE t >= ? ORDER BY t LIMIT ?");
+    let history = sqlx::query(&qry)
+        .bind(time - STATUS_HISTORY)
+        .bind(MAX_TRACK_POINTS as i64)
+        .fetch_all(&pool)
+        .await?
+        .iter()
+        .map(|row| {
+            let fix_type = row.get::<Option<String>, usize>(1).unwrap_or_default();
+            [row.get(0), FixQuality::from_fix_type(&fix_type).level()]
+        })
+        .collect();
+
+    Ok(Some(GpsStatus {
+        time,
+        quality: FixQuality::from_fix_type(&fix_type),
+        fix_type,
+        last_fix: latest_fix_time(&pool, &tables).await?,
+        altitude: latest.get(2),
+        geoid_separation: latest.get(3),
+        hdop: latest.get(4),
+        satellites: latest.get::<Option<i64>, usize>(5).and_then(|count| u32::try_from(count).ok()),
+        history,
+    }))
+}
+
+/// Whether `gps_data` has the optional column `name`
+async fn has_gps_column(pool: &SqlitePool, name: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    Ok(sqlx::query("SELECT COUNT(*) > 0 FROM pragma_table_info('gps_data') WHERE name = ?")
+        .bind(name)
+        .fetch_one(pool)
+        .await?
+        .get(0))
+}
+
+/// Most points returned for a time range, however wide it is
+const MAX_RANGE_POINTS: usize = 2000;
+
+/// Gets the latest 50 values of `column` in `table` as points of unix time and value, oldest first
+pub async fn latest_data(column: &str, table: &str) -> Result<Vec<PlotPoint>, Box<dyn std::error::Error>> {
     let pool = SqlitePool::connect(SQLITE_DATABASE_PATH.as_str()).await?;
-    let qry = format!("SELECT {} FROM {} WHERE id IN (SELECT id FROM {} ORDER BY id DESC LIMIT 50)", column, table, table);
+    let time = plottable_time(&pool, column, table).await?;
+    let value = calibration::value_expression(&calibration::load()?, column, table);
+
+    let qry = format!("SELECT {time}, {value} FROM {} WHERE {value} IS NOT NULL ORDER BY rowid DESC LIMIT 50", quote_identifier(table));
     let data = sqlx::query(&qry).fetch_all(&pool).await?;
 
-    let mut d: Vec<[f64; 2]> = vec![];
-    let mut i: f64 = 0.0;
-    for row in data {
-        let val: f64 = row.get(0);
-        d.push([i, val]);
-        i += 1.0;
-    }
+    let mut d: Vec<PlotPoint> = data.iter()
+        .filter_map(|row| Some([row.get::<Option<f64>, usize>(0)?, row.get::<f64, usize>(1)]))
+        .collect();
+    d.reverse();
 
     Ok(d)
-}
\ No newline at end of file
+}
+
+/// Gets the values of `column` in `table` between the unix times `from` and `to`, averaged into at most `points` evenly spaced buckets
+pub async fn range_data(column: &str, table: &str, from: f64, to: f64, points: usize) -> Result<Vec<PlotPoint>, Box<dyn std::error::Error>> {
+    let points = points.clamp(1, MAX_RANGE_POINTS);
+    let bucket = (to - from) / points as f64;
+
+    let pool = SqlitePool::connect(SQLITE_DATABASE_PATH.as_str()).await?;
+    let time = plottable_time(&pool, column, table).await?;
+    let value = calibration::value_expression(&calibration::load()?, column, table);
+
+    let qry = format!("SELECT AVG(t), AVG(v) FROM (
+            SELECT {time} AS t, {value} AS v FROM {} WHERE {value} IS NOT NULL
+        ) WHERE t BETWEEN ? AND ? GROUP BY CAST((t - ?) / ? AS INTEGER) ORDER BY 1", quote_identifier(table));
+    let data = sqlx::query(&qry)
+        .bind(from)
+        .bind(to)
+        .bind(from)
+        .bind(bucket)
+        .fetch_all(&pool)
+        .await?;
+
+    Ok(data.iter().map(|row| [row.get::<f64, usize>(0), row.get::<f64, usize>(1)]).collect())
+}
+
+/// Lists every sensor table with a time column, along with its numeric columns, which are the ones that can be plotted
+pub async fn schema() -> Result<Vec<TableSchema>, Box<dyn std::error::Error>> {
+    let pool = SqlitePool::connect(SQLITE_DATABASE_PATH.as_str()).await?;
+    Ok(sensor_tables(&pool).await?.into_iter().map(|(table, _)| table).collect())
+}
+
+/// Every sensor table that can be plotted, with the SQL expression giving the unix time of each of its rows
+pub async fn sensor_tables(pool: &SqlitePool) -> Result<Vec<(TableSchema, &'static str)>, Box<dyn std::error::Error>> {
+    let qry: &str = "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name";
+    let tables = sqlx::query(qry).fetch_all(pool).await?;
+
+    let mut schema = vec![];
+    for table in tables {
+        let name: String = table.get(0);
+        // a recorded table named like the geofences alert rules watch would be mistaken for them
+        if SERVER_TABLES.contains(&name.as_str()) || name == GEOFENCE_TABLE {
+            continue;
+        }
+
+        let columns = sqlx::query("SELECT name, type FROM pragma_table_info(?)")
+            .bind(&name)
+            .fetch_all(pool)
+            .await?;
+        let names: Vec<String> = columns.iter().map(|column| column.get(0)).collect();
+        let Some(time) = time_expression(&names) else {
+            continue;
+        };
+        let columns: Vec<String> = columns
+            .into_iter()
+            .filter(|column| is_numeric(&column.get::<String, usize>(1)))
+            .map(|column| column.get::<String, usize>(0))
+            .filter(|column| column != "id")
+            .collect();
+
+        if !columns.is_empty() {
+            schema.push((TableSchema { name, columns }, time));
+        }
+    }
+
+    Ok(schema)
+}
+
+/// Checks `column` of `table` can be plotted
+///
+/// returns: the SQL expression giving the unix time of each of the table's rows
+async fn plottable_time(pool: &SqlitePool, column: &str, table: &str) -> Result<&'static str, Box<dyn std::error::Error>> {
+    table_time(&sensor_tables(pool).await?, column, table)
+}
+
+/// Checks `column` of `table` is among the plottable columns of `tables`, as listed by `sensor_tables`
+///
+/// returns: the SQL expression giving the unix time of each of the table's rows
+fn table_time(tables: &[(TableSchema, &'static str)], column: &str, table: &str) -> Result<&'static str, Box<dyn std::error::Error>> {
+    let source = ColumnRef { table: table.to_string(), column: column.to_string() };
+    tables.iter()
+        .find(|(schema, _)| has_column(std::slice::from_ref(schema), &source))
+        .map(|(_, time)| *time)
+        .ok_or_else(|| format!("{}.{} can't be plotted", table, column).into())
+}
+
+/// `name` quoted as an SQL identifier, so it is never read as SQL whatever characters it holds
+pub fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// SQL expression for the unix time of a row, from a table's column names, rounded to the millisecond
+/// as julianday isn't exact. Sensor tables have a `timestamp`, except `gps_data` which records the date and time of each fix
+fn time_expression(columns: &[String]) -> Option<&'static str> {
+    let has = |name: &str| columns.iter().any(|column| column == name);
+    if has("timestamp") {
+        Some("ROUND((julianday(timestamp) - 2440587.5) * 86400.0, 3)")
+    } else if has("fix_date") && has("fix_time") {
+        Some("ROUND((julianday(fix_date || ' ' || fix_time) - 2440587.5) * 86400.0, 3)")
+    } else {
+        None
+    }
+}
+
+/// Whether a declared column type has numeric affinity, following SQLite's affinity rules
+fn is_numeric(declared_type: &str) -> bool {
+    let declared_type = declared_type.to_uppercase();
+    ["INT", "REAL", "FLOA", "DOUB", "NUM", "DEC"].iter().any(|t| declared_type.contains(t))
+}
+
+/// Publishes the settings `sensors` offer, as set in `config`, to the acquisition process, leaving unchanged settings untouched
+pub async fn publish_settings(config: &Config, sensors: &[SensorCapabilities]) -> Result<(), Box<dyn std::error::Error>> {
+    let pool = SqlitePool::connect(SQLITE_DATABASE_PATH.as_str()).await?;
+    sqlx::query(SENSOR_SETTINGS_SCHEMA).execute(&pool).await?;
+
+    let qry: &str = "INSERT INTO sensor_settings (name, value, requested_at) VALUES (?, ?, datetime('now'))
+        ON CONFLICT(name) DO UPDATE SET value = excluded.value, requested_at = excluded.requested_at
+        WHERE sensor_settings.value != excluded.value";
+    for (name, value) in config.hardware_settings(sensors) {
+        sqlx::query(qry).bind(name).bind(value).execute(&pool).await?;
+    }
+
+    Ok(())
+}
+
+/// Gets the publication state of every hardware setting
+pub async fn settings_status() -> Result<Vec<SettingStatus>, Box<dyn std::error::Error>> {
+    let pool = SqlitePool::connect(SQLITE_DATABASE_PATH.as_str()).await?;
+    sqlx::query(SENSOR_SETTINGS_SCHEMA).execute(&pool).await?;
+
+    let qry: &str = "SELECT name, value, requested_at, applied_value, applied_at FROM sensor_settings ORDER BY name";
+    let settings = sqlx::query(qry).fetch_all(&pool).await?;
+
+    let mut status: Vec<SettingStatus> = vec![];
+    for row in settings {
+        status.push(SettingStatus {
+            name: row.get(0),
+            value: row.get(1),
+            requested_at: row.get(2),
+            applied_value: row.get(3),
+            applied_at: row.get(4),
+        });
+    }
+
+    Ok(status)
+}
+
+/// Unix time of the newest row holding a value of `source`, if there is one, `tables` being the sensor tables
+pub async fn latest_time(pool: &SqlitePool, tables: &[(TableSchema, &'static str)], source: &ColumnRef) -> Result<Option<f64>, Box<dyn std::error::Error>> {
+    let time = table_time(tables, &source.column, &source.table)?;
+
+    let qry = format!("SELECT MAX({time}) FROM {} WHERE {} IS NOT NULL", quote_identifier(&source.table), quote_identifier(&source.column));
+    Ok(sqlx::query(&qry).fetch_one(pool).await?.get(0))
+}
+
+/// Gets the values of `source` recorded after the unix time `after`, oldest first, `tables` being the sensor tables
+pub async fn data_since(pool: &SqlitePool, tables: &[(TableSchema, &'static str)], source: &ColumnRef, after: f64) -> Result<Vec<PlotPoint>, Box<dyn std::error::Error>> {
+    let time = table_time(tables, &source.column, &source.table)?;
+    let value = calibration::value_expression(&calibration::load()?, &source.column, &source.table);
+
+    let qry = format!("SELECT t, v FROM (
+            SELECT {time} AS t, {value} AS v FROM {} WHERE {value} IS NOT NULL
+        ) WHERE t > ? ORDER BY t LIMIT ?", quote_identifier(&source.table));
+    let data = sqlx::query(&qry).bind(after).bind(MAX_ALERT_ROWS).fetch_all(pool).await?;
+
+    Ok(data.iter().map(|row| [row.get::<f64, usize>(0), row.get::<f64, usize>(1)]).collect())
+}
+
+/// Averages the accelerometer readings of the `seconds` before the newest one, as recorded rather than calibrated
+pub async fn accelerometer_capture(seconds: f64) -> Result<Option<AccelerometerCapture>, Box<dyn std::error::Error>> {
+    let pool = SqlitePool::connect(SQLITE_DATABASE_PATH.as_str()).await?;
+    let time = plottable_time(&pool, ACCELEROMETER_COLUMNS[0], ACCELEROMETER_TABLE).await?;
+
+    let [x, y, z] = ACCELEROMETER_COLUMNS;
+    let qry = format!("SELECT COUNT(*), MAX(t), AVG(x), AVG(y), AVG(z), AVG(x * x), AVG(y * y), AVG(z * z) FROM (
+            SELECT {time} AS t, CAST(\"{x}\" AS REAL) AS x, CAST(\"{y}\" AS REAL) AS y, CAST(\"{z}\" AS REAL) AS z
+            FROM \"{ACCELEROMETER_TABLE}\"
+        ) WHERE x IS NOT NULL AND y IS NOT NULL AND z IS NOT NULL AND t >= (SELECT MAX({time}) FROM \"{ACCELEROMETER_TABLE}\") - ?");
+    let row = sqlx::query(&qry).bind(seconds).fetch_one(&pool).await?;
+
+    let samples = row.get::<i64, usize>(0) as usize;
+    let Some(end) = row.get::<Option<f64>, usize>(1) else {
+        return Ok(None);
+    };
+    let mean = [2, 3, 4].map(|i| row.get::<f64, usize>(i));
+    // variance is the mean of the squares less the square of the mean, which rounding can take just below 0
+    let spread = [0, 1, 2].map(|i| (row.get::<f64, usize>(i + 5) - mean[i] * mean[i]).max(0.0).sqrt());
+    Ok(Some(AccelerometerCapture { samples, mean, spread, end }))
+}
+
+/// Unix time of the newest valid GPS fix, if there is one, `tables` being the sensor tables
+pub async fn latest_fix_time(pool: &SqlitePool, tables: &[(TableSchema, &'static str)]) -> Result<Option<f64>, Box<dyn std::error::Error>> {
+    let time = table_time(tables, "latitude", "gps_data")?;
+
+    let qry = format!("SELECT MAX({time}) FROM gps_data WHERE {valid_fix} AND latitude IS NOT NULL AND longitude IS NOT NULL", valid_fix = *VALID_FIX);
+    Ok(sqlx::query(&qry).fetch_one(pool).await?.get(0))
+}
+
+/// Gets the unix time and position of the valid GPS fixes after the unix time `after`, oldest first,
+/// `tables` being the sensor tables
+pub async fn fixes_since(pool: &SqlitePool, tables: &[(TableSchema, &'static str)], after: f64) -> Result<Vec<(f64, LatLon)>, Box<dyn std::error::Error>> {
+    let time = table_time(tables, "latitude", "gps_data")?;
+
+    let qry = format!("SELECT t, latitude, longitude FROM (
+            SELECT {time} AS t, latitude, longitude FROM gps_data
+            WHERE {valid_fix} AND latitude IS NOT NULL AND longitude IS NOT NULL
+        ) WHERE t > ? ORDER BY t LIMIT ?", valid_fix = *VALID_FIX);
+    let fixes = sqlx::query(&qry).bind(after).bind(MAX_ALERT_ROWS).fetch_all(pool).await?;
+
+    Ok(fixes.iter().map(|row| (row.get(0), [row.get(1), row.get(2)])).collect())
+}
+
+/// Records that the GPS crossed the edge of `geofence` at the unix time `time`
+pub async fn add_geofence_event(pool: &SqlitePool, geofence: &str, crossing: Crossing, time: f64, position: LatLon) -> Result<(), Box<dyn std::error::Error>> {
+    sqlx::query(GEOFENCE_EVENTS_SCHEMA).execute(pool).await?;
+
+    sqlx::query("INSERT INTO geofence_events (geofence, crossing, time, latitude, longitude) VALUES (?, ?, ?, ?, ?)")
+        .bind(geofence)
+        .bind(crossing.as_str())
+        .bind(time)
+        .bind(position[0])
+        .bind(position[1])
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Gets the last crossing logged for each geofence, which tells whether the GPS was last inside it
+pub async fn last_crossings(pool: &SqlitePool) -> Result<Vec<(String, Crossing)>, Box<dyn std::error::Error>> {
+    sqlx::query(GEOFENCE_EVENTS_SCHEMA).execute(pool).await?;
+
+    let qry: &str = "SELECT geofence, crossing FROM geofence_events
+        WHERE id IN (SELECT MAX(id) FROM geofence_events GROUP BY geofence)";
+    let crossings = sqlx::query(qry).fetch_all(pool).await?;
+
+    Ok(crossings.iter()
+        .filter_map(|row| {
+            let crossing = Crossing::ALL.into_iter().find(|c| c.as_str() == row.get::<&str, usize>(1))?;
+            Some((row.get(0), crossing))
+        })
+        .collect())
+}
+
+/// Records that `rule` was raised at the unix time `started_at`
+///
+/// returns: id of the new alert event
+pub async fn open_alert(pool: &SqlitePool, rule: &AlertRule, started_at: f64, peak: f64) -> Result<i64, Box<dyn std::error::Error>> {
+    sqlx::query(ALERT_EVENTS_SCHEMA).execute(pool).await?;
+
+    // alerts are still raised when there's no GPS data to place them with
+    let position = fix_near(pool, started_at).await.unwrap_or_else(|why| {
+        println!("couldn't place alert: {}", why);
+        None
+    });
+
+    let qry: &str = "INSERT INTO alert_events (rule, table_name, column_name, comparator, threshold, started_at, peak, latitude, longitude)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)";
+    let result = sqlx::query(qry)
+        .bind(&rule.name)
+        .bind(&rule.source.table)
+        .bind(&rule.source.column)
+        .bind(rule.comparator.as_str())
+        .bind(rule.threshold)
+        .bind(started_at)
+        .bind(peak)
+        .bind(position.map(|[lat, _]| lat))
+        .bind(position.map(|[_, lon]| lon))
+        .execute(pool)
+        .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+/// Updates the peak of an alert event, and clears it if `cleared_at` is given
+pub async fn update_alert(pool: &SqlitePool, id: i64, peak: f64, cleared_at: Option<f64>) -> Result<(), Box<dyn std::error::Error>> {
+    sqlx::query("UPDATE alert_events SET peak = ?, cleared_at = ? WHERE id = ?")
+        .bind(peak)
+        .bind(cleared_at)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Gets the latest alert events, newest first
+pub async fn alert_events() -> Result<Vec<AlertEvent>, Box<dyn std::error::Error>> {
+    let pool = SqlitePool::connect(SQLITE_DATABASE_PATH.as_str()).await?;
+    query_alerts(&pool, "ORDER BY started_at DESC LIMIT ?").await
+}
+
+/// Gets the alert events which haven't been cleared
+pub async fn active_alerts(pool: &SqlitePool) -> Result<Vec<AlertEvent>, Box<dyn std::error::Error>> {
+    query_alerts(pool, "WHERE cleared_at IS NULL ORDER BY started_at DESC LIMIT ?").await
+}
+
+async fn query_alerts(pool: &SqlitePool, clauses: &str) -> Result<Vec<AlertEvent>, Box<dyn std::error::Error>> {
+    sqlx::query(ALERT_EVENTS_SCHEMA).execute(pool).await?;
+
+    let qry = format!("SELECT id, rule, table_name, column_name, comparator, threshold, started_at, cleared_at, peak, latitude, longitude
+        FROM alert_events {clauses}");
+    let events = sqlx::query(&qry).bind(MAX_ALERT_EVENTS).fetch_all(pool).await?;
+
+    Ok(events.iter().map(|row| AlertEvent {
+        id: row.get(0),
+        rule: row.get(1),
+        source: ColumnRef { table: row.get(2), column: row.get(3) },
+        comparator: Comparator::ALL
+            .into_iter()
+            .find(|c| c.as_str() == row.get::<&str, usize>(4))
+            .unwrap_or_default(),
+        threshold: row.get(5),
+        started_at: row.get(6),
+        cleared_at: row.get(7),
+        peak: row.get(8),
+        position: match (row.get(9), row.get(10)) {
+            (Some(lat), Some(lon)) => Some([lat, lon]),
+            _ => None,
+        },
+    }).collect())
+}
+
+/// Stores an annotation made at the unix time `time`, placed at the nearest valid GPS fix within `MAX_FIX_GAP`
+pub async fn add_annotation(annotation: &NewAnnotation, time: f64) -> Result<Annotation, Box<dyn std::error::Error>> {
+    let pool = SqlitePool::connect(SQLITE_DATABASE_PATH.as_str()).await?;
+    sqlx::query(ANNOTATIONS_SCHEMA).execute(&pool).await?;
+
+    // annotations are still kept when there's no GPS data to place them with
+    let position = fix_near(&pool, time).await.unwrap_or_else(|why| {
+        println!("couldn't place annotation: {}", why);
+        None
+    });
+
+    let result = sqlx::query("INSERT INTO annotations (time, text, latitude, longitude) VALUES (?, ?, ?, ?)")
+        .bind(time)
+        .bind(&annotation.text)
+        .bind(position.map(|[lat, _]| lat))
+        .bind(position.map(|[_, lon]| lon))
+        .execute(&pool)
+        .await?;
+
+    Ok(Annotation {
+        id: result.last_insert_rowid(),
+        time,
+        text: annotation.text.clone(),
+        position,
+    })
+}
+
+/// Position of the valid GPS fix nearest to the unix time `time`, if one is within `MAX_FIX_GAP`
+async fn fix_near(pool: &SqlitePool, time: f64) -> Result<Option<LatLon>, Box<dyn std::error::Error>> {
+    let fix_time = plottable_time(pool, "latitude", "gps_data").await?;
+    let qry = format!("SELECT latitude, longitude FROM (
+            SELECT {fix_time} AS t, latitude, longitude FROM gps_data WHERE {valid_fix}
+        ) WHERE t BETWEEN ? AND ? ORDER BY ABS(t - ?) LIMIT 1", valid_fix = *VALID_FIX);
+    let fix = sqlx::query(&qry)
+        .bind(time - MAX_FIX_GAP)
+        .bind(time + MAX_FIX_GAP)
+        .bind(time)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(fix.map(|row| [row.get(0), row.get(1)]))
+}
+
+/// Gets the latest annotations, oldest first
+pub async fn annotations() -> Result<Vec<Annotation>, Box<dyn std::error::Error>> {
+    let pool = SqlitePool::connect(SQLITE_DATABASE_PATH.as_str()).await?;
+    sqlx::query(ANNOTATIONS_SCHEMA).execute(&pool).await?;
+
+    let qry: &str = "SELECT id, time, text, latitude, longitude FROM (
+            SELECT * FROM annotations ORDER BY time DESC LIMIT ?
+        ) ORDER BY time";
+    let annotations = sqlx::query(qry).bind(MAX_ANNOTATIONS).fetch_all(&pool).await?;
+
+    Ok(annotations.iter().map(|row| {
+        let latitude: Option<f64> = row.get(3);
+        let longitude: Option<f64> = row.get(4);
+        Annotation {
+            id: row.get(0),
+            time: row.get(1),
+            text: row.get(2),
+            position: latitude.zip(longitude).map(|(lat, lon)| [lat, lon]),
+        }
+    }).collect())
+}
+
+/// Deletes an annotation
+///
+/// returns: whether there was an annotation with that id
+pub async fn delete_annotation(id: i64) -> Result<bool, Box<dyn std::error::Error>> {
+    let pool = SqlitePool::connect(SQLITE_DATABASE_PATH.as_str()).await?;
+    sqlx::query(ANNOTATIONS_SCHEMA).execute(&pool).await?;
+
+    let result = sqlx::query("DELETE FROM annotations WHERE id = ?").bind(id).execute(&pool).await?;
+    Ok(result.rows_affected() > 0)
+}
@@ -0,0 +1,103 @@
+use serde::Serialize;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counts the writes made, giving each its own temporary file
+static WRITES: AtomicU64 = AtomicU64::new(0);
+
+/// Writes `contents` next to `path` and renames it over the real file,
+/// so a crash never leaves half a file behind. Each write goes through its own
+/// temporary file, so concurrent writes of one path never mix their contents
+pub fn write_atomic(path: &Path, contents: &str) -> Result<(), String> {
+    let file_name = path.file_name()
+        .ok_or_else(|| format!("couldn't write to {}: it isn't a file", path.display()))?;
+    let tmp_path = path.with_file_name(format!("{}.{}.tmp", file_name.to_string_lossy(), WRITES.fetch_add(1, Ordering::Relaxed)));
+    let written = fs::File::create(&tmp_path)
+        .map_err(|why| format!("couldn't create {}: {why}", path.display()))
+        .and_then(|mut file| file.write_all(contents.as_bytes())
+            .and_then(|_| file.sync_all())
+            .map_err(|why| format!("couldn't write to {}: {why}", path.display())))
+        .and_then(|_| fs::rename(&tmp_path, path)
+            .map_err(|why| format!("couldn't replace {}: {why}", path.display())));
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    written
+}
+
+/// Appends `entry` to the JSON lines file at `path`, creating it if needed
+pub fn append_line<T: Serialize>(path: &Path, entry: &T) -> Result<(), String> {
+    let line = serde_json::to_string(entry)
+        .map_err(|why| format!("couldn't serialize an entry of {}: {why}", path.display()))?;
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|why| format!("couldn't open {}: {why}", path.display()))?;
+    writeln!(file, "{line}").map_err(|why| format!("couldn't write to {}: {why}", path.display()))
+}
+
+/// Reads every entry of the JSON lines file at `path`, oldest first, none if it doesn't exist yet
+pub fn read_lines(path: &Path) -> Result<Vec<serde_json::Value>, String> {
+    let content = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(why) => return Err(format!("couldn't read {}: {why}", path.display())),
+    };
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(|why| format!("couldn't parse {}: {why}", path.display())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn concurrent_writes_leave_one_whole_file() {
+        let dir = std::env::temp_dir().join(format!("pda_storage_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("settings.json");
+
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let path = path.clone();
+                thread::spawn(move || write_atomic(&path, &i.to_string().repeat(10_000)))
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap().unwrap();
+        }
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let first = contents.chars().next().unwrap();
+        assert_eq!(contents.len(), 10_000);
+        assert!(contents.chars().all(|c| c == first));
+        // every temporary file was renamed away
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn appended_lines_are_read_back_in_order() {
+        let dir = std::env::temp_dir().join(format!("pda_storage_lines_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("history.jsonl");
+
+        assert_eq!(read_lines(&path).unwrap(), Vec::<serde_json::Value>::new());
+        for entry in [1, 2, 3] {
+            append_line(&path, &serde_json::json!({ "entry": entry })).unwrap();
+        }
+        let entries: Vec<_> = read_lines(&path).unwrap().iter().map(|line| line["entry"].clone()).collect();
+        assert_eq!(entries, [1, 2, 3]);
+
+        fs::write(&path, "{\"entry\": 1}\nnot json\n").unwrap();
+        assert!(read_lines(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,77 @@
+use protocol::TileSet;
+use rocket::http::ContentType;
+use sqlx::{sqlite::SqlitePool, Row as _};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Tiles laid out as `<zoom>/<x>/<y>.png`, as written by the `seed_tiles` tool
+const TILES_DIR: &str = "tiles";
+/// Tiles packed into a single SQLite file, used for any tile missing from `TILES_DIR`
+const MBTILES_PATH: &str = "tiles.mbtiles";
+const EXTENSIONS: [&str; 3] = ["png", "jpg", "jpeg"];
+
+/// Zoom levels available locally, none if there are no local tiles at all
+pub async fn available() -> Result<Option<TileSet>, Box<dyn std::error::Error>> {
+    let mut zooms: Vec<u8> = match fs::read_dir(TILES_DIR) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+            .collect(),
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => vec![],
+        Err(why) => return Err(format!("couldn't read {TILES_DIR}: {why}").into()),
+    };
+    let pool = mbtiles().await?;
+    if let Some(pool) = pool {
+        let row = sqlx::query("SELECT MIN(zoom_level), MAX(zoom_level) FROM tiles").fetch_one(&pool).await?;
+        zooms.extend(row.get::<Option<u8>, usize>(0));
+        zooms.extend(row.get::<Option<u8>, usize>(1));
+    }
+
+    Ok(match (zooms.iter().min(), zooms.iter().max()) {
+        (Some(&min_zoom), Some(&max_zoom)) => Some(TileSet { min_zoom, max_zoom }),
+        _ => None,
+    })
+}
+
+/// Reads one tile in the usual XYZ scheme, from the tile directory or else the MBTiles file
+///
+/// returns: the image and its type, none if the tile isn't stored locally
+pub async fn load(zoom: u8, x: u32, y: u32) -> Result<Option<(ContentType, Vec<u8>)>, Box<dyn std::error::Error>> {
+    for extension in EXTENSIONS {
+        let path: PathBuf = [TILES_DIR, &zoom.to_string(), &x.to_string(), &format!("{y}.{extension}")].iter().collect();
+        match fs::read(&path) {
+            Ok(image) => return Ok(Some((content_type(&image), image))),
+            Err(why) if why.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(why) => return Err(format!("couldn't read {}: {why}", path.display()).into()),
+        }
+    }
+
+    let Some(pool) = mbtiles().await? else {
+        return Ok(None);
+    };
+    // MBTiles counts rows from the bottom of the map
+    let Some(row) = 1u32.checked_shl(zoom.into()).and_then(|rows| rows.checked_sub(y.checked_add(1)?)) else {
+        return Ok(None);
+    };
+    let image: Option<Vec<u8>> = sqlx::query("SELECT tile_data FROM tiles WHERE zoom_level = ? AND tile_column = ? AND tile_row = ?")
+        .bind(zoom)
+        .bind(x)
+        .bind(row)
+        .fetch_optional(&pool)
+        .await?
+        .map(|row| row.get(0));
+    Ok(image.map(|image| (content_type(&image), image)))
+}
+
+async fn mbtiles() -> Result<Option<SqlitePool>, Box<dyn std::error::Error>> {
+    if !Path::new(MBTILES_PATH).exists() {
+        return Ok(None);
+    }
+    Ok(Some(SqlitePool::connect(&format!("sqlite:{MBTILES_PATH}?mode=ro")).await?))
+}
+
+/// Tells PNG from JPEG by the image's first bytes
+fn content_type(image: &[u8]) -> ContentType {
+    if image.starts_with(&[0xFF, 0xD8]) { ContentType::JPEG } else { ContentType::PNG }
+}
@@ -0,0 +1,149 @@
+use protocol::{distance, TrackPoint, TripSummary};
+use crate::sql_parsing;
+
+/// Speed below which the receiver counts as stopped, in metres per second, so a stationary fix wandering isn't moving
+const MIN_MOVING_SPEED: f64 = 0.5;
+/// Gap between fixes past which the time between them isn't counted as moving, in seconds
+const MAX_MOVING_GAP: f64 = 30.0;
+/// Change in altitude ignored as noise when adding up elevation gain and loss, in metres
+const ELEVATION_NOISE: f64 = 3.0;
+
+/// Summarises the trip between the unix times `from` and `to`, or the current session's,
+/// none if there are no valid fixes
+pub async fn summary(range: Option<(f64, f64)>) -> Result<Option<TripSummary>, Box<dyn std::error::Error>> {
+    Ok(summarize(&sql_parsing::track_points(range).await?))
+}
+
+/// Totals of the trip made of `points`, oldest first
+fn summarize(points: &[TrackPoint]) -> Option<TripSummary> {
+    let (first, last) = (points.first()?, points.last()?);
+
+    let mut total_distance = 0.0;
+    let mut moving_time = 0.0;
+    let mut moving_speed = 0.0; // speed over ground times the seconds it was held for
+    for pair in points.windows(2) {
+        let (from, to) = (&pair[0], &pair[1]);
+        let metres = distance(from.position, to.position);
+        let seconds = to.time - from.time;
+        total_distance += metres;
+        if seconds > 0.0 && seconds <= MAX_MOVING_GAP && metres / seconds >= MIN_MOVING_SPEED {
+            moving_time += seconds;
+            moving_speed += (from.speed + to.speed) / 2.0 * seconds;
+        }
+    }
+
+    // altitude is only counted once it has moved further than its noise from where it was last counted
+    let (mut elevation_gain, mut elevation_loss) = (0.0, 0.0);
+    let mut reference = first.altitude;
+    for point in points {
+        let change = point.altitude - reference;
+        if change.abs() >= ELEVATION_NOISE {
+            if change > 0.0 {
+                elevation_gain += change;
+            } else {
+                elevation_loss -= change;
+            }
+            reference = point.altitude;
+        }
+    }
+
+    let [lat, lon] = first.position;
+    let bounds = points.iter().fold([[lat, lon], [lat, lon]], |[[s, w], [n, e]], point| {
+        let [lat, lon] = point.position;
+        [[s.min(lat), w.min(lon)], [n.max(lat), e.max(lon)]]
+    });
+
+    Some(TripSummary {
+        start: first.time,
+        end: last.time,
+        distance: total_distance,
+        moving_time,
+        max_speed: points.iter().map(|point| point.speed).fold(0.0, f64::max),
+        average_speed: if moving_time > 0.0 { moving_speed / moving_time } else { 0.0 },
+        elevation_gain,
+        elevation_loss,
+        bounds,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Metres between fixes a thousandth of a degree of longitude apart on the equator
+    const STEP: f64 = 111.19492664455875;
+
+    fn point(time: f64, lon: f64, speed: f64, altitude: f64) -> TrackPoint {
+        TrackPoint { time, position: [0.0, lon], speed, altitude }
+    }
+
+    fn assert_near(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "{} isn't {}", actual, expected);
+    }
+
+    #[test]
+    fn no_fixes_have_no_summary() {
+        assert_eq!(summarize(&[]), None);
+    }
+
+    #[test]
+    fn single_fix_is_a_trip_going_nowhere() {
+        let summary = summarize(&[point(100.0, 0.5, 3.0, 20.0)]).unwrap();
+        assert_eq!((summary.start, summary.end), (100.0, 100.0));
+        assert_eq!(summary.distance, 0.0);
+        assert_eq!(summary.moving_time, 0.0);
+        assert_eq!(summary.average_speed, 0.0);
+        assert_eq!(summary.max_speed, 3.0);
+        assert_eq!((summary.elevation_gain, summary.elevation_loss), (0.0, 0.0));
+        assert_eq!(summary.bounds, [[0.0, 0.5], [0.0, 0.5]]);
+    }
+
+    #[test]
+    fn standing_still_isnt_moving() {
+        let points: Vec<TrackPoint> = (0..10).map(|i| point(i as f64, 0.0, 0.0, 20.0)).collect();
+        let summary = summarize(&points).unwrap();
+        assert_eq!(summary.distance, 0.0);
+        assert_eq!(summary.moving_time, 0.0);
+        assert_eq!(summary.average_speed, 0.0);
+        assert_eq!(summary.end - summary.start, 9.0);
+    }
+
+    #[test]
+    fn steady_track() {
+        let points: Vec<TrackPoint> = (0..5).map(|i| point(10.0 * i as f64, 0.001 * i as f64, 20.0, 50.0)).collect();
+        let summary = summarize(&points).unwrap();
+        assert_near(summary.distance, 4.0 * STEP);
+        assert_eq!(summary.moving_time, 40.0);
+        assert_eq!(summary.average_speed, 20.0);
+        assert_eq!(summary.bounds, [[0.0, 0.0], [0.0, 0.004]]);
+    }
+
+    #[test]
+    fn gap_adds_distance_but_not_moving_time() {
+        let points = [
+            point(0.0, 0.000, 20.0, 0.0),
+            point(10.0, 0.001, 20.0, 0.0),
+            // the receiver lost its fix for two minutes, then carried on
+            point(130.0, 0.003, 10.0, 0.0),
+            point(140.0, 0.004, 10.0, 0.0),
+        ];
+        let summary = summarize(&points).unwrap();
+        assert_near(summary.distance, 4.0 * STEP);
+        assert_eq!(summary.moving_time, 20.0);
+        assert_eq!(summary.average_speed, 15.0);
+        assert_eq!(summary.max_speed, 20.0);
+        assert_eq!(summary.end - summary.start, 140.0);
+    }
+
+    #[test]
+    fn elevation_ignores_noise() {
+        let altitudes = [100.0, 101.0, 99.0, 102.0, 110.0, 108.5, 104.0, 104.5];
+        let points: Vec<TrackPoint> = altitudes.iter().enumerate()
+            .map(|(i, &altitude)| point(10.0 * i as f64, 0.001 * i as f64, 20.0, altitude))
+            .collect();
+        let summary = summarize(&points).unwrap();
+        // counted from 100 up to 110, then down to 104, the wobbles under 3 metres aside
+        assert_near(summary.elevation_gain, 10.0);
+        assert_near(summary.elevation_loss, 6.0);
+    }
+}
@@ -0,0 +1,164 @@
+use egui::*;
+use protocol::{geofence_schema, validate_rules, AlertEvent, AlertRule, ColumnRef, Comparator, Geofence, Quantity, TableSchema, Units, GEOFENCE_TABLE};
+use crate::{api::{self, ApiError}, dashboard::source_controls, utils::{error_banner, format_time, spawn_repainting, PollableValue}};
+
+/// Editor for the alert rules evaluated by the server, shown on the config panel
+pub struct AlertEditor {
+    rules: Vec<AlertRule>,
+    /// rules being loaded or saved, replacing the edited ones once ready
+    pending: Option<poll_promise::Promise<Result<Vec<AlertRule>, ApiError>>>,
+    schema: PollableValue<Vec<TableSchema>>,
+    /// geofences, which rules can watch as well as the sensor tables
+    geofences: PollableValue<Vec<Geofence>>,
+    error: Option<String>,
+}
+
+impl AlertEditor {
+    pub fn new(ctx: &Context) -> Self {
+        Self {
+            rules: vec![],
+            pending: Some(spawn_repainting(ctx, api::alert_rules())),
+            schema: PollableValue::new(ctx, api::schema),
+            geofences: PollableValue::new(ctx, api::geofences),
+            error: None,
+        }
+    }
+
+    /// Thresholds and hysteresis are edited in `units`, and stored in the units the server keeps the data in
+    pub fn ui(&mut self, ui: &mut Ui, units: &Units) {
+        if let Some(result) = self.pending.as_ref().and_then(|p| p.ready()) {
+            match result {
+                Ok(rules) => {
+                    self.rules = rules.clone();
+                    self.error = None;
+                },
+                Err(why) => self.error = Some(why.to_string()),
+            }
+            self.pending = None;
+        }
+        let mut schema = self.schema.poll().unwrap_or_default();
+        schema.push(geofence_schema(&self.geofences.poll().unwrap_or_default()));
+
+        if let Some(why) = &self.error {
+            error_banner(ui, &format!("Alerts: {}", why));
+        }
+        if let Some(why) = self.schema.error() {
+            error_banner(ui, &format!("Sensor tables: {}", why));
+        }
+        if let Some(why) = self.geofences.error() {
+            error_banner(ui, &format!("Geofences: {}", why));
+        }
+
+        let mut removed = None;
+        for (i, rule) in self.rules.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.add(TextEdit::singleline(&mut rule.name).desired_width(120.0));
+                source_controls(ui, &schema, &mut rule.source, ("alert", i));
+                let geofence = rule.source.table == GEOFENCE_TABLE;
+                if geofence {
+                    // a geofence reads 1 while the GPS is inside it and 0 while outside
+                    rule.threshold = 0.5;
+                    rule.hysteresis = 0.0;
+                }
+                let condition = |comparator: Comparator| if geofence { inside_or_outside(comparator) } else { comparator.as_str() };
+                ComboBox::from_id_source(("alert_comparator", i))
+                    .selected_text(condition(rule.comparator))
+                    .show_ui(ui, |ui| {
+                        for comparator in Comparator::ALL {
+                            ui.selectable_value(&mut rule.comparator, comparator, condition(comparator));
+                        }
+                    });
+                let quantity = Quantity::of(&rule.source);
+                let suffix = units.suffix(&rule.source);
+                if !geofence {
+                    let mut threshold = quantity.map_or(rule.threshold, |quantity| units.convert(quantity, rule.threshold));
+                    if ui.add(DragValue::new(&mut threshold).speed(0.1).suffix(&suffix)).changed() {
+                        rule.threshold = quantity.map_or(threshold, |quantity| units.to_stored(quantity, threshold));
+                    }
+                }
+                ui.label("for");
+                ui.add(DragValue::new(&mut rule.duration).speed(0.1).clamp_range(0.0..=f64::MAX).suffix(" s"));
+                if !geofence {
+                    ui.label("hysteresis");
+                    let mut hysteresis = quantity.map_or(rule.hysteresis, |quantity| units.convert_difference(quantity, rule.hysteresis));
+                    if ui.add(DragValue::new(&mut hysteresis).speed(0.1).clamp_range(0.0..=f64::MAX).suffix(&suffix)).changed() {
+                        rule.hysteresis = quantity.map_or(hysteresis, |quantity| units.difference_to_stored(quantity, hysteresis));
+                    }
+                }
+                if ui.button("🗑").clicked() {
+                    removed = Some(i);
+                }
+            });
+        }
+        if let Some(i) = removed {
+            self.rules.remove(i);
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("➕ Add alert").clicked() {
+                if let Some(rule) = new_rule(&schema, self.rules.len()) {
+                    self.rules.push(rule);
+                }
+            }
+            if ui.add_enabled(self.pending.is_none(), Button::new("Save alerts")).clicked() {
+                match validate_rules(&self.rules, &schema) {
+                    Ok(()) => {
+                        self.pending = Some(spawn_repainting(ui.ctx(), api::update_alert_rules(self.rules.clone())));
+                    },
+                    Err(why) => self.error = Some(why),
+                }
+            }
+        });
+    }
+
+    /// Requests the rules again, after the server address changed
+    pub fn reload(&mut self, ctx: &Context) {
+        self.pending = Some(spawn_repainting(ctx, api::alert_rules()));
+        self.schema.refresh();
+        self.geofences.refresh();
+    }
+}
+
+/// A rule watching the first column available, for adding to the list
+fn new_rule(schema: &[TableSchema], count: usize) -> Option<AlertRule> {
+    let table = schema.first()?;
+    let column = table.columns.first()?;
+    Some(AlertRule {
+        name: format!("Alert {}", count + 1),
+        source: ColumnRef { table: table.name.clone(), column: column.clone() },
+        comparator: Comparator::Above,
+        threshold: 0.0,
+        duration: 0.0,
+        hysteresis: 0.0,
+    })
+}
+
+/// How a rule watching a geofence reads, as it's raised above 0.5 while inside and below while outside
+fn inside_or_outside(comparator: Comparator) -> &'static str {
+    match comparator {
+        Comparator::Above => "inside",
+        Comparator::Below => "outside",
+    }
+}
+
+/// Shows a banner for every alert which hasn't cleared yet, with its threshold and peak in `units`
+pub fn active_alerts(ui: &mut Ui, events: &[AlertEvent], units: &Units) {
+    for event in events.iter().filter(|event| event.is_active()) {
+        let since = format_time(event.started_at * 1000.0);
+        let text = if event.source.table == GEOFENCE_TABLE {
+            format!("⚠ {}: {} {} since {}", event.rule, inside_or_outside(event.comparator), event.source.column, since)
+        } else {
+            let unit = units.suffix(&event.source);
+            format!("⚠ {}: {} {} {:.2}{} since {}, peak {:.2}{}",
+                event.rule, event.source.column, event.comparator.as_str(), units.convert_column(&event.source, event.threshold),
+                unit, since, units.convert_column(&event.source, event.peak), unit)
+        };
+        Frame::none()
+            .fill(ui.visuals().error_fg_color)
+            .inner_margin(6.0)
+            .show(ui, |ui| {
+                ui.set_width(ui.available_width());
+                ui.colored_label(ui.visuals().extreme_bg_color, RichText::new(text).strong());
+            });
+    }
+}
@@ -0,0 +1,315 @@
+//! Client for every endpoint on the server, with one typed function per endpoint.
+//!
+//! By default the server is assumed to be the one which served the page, this can be
+//! overridden from the config panel and is remembered in the browser's local storage.
+//!
+//! Identical GET requests in flight at the same time share a single request. A request is
+//! cancelled once every future waiting on it has been dropped, see `PollableValue::refresh`.
+
+use futures::future::{FutureExt, LocalBoxFuture, Shared, WeakShared};
+use log::debug;
+use protocol::{routes, AccelerometerCapture, AlertEvent, AlertRule, Annotation, Calibration, CalibrationRevision, Config, Dashboard, DataTable, Fix, Geofence, GpsStatus, NewAnnotation, PlotPoint, RecordingUpdate, Row, SensorCapabilities, SettingStatus, TableSchema, TileSet, TrackPoint, TripSummary};
+use serde::{de::DeserializeOwned, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::rc::Rc;
+use std::sync::Mutex;
+use crate::utils::{load_value, save_value};
+
+const FALLBACK_URL: &str = "http://127.0.0.1:8000";
+const STORAGE_KEY: &str = "pda_server_url";
+
+static SERVER_URL_OVERRIDE: Mutex<Option<String>> = Mutex::new(None);
+static CONNECTION: Mutex<Connection> = Mutex::new(Connection::Unknown);
+
+type SharedRequest = Shared<LocalBoxFuture<'static, Result<Rc<str>, ApiError>>>;
+
+thread_local! {
+    /// GET requests currently waiting on the server, by url
+    static IN_FLIGHT: RefCell<HashMap<String, WeakShared<LocalBoxFuture<'static, Result<Rc<str>, ApiError>>>>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Why a request to the server failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiError {
+    /// The server couldn't be reached
+    Network(String),
+    /// The server answered with an error status and message
+    Status { code: u16, message: String },
+    /// The response didn't have the expected shape
+    Decode(String),
+    /// The request was superseded before it completed
+    Cancelled,
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Network(why) => write!(f, "couldn't reach server: {}", why),
+            ApiError::Status { code, message } => write!(f, "server responded {}: {}", code, message),
+            ApiError::Decode(why) => write!(f, "unexpected response: {}", why),
+            ApiError::Cancelled => write!(f, "request cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// Whether the server answered the most recent request
+#[derive(Debug, Clone, PartialEq)]
+pub enum Connection {
+    Unknown,
+    Connected,
+    Disconnected(String),
+}
+
+/// Loads a previously saved server address, call once on startup
+pub fn init() {
+    *SERVER_URL_OVERRIDE.lock().unwrap() = load_value(STORAGE_KEY);
+}
+
+/// Address of the server every request is sent to, without a trailing slash
+pub fn base_url() -> String {
+    if let Some(url) = SERVER_URL_OVERRIDE.lock().unwrap().clone() {
+        return url;
+    }
+    page_origin().unwrap_or_else(|| FALLBACK_URL.to_string())
+}
+
+/// Whether the server address was set by the user rather than taken from the page
+pub fn is_overridden() -> bool {
+    SERVER_URL_OVERRIDE.lock().unwrap().is_some()
+}
+
+/// Sends all further requests to `url`, or back to the page origin if `None`
+///
+/// returns: an error if `url` isn't an http(s) address
+pub fn set_base_url(url: Option<&str>) -> Result<(), String> {
+    let url = match url {
+        Some(u) => Some(normalize(u)?),
+        None => None,
+    };
+    save_value(STORAGE_KEY, url.as_deref());
+    *SERVER_URL_OVERRIDE.lock().unwrap() = url;
+    Ok(())
+}
+
+pub fn settings() -> impl Future<Output = Result<Config, ApiError>> {
+    get(routes::SETTINGS.to_string())
+}
+
+pub fn settings_status() -> impl Future<Output = Result<Vec<SettingStatus>, ApiError>> {
+    get(routes::SETTINGS_STATUS.to_string())
+}
+
+pub fn capabilities() -> impl Future<Output = Result<Vec<SensorCapabilities>, ApiError>> {
+    get(routes::CAPABILITIES.to_string())
+}
+
+/// returns: the config as stored by the server
+pub fn update_settings(config: Config) -> impl Future<Output = Result<Config, ApiError>> {
+    post(routes::UPDATE_SETTINGS.to_string(), config)
+}
+
+pub fn calibration() -> impl Future<Output = Result<Calibration, ApiError>> {
+    get(routes::CALIBRATION.to_string())
+}
+
+pub fn calibration_history() -> impl Future<Output = Result<Vec<CalibrationRevision>, ApiError>> {
+    get(routes::CALIBRATION_HISTORY.to_string())
+}
+
+/// Raw accelerometer readings of the last `seconds` averaged together, none if nothing has been recorded
+pub fn calibration_capture(seconds: f64) -> impl Future<Output = Result<Option<AccelerometerCapture>, ApiError>> {
+    get(routes::calibration_capture(seconds))
+}
+
+/// returns: the calibration as stored by the server, as a new version if it changed
+pub fn update_calibration(calibration: Calibration) -> impl Future<Output = Result<Calibration, ApiError>> {
+    post(routes::UPDATE_CALIBRATION.to_string(), calibration)
+}
+
+pub fn update(recording: RecordingUpdate) -> impl Future<Output = Result<RecordingUpdate, ApiError>> {
+    post(routes::UPDATE.to_string(), recording)
+}
+
+pub fn data_latest(column: &str, table: &str) -> impl Future<Output = Result<Vec<PlotPoint>, ApiError>> {
+    get(routes::data_latest(column, table))
+}
+
+pub fn data_range(column: &str, table: &str, from: f64, to: f64, points: usize) -> impl Future<Output = Result<Vec<PlotPoint>, ApiError>> {
+    get(routes::data_range(column, table, from, to, points))
+}
+
+pub fn tile_set() -> impl Future<Output = Result<Option<TileSet>, ApiError>> {
+    get(routes::TILES.to_string())
+}
+
+pub fn gps_fix() -> impl Future<Output = Result<Option<Fix>, ApiError>> {
+    get(routes::GPS_FIX.to_string())
+}
+
+pub fn gps_status() -> impl Future<Output = Result<Option<GpsStatus>, ApiError>> {
+    get(routes::GPS_STATUS.to_string())
+}
+
+/// The GPS track between the unix times `from` and `to`, or the current session's without a range
+pub fn gps_track(range: Option<[f64; 2]>) -> impl Future<Output = Result<Vec<TrackPoint>, ApiError>> {
+    get(match range {
+        Some([from, to]) => routes::gps_track_range(from, to),
+        None => routes::GPS_TRACK.to_string(),
+    })
+}
+
+/// Trip summary between the unix times `from` and `to`, or of the current session without a range
+pub fn trip_summary(range: Option<[f64; 2]>) -> impl Future<Output = Result<Option<TripSummary>, ApiError>> {
+    get(match range {
+        Some([from, to]) => routes::trip_summary_range(from, to),
+        None => routes::TRIP_SUMMARY.to_string(),
+    })
+}
+
+pub fn data_full(table: DataTable) -> impl Future<Output = Result<Vec<Row>, ApiError>> {
+    get(routes::data_full(table))
+}
+
+pub fn schema() -> impl Future<Output = Result<Vec<TableSchema>, ApiError>> {
+    get(routes::SCHEMA.to_string())
+}
+
+pub fn dashboards(user: &str) -> impl Future<Output = Result<Vec<String>, ApiError>> {
+    get(routes::dashboards(user))
+}
+
+pub fn dashboard(user: &str, name: &str) -> impl Future<Output = Result<Dashboard, ApiError>> {
+    get(routes::dashboard(user, name))
+}
+
+/// returns: the dashboard as stored by the server
+pub fn save_dashboard(user: &str, dashboard: Dashboard) -> impl Future<Output = Result<Dashboard, ApiError>> {
+    post(routes::update_dashboard(user), dashboard)
+}
+
+pub fn delete_dashboard(user: &str, name: &str) -> impl Future<Output = Result<(), ApiError>> {
+    delete(routes::dashboard(user, name))
+}
+
+pub fn alert_rules() -> impl Future<Output = Result<Vec<AlertRule>, ApiError>> {
+    get(routes::ALERT_RULES.to_string())
+}
+
+pub fn update_alert_rules(rules: Vec<AlertRule>) -> impl Future<Output = Result<Vec<AlertRule>, ApiError>> {
+    post(routes::UPDATE_ALERT_RULES.to_string(), rules)
+}
+
+pub fn alert_events() -> impl Future<Output = Result<Vec<AlertEvent>, ApiError>> {
+    get(routes::ALERT_EVENTS.to_string())
+}
+
+pub fn geofences() -> impl Future<Output = Result<Vec<Geofence>, ApiError>> {
+    get(routes::GEOFENCES.to_string())
+}
+
+/// Replaces every geofence on the server
+///
+/// returns: the geofences as stored by the server
+pub fn update_geofences(geofences: Vec<Geofence>) -> impl Future<Output = Result<Vec<Geofence>, ApiError>> {
+    post(routes::UPDATE_GEOFENCES.to_string(), geofences)
+}
+
+pub fn annotations() -> impl Future<Output = Result<Vec<Annotation>, ApiError>> {
+    get(routes::ANNOTATIONS.to_string())
+}
+
+pub fn add_annotation(annotation: NewAnnotation) -> impl Future<Output = Result<Annotation, ApiError>> {
+    post(routes::UPDATE_ANNOTATION.to_string(), annotation)
+}
+
+/// State of the connection as of the most recent request
+pub fn connection() -> Connection {
+    CONNECTION.lock().unwrap().clone()
+}
+
+async fn get<T: DeserializeOwned>(path: String) -> Result<T, ApiError> {
+    let url = endpoint(&path);
+    let request = IN_FLIGHT.with(|in_flight| {
+        let mut in_flight = in_flight.borrow_mut();
+        if let Some(request) = in_flight.get(&url).and_then(WeakShared::upgrade) {
+            return request;
+        }
+        let request: SharedRequest = send_get(url.clone()).boxed_local().shared();
+        if let Some(weak) = request.downgrade() {
+            in_flight.insert(url.clone(), weak);
+        }
+        request
+    });
+    decode(&request.await?)
+}
+
+async fn send_get(url: String) -> Result<Rc<str>, ApiError> {
+    let client = reqwest_wasm::Client::new();
+    let result = send(client.get(&url)).await;
+    IN_FLIGHT.with(|in_flight| in_flight.borrow_mut().remove(&url));
+    result.map(Rc::from)
+}
+
+async fn post<B: Serialize, T: DeserializeOwned>(path: String, body: B) -> Result<T, ApiError> {
+    let client = reqwest_wasm::Client::new();
+    let body = send(client.post(endpoint(&path)).json(&body)).await?;
+    decode(&body)
+}
+
+async fn delete<T: DeserializeOwned>(path: String) -> Result<T, ApiError> {
+    let client = reqwest_wasm::Client::new();
+    let body = send(client.delete(endpoint(&path))).await?;
+    decode(&body)
+}
+
+/// Sends a request, recording whether the server could be reached
+async fn send(request: reqwest_wasm::RequestBuilder) -> Result<String, ApiError> {
+    let res = match request.send().await {
+        Err(why) => {
+            debug!("failed to send: {}", why);
+            let error = ApiError::Network(why.to_string());
+            *CONNECTION.lock().unwrap() = Connection::Disconnected(error.to_string());
+            return Err(error);
+        },
+        Ok(result) => {
+            *CONNECTION.lock().unwrap() = Connection::Connected;
+            result
+        },
+    };
+    let status = res.status();
+    let body = res.text().await.map_err(|why| ApiError::Network(why.to_string()))?;
+    if !status.is_success() {
+        debug!("server responded {}: {}", status, body);
+        return Err(ApiError::Status { code: status.as_u16(), message: body });
+    }
+    Ok(body)
+}
+
+fn decode<T: DeserializeOwned>(body: &str) -> Result<T, ApiError> {
+    serde_json::from_str(body).map_err(|why| {
+        debug!("failed to parse json: {}", why);
+        ApiError::Decode(why.to_string())
+    })
+}
+
+fn endpoint(path: &str) -> String {
+    format!("{}{}", base_url(), path)
+}
+
+fn normalize(url: &str) -> Result<String, String> {
+    let url = url.trim().trim_end_matches('/');
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        return Err(format!("\"{url}\" must start with http:// or https://"));
+    }
+    Ok(url.to_string())
+}
+
+fn page_origin() -> Option<String> {
+    web_sys::window()?.location().origin().ok()
+}
@@ -0,0 +1,298 @@
+use std::time::Duration;
+use egui::*;
+use poll_promise::Promise;
+use web_time::Instant;
+use protocol::{AccelerometerCalibration, AccelerometerCapture, Calibration, CalibrationRevision, Orientation, MAX_CURVE_POINTS};
+use crate::{api::{self, ApiError}, utils::{error_banner, format_time, spawn_repainting, PollableValue}};
+
+const AXES: [&str; 3] = ["X", "Y", "Z"];
+/// How long the accelerometer is held in each orientation, all of which is averaged into its capture
+const CAPTURE_TIME: Duration = Duration::from_secs(3);
+/// Standard deviation of a capture past which the accelerometer likely moved, in its raw units which are roughly m/s²
+const STEADY_SPREAD: f64 = 0.2;
+/// Age of the newest reading past which the accelerometer likely isn't recording
+const STALE_AFTER: f64 = 10.0; // seconds
+
+/// Editor for the calibration the server applies to the sensor values it serves, shown on the config panel
+pub struct CalibrationEditor {
+    calibration: Calibration,
+    /// calibration being loaded or saved, replacing the edited one once ready
+    pending: Option<Promise<Result<Calibration, ApiError>>>,
+    history: PollableValue<Vec<CalibrationRevision>>,
+    /// calibration as last loaded from or saved on the server
+    stored: Calibration,
+    wizard: Option<Wizard>,
+    error: Option<String>,
+}
+
+impl CalibrationEditor {
+    pub fn new(ctx: &Context) -> Self {
+        Self {
+            calibration: Calibration::default(),
+            pending: Some(spawn_repainting(ctx, api::calibration())),
+            history: PollableValue::new(ctx, api::calibration_history),
+            stored: Calibration::default(),
+            wizard: None,
+            error: None,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui) {
+        if let Some(result) = self.pending.as_ref().and_then(|p| p.ready()) {
+            match result {
+                Ok(calibration) => {
+                    self.calibration = calibration.clone();
+                    self.stored = calibration.clone();
+                    self.error = None;
+                    self.history.refresh();
+                },
+                Err(why) => self.error = Some(why.to_string()),
+            }
+            self.pending = None;
+        }
+        let history = self.history.poll().unwrap_or_default();
+
+        if let Some(why) = &self.error {
+            error_banner(ui, &format!("Calibration: {}", why));
+        }
+        if let Some(why) = self.history.error() {
+            error_banner(ui, &format!("Calibration history: {}", why));
+        }
+
+        ui.label(match self.calibration.version {
+            0 => "Not calibrated yet, values are served as recorded".to_string(),
+            version => format!("Version {}", version),
+        });
+
+        ui.horizontal(|ui| {
+            ui.strong("Accelerometer");
+            if ui.add_enabled(self.wizard.is_none(), Button::new("🧭 Calibration wizard")).clicked() {
+                self.wizard = Some(Wizard::new());
+            }
+        });
+        if let Some(wizard) = &mut self.wizard {
+            match wizard.ui(ui) {
+                Some(WizardAction::Save(accelerometer)) => {
+                    // only the accelerometer's calibration changes, whatever else is being edited
+                    let calibration = Calibration { accelerometer, ..self.stored.clone() };
+                    self.pending = Some(spawn_repainting(ui.ctx(), api::update_calibration(calibration)));
+                    self.wizard = None;
+                },
+                Some(WizardAction::Cancel) => self.wizard = None,
+                None => {},
+            }
+        }
+        let accelerometer = &mut self.calibration.accelerometer;
+        Grid::new("accelerometer_calibration").num_columns(4).show(ui, |ui| {
+            ui.label("");
+            for axis in AXES {
+                ui.label(axis);
+            }
+            ui.end_row();
+
+            ui.label("Offset");
+            for offset in &mut accelerometer.offset {
+                ui.add(DragValue::new(offset).speed(0.01));
+            }
+            ui.end_row();
+
+            ui.label("Scale");
+            for scale in &mut accelerometer.scale {
+                ui.add(DragValue::new(scale).speed(0.01));
+            }
+            ui.end_row();
+
+            for (axis, row) in AXES.iter().zip(&mut accelerometer.alignment) {
+                ui.label(format!("Alignment {}", axis));
+                for weight in row {
+                    ui.add(DragValue::new(weight).speed(0.01));
+                }
+                ui.end_row();
+            }
+        });
+
+        ui.strong("Temperature");
+        let temperature = &mut self.calibration.temperature;
+        ui.horizontal(|ui| {
+            ui.label("Offset");
+            ui.add(DragValue::new(&mut temperature.offset).speed(0.01));
+            ui.label("Scale");
+            ui.add(DragValue::new(&mut temperature.scale).speed(0.01));
+        });
+        let mut removed = None;
+        for (i, [reading, reference]) in temperature.curve.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label("Reading");
+                ui.add(DragValue::new(reading).speed(0.1));
+                ui.label("is");
+                ui.add(DragValue::new(reference).speed(0.1));
+                if ui.button("🗑").clicked() {
+                    removed = Some(i);
+                }
+            });
+        }
+        if let Some(i) = removed {
+            temperature.curve.remove(i);
+        }
+        if ui.add_enabled(temperature.curve.len() < MAX_CURVE_POINTS, Button::new("➕ Add curve point")).clicked() {
+            // continue past the last point, so the readings stay increasing
+            let next = temperature.curve.last().map_or([0.0, 0.0], |[reading, reference]| [reading + 10.0, reference + 10.0]);
+            temperature.curve.push(next);
+        }
+
+        ui.horizontal(|ui| {
+            if ui.add_enabled(self.pending.is_none(), Button::new("Save calibration")).clicked() {
+                match self.calibration.validate() {
+                    Ok(()) => {
+                        self.pending = Some(spawn_repainting(ui.ctx(), api::update_calibration(self.calibration.clone())));
+                    },
+                    Err(why) => self.error = Some(why),
+                }
+            }
+            if ui.button("Reset").on_hover_text("Serve values as recorded once saved").clicked() {
+                self.calibration = Calibration { version: self.calibration.version, ..Calibration::default() };
+            }
+            // an older version is edited like any other, and saved as a new version
+            ui.add_enabled_ui(!history.is_empty(), |ui| {
+                ui.menu_button("Previous versions", |ui| {
+                    for revision in history.iter().rev() {
+                        let text = format!("Version {}, replaced {}", revision.calibration.version, format_time(revision.replaced_at as f64 * 1000.0));
+                        if ui.button(text).clicked() {
+                            self.calibration = Calibration { version: self.calibration.version, ..revision.calibration.clone() };
+                            ui.close_menu();
+                        }
+                    }
+                });
+            });
+        });
+    }
+
+    /// Requests the calibration again, after the server address changed
+    pub fn reload(&mut self, ctx: &Context) {
+        self.pending = Some(spawn_repainting(ctx, api::calibration()));
+        self.history.refresh();
+        self.wizard = None;
+    }
+}
+
+enum WizardAction {
+    /// store the computed calibration as the accelerometer's active one
+    Save(AccelerometerCalibration),
+    Cancel,
+}
+
+/// Walks through holding the accelerometer in each of `Orientation::ALL`, averaging its readings in each,
+/// then computes its calibration from them
+struct Wizard {
+    /// readings of the orientations captured so far, in order
+    captures: Vec<AccelerometerCapture>,
+    /// when the accelerometer was put in the next orientation, which is captured once `CAPTURE_TIME` has passed
+    holding_since: Option<Instant>,
+    capturing: Option<Promise<Result<Option<AccelerometerCapture>, ApiError>>>,
+    error: Option<String>,
+}
+
+impl Wizard {
+    fn new() -> Self {
+        Self {
+            captures: vec![],
+            holding_since: None,
+            capturing: None,
+            error: None,
+        }
+    }
+
+    fn ui(&mut self, ui: &mut Ui) -> Option<WizardAction> {
+        if let Some(result) = self.capturing.as_ref().and_then(|p| p.ready()) {
+            match result {
+                Ok(Some(capture)) => {
+                    self.captures.push(capture.clone());
+                    self.error = None;
+                },
+                Ok(None) => self.error = Some("no accelerometer readings have been recorded".to_string()),
+                Err(why) => self.error = Some(why.to_string()),
+            }
+            self.capturing = None;
+        }
+        if let Some(since) = self.holding_since {
+            let elapsed = since.elapsed();
+            if elapsed >= CAPTURE_TIME {
+                self.holding_since = None;
+                self.capturing = Some(spawn_repainting(ui.ctx(), api::calibration_capture(CAPTURE_TIME.as_secs_f64())));
+            } else {
+                // keep the countdown moving
+                ui.ctx().request_repaint_after(Duration::from_millis(100));
+            }
+        }
+        let busy = self.holding_since.is_some() || self.capturing.is_some();
+
+        let mut action = None;
+        Frame::group(ui.style()).show(ui, |ui| {
+            if let Some(why) = &self.error {
+                ui.colored_label(ui.visuals().error_fg_color, why);
+            }
+            if let Some(capture) = self.captures.last() {
+                let orientation = Orientation::ALL[self.captures.len() - 1];
+                let [x, y, z] = capture.mean;
+                ui.label(format!("{}: averaged {} readings to {:.3}, {:.3}, {:.3}", orientation.as_str(), capture.samples, x, y, z));
+                let warn = |ui: &mut Ui, text: String| { ui.colored_label(ui.visuals().warn_fg_color, text); };
+                if capture.spread.iter().any(|spread| *spread > STEADY_SPREAD) {
+                    warn(ui, "The readings varied a lot, retake it holding the sensor still".to_string());
+                }
+                if !orientation.matches(capture.mean) {
+                    warn(ui, format!("The readings don't show gravity along the {}, check the sensor's orientation", orientation.as_str()));
+                }
+                if js_sys::Date::now() / 1000.0 - capture.end > STALE_AFTER {
+                    warn(ui, format!("The newest reading is from {}, is the accelerometer recording?", format_time(capture.end * 1000.0)));
+                }
+            }
+
+            match Orientation::ALL.get(self.captures.len()) {
+                Some(orientation) => {
+                    ui.strong(format!("Step {} of {}: hold the sensor still with its {}", self.captures.len() + 1, Orientation::ALL.len(), orientation.as_str()));
+                    ui.horizontal(|ui| {
+                        if ui.add_enabled(!busy, Button::new("Capture")).clicked() {
+                            self.holding_since = Some(Instant::now());
+                        }
+                        if let Some(since) = self.holding_since {
+                            let left = CAPTURE_TIME.saturating_sub(since.elapsed());
+                            ui.label(format!("hold still for {:.0} s", left.as_secs_f32().ceil()));
+                        } else if busy {
+                            ui.spinner();
+                        }
+                    });
+                },
+                None => {
+                    let mut means = [[0.0; 3]; 6];
+                    for (mean, capture) in means.iter_mut().zip(&self.captures) {
+                        *mean = capture.mean;
+                    }
+                    match AccelerometerCalibration::from_orientations(&means) {
+                        Ok(calibration) => {
+                            let [x, y, z] = calibration.offset;
+                            ui.label(format!("Offset {:.4}, {:.4}, {:.4}", x, y, z));
+                            let [x, y, z] = calibration.scale;
+                            ui.label(format!("Scale {:.4}, {:.4}, {:.4}", x, y, z));
+                            if ui.button("💾 Save as active calibration").clicked() {
+                                action = Some(WizardAction::Save(calibration));
+                            }
+                        },
+                        Err(why) => {
+                            ui.colored_label(ui.visuals().error_fg_color, why);
+                        },
+                    }
+                },
+            }
+
+            ui.horizontal(|ui| {
+                if ui.add_enabled(!busy && !self.captures.is_empty(), Button::new("↶ Retake previous")).clicked() {
+                    self.captures.pop();
+                }
+                if ui.button("✖ Cancel").clicked() {
+                    action = Some(WizardAction::Cancel);
+                }
+            });
+        });
+        action
+    }
+}
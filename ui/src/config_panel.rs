@@ -1,63 +1,83 @@
 use wasm_bindgen::prelude::*;
 use egui::*;
-use wasm_bindgen_futures::spawn_local;
-use log::debug;
-use serde::{Deserialize, Serialize};
+use protocol::{settings, AccelerationUnit, Config, CoordinateFormat, LengthSystem, SensorCapabilities, SettingDescription, SettingKind, SettingStatus, SettingValue, SpeedUnit, TemperatureUnit, REFRESH_TIME_RANGE};
+use std::time::Duration;
+use crate::{alerts::AlertEditor, api::{self, ApiError}, calibration::CalibrationEditor, utils::{error_banner, spawn_repainting, PollableValue}};
 
-#[wasm_bindgen]
-#[derive(Copy, Clone, Deserialize, Serialize)]
-pub struct Config {
-    pub temp_enabled: bool,
-    pub temp_sensitivity: f32,
-    pub accel_enabled: bool,
-    pub accel_sensitivity: f32,
-    pub refresh_time: f32, // seconds
-}
-
-impl Config {
-    pub fn new(
-        temp_enabled: bool, 
-        temp_sensitivity: f32, 
-        accel_enabled: bool, 
-        accel_sensitivity: f32,
-        refresh_time: f32) -> Self {
-            
-        Self {
-            temp_enabled,
-            temp_sensitivity,
-            accel_enabled,
-            accel_sensitivity,
-            refresh_time
-        }
-    }
-}
+const STATUS_REFRESH: Duration = Duration::from_secs(2);
 
 #[wasm_bindgen]
 pub struct ConfigPanel {
-    settings_promise: poll_promise::Promise<Option<String>>,
+    settings_promise: poll_promise::Promise<Result<Config, ApiError>>,
     config_received: bool,
+    settings_error: Option<String>,
+    #[wasm_bindgen(skip)]
     pub config: Config,
+    status: PollableValue<Vec<SettingStatus>>,
+    /// settings offered by each sensor, which the sensor panels are built from
+    capabilities: PollableValue<Vec<SensorCapabilities>>,
+    server_url: String,
+    server_url_error: Option<String>,
+    alerts: AlertEditor,
+    calibration: CalibrationEditor,
 }
 
-impl Default for ConfigPanel {
-    fn default() -> Self {
+impl ConfigPanel {
+    pub fn new(ctx: &Context) -> Self {
         Self {
-            settings_promise: poll_promise::Promise::spawn_local(async {
-                ConfigPanel::req_settings().await
-            }),
+            settings_promise: spawn_repainting(ctx, api::settings()),
             config_received: false,
-            config: Config::new(
-                true, 
-                8.0, 
-                true, 
-                8.0,
-                1.0),
+            settings_error: None,
+            config: Config::default(),
+            status: PollableValue::new(ctx, api::settings_status),
+            capabilities: PollableValue::new(ctx, api::capabilities),
+            server_url: api::base_url(),
+            server_url_error: None,
+            alerts: AlertEditor::new(ctx),
+            calibration: CalibrationEditor::new(ctx),
         }
     }
-}
 
-impl ConfigPanel {
     pub fn ui(&mut self, ui: &mut Ui) {
+        // keep asking the server whether the acquisition process has applied the settings
+        let status = self.status.poll_every(STATUS_REFRESH).unwrap_or_default();
+        let capabilities = self.capabilities.poll();
+        let described = capabilities.is_some();
+        let capabilities = capabilities.unwrap_or_default();
+
+        if let Some(why) = &self.settings_error {
+            error_banner(ui, &format!("Settings: {}", why));
+        }
+        if let Some(why) = self.status.error() {
+            error_banner(ui, &format!("Settings status: {}", why));
+        }
+        if let Some(why) = self.capabilities.error() {
+            error_banner(ui, &format!("Sensor capabilities: {}", why));
+        }
+
+        egui::TopBottomPanel::top("server_panel")
+            .show_inside(ui, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.heading("Server");
+                });
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut self.server_url)
+                           .hint_text(api::base_url()));
+                    if ui.button("Apply").clicked() {
+                        match api::set_base_url(Some(&self.server_url)) {
+                            Ok(()) => self.reload(ui.ctx()),
+                            Err(why) => self.server_url_error = Some(why),
+                        }
+                    }
+                    if ui.add_enabled(api::is_overridden(), egui::Button::new("Use page address")).clicked() {
+                        let _ = api::set_base_url(None);
+                        self.reload(ui.ctx());
+                    }
+                });
+                if let Some(why) = &self.server_url_error {
+                    ui.colored_label(Color32::RED, why);
+                }
+            });
         egui::TopBottomPanel::top("general_panel")
             .show_inside(ui, |ui| {
                 egui::ScrollArea::vertical().show(ui, |ui| {
@@ -67,105 +87,189 @@ impl ConfigPanel {
                     ui.horizontal(|ui| {
                         ui.add(egui::DragValue::
                                 new(&mut self.config.refresh_time).speed(0.1)
-                                .clamp_range(0..=120));
+                                .clamp_range(REFRESH_TIME_RANGE));
                         ui.add(egui::Label::new("Refresh Delay (seconds)"));
                     });
+                    ui.horizontal(|ui| {
+                        let selected = &mut self.config.coordinate_format;
+                        egui::ComboBox::from_id_source("coordinate_format")
+                            .selected_text(selected.as_str())
+                            .show_ui(ui, |ui| {
+                                for format in CoordinateFormat::ALL {
+                                    ui.selectable_value(selected, format, format.as_str());
+                                }
+                            });
+                        ui.add(egui::Label::new("Coordinate Format"));
+                    });
                 });
             });
-        egui::TopBottomPanel::top("temp_panel")
+        egui::TopBottomPanel::top("units_panel")
             .show_inside(ui, |ui| {
                 egui::ScrollArea::vertical().show(ui, |ui| {
                     ui.vertical_centered(|ui| {
-                        ui.heading("Temperature Sensor");
+                        ui.heading("Units");
+                    });
+                    let units = &mut self.config.units;
+                    unit_combo(ui, "Temperature", &mut units.temperature, &TemperatureUnit::ALL, TemperatureUnit::as_str);
+                    unit_combo(ui, "Acceleration", &mut units.acceleration, &AccelerationUnit::ALL, AccelerationUnit::as_str);
+                    unit_combo(ui, "Speed", &mut units.speed, &SpeedUnit::ALL, SpeedUnit::as_str);
+                    unit_combo(ui, "Distance and Height", &mut units.length, &LengthSystem::ALL, LengthSystem::as_str);
+                });
+            });
+        // a panel for each sensor, with whichever settings the server says it offers
+        for sensor in &capabilities {
+            egui::TopBottomPanel::top(Id::new(("sensor_panel", &sensor.name)))
+                .show_inside(ui, |ui| {
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        ui.vertical_centered(|ui| {
+                            ui.heading(&sensor.title);
+                        });
+                        let enabled = match &sensor.enabled_by {
+                            Some(toggle) => self.config.hardware_value(toggle, &capabilities) != Some(SettingValue::Toggle(false)),
+                            None => true,
+                        };
+                        for setting in &sensor.settings {
+                            let applies = enabled || sensor.enabled_by.as_ref() == Some(&setting.name);
+                            ui.add_enabled_ui(applies, |ui| {
+                                ui.horizontal(|ui| {
+                                    setting_control(ui, &mut self.config, setting, &capabilities);
+                                    status_label(ui, &status, &setting.name);
+                                });
+                            });
+                        }
                     });
-                    ui.checkbox(&mut self.config.temp_enabled, "Enabled");
-                    ui.add_enabled_ui(self.config.temp_enabled, |ui| {
+                });
+        }
+        // stored settings the sensors no longer offer are refused by the server until removed
+        let offered = |name: &String| settings(&capabilities).any(|setting| &setting.name == name);
+        let unoffered: Vec<String> = self.config.hardware.keys().filter(|name| !offered(name)).cloned().collect();
+        if described && !unoffered.is_empty() {
+            egui::TopBottomPanel::top("unoffered_panel")
+                .show_inside(ui, |ui| {
+                    ui.vertical_centered(|ui| {
+                        ui.heading("Settings No Sensor Offers");
+                    });
+                    for name in unoffered {
                         ui.horizontal(|ui| {
-                            ui.add(egui::DragValue::
-                                   new(&mut self.config.temp_sensitivity).speed(0.1)
-                                   .clamp_range(0..=120));
-                            ui.add(egui::Label::new("Sensitivity"));
+                            ui.colored_label(ui.visuals().warn_fg_color, format!("{} = {}", name, self.config.hardware[&name]));
+                            if ui.button("🗑").on_hover_text("Remove, so the settings can be saved").clicked() {
+                                self.config.hardware.remove(&name);
+                            }
                         });
+                    }
+                });
+        }
+        egui::TopBottomPanel::top("calibration_panel")
+            .show_inside(ui, |ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    ui.vertical_centered(|ui| {
+                        ui.heading("Calibration");
                     });
+                    self.calibration.ui(ui);
                 });
             });
-        egui::TopBottomPanel::top("accel_panel")
+        egui::TopBottomPanel::top("alerts_panel")
             .show_inside(ui, |ui| {
                 egui::ScrollArea::vertical().show(ui, |ui| {
                     ui.vertical_centered(|ui| {
-                        ui.heading("Accelerometer");
-                    });
-                    ui.checkbox(&mut self.config.accel_enabled, "Enabled");
-                    ui.add_enabled_ui(self.config.accel_enabled, |ui| {
-                        ui.horizontal(|ui| {
-                            ui.add(egui::DragValue::
-                                   new(&mut self.config.accel_sensitivity).speed(0.1)
-                                   .clamp_range(0..=120));
-                            ui.add(egui::Label::new("Sensitivity"));
-                        });
+                        ui.heading("Alerts");
                     });
+                    self.alerts.ui(ui, &self.config.units);
                 });
             });
         ui.horizontal(|ui| {
-            // this button will send config to server
+            // this button will send config to server, the stored config is polled back in below
             if ui.button("Save").clicked() {
-                let config = self.config.clone();
-                spawn_local(async move {
-                    ConfigPanel::send_settings_update(config).await;
-                });
+                self.settings_promise = spawn_repainting(ui.ctx(), api::update_settings(self.config.clone()));
+                self.config_received = false;
+                self.status.refresh();
             }
         });
         // Poll config until ready
         if !self.config_received {
             if let Some(result) = self.settings_promise.ready() {
-                if let Some(json) = result {
-                    self.config = serde_json::from_str(json.as_str()).unwrap();
-                    self.config_received = true;
-                } else {
-                    debug!("Result error")
+                match result {
+                    Ok(config) => {
+                        self.config = config.clone();
+                        self.settings_error = None;
+                    },
+                    Err(why) => self.settings_error = Some(why.to_string()),
                 }
+                self.config_received = true;
             }
         }
     }
 
-    /// Sends config to sever as JSON
-    async fn send_settings_update(config: Config) {
-        let client = reqwest_wasm::Client::new();
-        let res = match &serde_json::to_string(&config) {
-            Ok(j) => {
-                match client.post("http://127.0.0.1:8000/update/settings").json(j).send().await {
-                    Ok(r) => r.text().await,
-                    Err(e) => Err(e),
-                }
-            },
-            Err(e) => {
-                debug!("couldn't serialize: {:?}", e);
-                return;
-            },
-        };
-        debug!("res: {:?}", res);
+    /// Requests config and its status again, after the server address changed
+    fn reload(&mut self, ctx: &Context) {
+        self.server_url = api::base_url();
+        self.server_url_error = None;
+        self.settings_promise = spawn_repainting(ctx, api::settings());
+        self.config_received = false;
+        self.status.refresh();
+        self.capabilities.refresh();
+        self.alerts.reload(ctx);
+        self.calibration.reload(ctx);
     }
-    
-    /// Requests config from server, and pack it into a promise
-    async fn req_settings() -> Option<String> {
-        let client = reqwest_wasm::Client::new();
-        let res = match client.get("http://127.0.0.1:8000/req/settings").send().await {
-            Err(why) => {
-                debug!("failed to get: {}", why);
-                return None;
-            },
-            Ok(result) => {
-                result
-            },
-        };
-        return match res.json().await {
-            Err(why) => {
-                debug!("failed parse json: {}", why);
-                return None;
-            },
-            Ok(result) => {
-                Some(result)
-            }
+}
+
+/// Control editing `setting` in `config`, as fits its kind, followed by its label
+fn setting_control(ui: &mut Ui, config: &mut Config, setting: &SettingDescription, sensors: &[SensorCapabilities]) {
+    let Some(current) = config.hardware_value(&setting.name, sensors) else {
+        return;
+    };
+    let suffix = if setting.unit.is_empty() { String::new() } else { format!(" {}", setting.unit) };
+    let mut value = current.clone();
+    match (&setting.kind, &mut value) {
+        (SettingKind::Toggle, SettingValue::Toggle(on)) => {
+            ui.checkbox(on, "");
+        },
+        (SettingKind::Number { min, max, step }, SettingValue::Number(number)) => {
+            ui.add(egui::DragValue::new(number).speed(*step).clamp_range(*min..=*max).suffix(&suffix));
+        },
+        (SettingKind::Choice(options), SettingValue::Choice(choice)) => {
+            egui::ComboBox::from_id_source(("setting", &setting.name))
+                .selected_text(format!("{}{}", choice, suffix))
+                .show_ui(ui, |ui| {
+                    for option in options {
+                        ui.selectable_value(choice, option.clone(), format!("{}{}", option, suffix));
+                    }
+                });
+        },
+        // a stored value of another kind than described is replaced by the default
+        _ => value = setting.default.clone(),
+    }
+    ui.add(egui::Label::new(&setting.label));
+    if value != current {
+        config.hardware.insert(setting.name.clone(), value);
+    }
+}
+
+/// Shows whether the acquisition process has applied the setting called `name`
+fn status_label(ui: &mut Ui, status: &[SettingStatus], name: &str) {
+    if let Some(setting) = status.iter().find(|s| s.name == name) {
+        let applied_at = setting.applied_at.as_deref().unwrap_or("never");
+        if setting.is_applied() {
+            ui.colored_label(Color32::GREEN, "applied")
+                .on_hover_text(format!("applied at {}", applied_at));
+        } else {
+            ui.colored_label(Color32::YELLOW, "pending")
+                .on_hover_text(format!("requested {} at {}, last applied at {}",
+                    setting.value, setting.requested_at, applied_at));
         }
     }
-}
\ No newline at end of file
+}
+
+/// Combo box choosing the unit `label` is shown in
+fn unit_combo<T: Copy + PartialEq>(ui: &mut Ui, label: &str, selected: &mut T, options: &[T], name: fn(&T) -> &'static str) {
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_source(("unit", label))
+            .selected_text(name(selected))
+            .show_ui(ui, |ui| {
+                for option in options.iter().copied() {
+                    ui.selectable_value(selected, option, name(&option));
+                }
+            });
+        ui.add(egui::Label::new(label));
+    });
+}
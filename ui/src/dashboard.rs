@@ -0,0 +1,284 @@
+use egui::*;
+use protocol::{validate_name, ColumnRef, Dashboard, Series, TableSchema, Tile, YAxis, MAX_SERIES, TILE_HEIGHT_RANGE};
+use std::sync::Mutex;
+use crate::{api::{self, ApiError}, utils::{error_banner, load_value, save_value, spawn_repainting, PollableValue}};
+
+const USER_KEY: &str = "pda_dashboard_user";
+const LAYOUT_KEY: &str = "pda_dashboard_layout";
+const DEFAULT_USER: &str = "default";
+const NEW_TILE_HEIGHT: f32 = 200.0;
+
+/// Colours given to new series, in the order they're added to a graph
+const SERIES_COLORS: [[u8; 3]; 6] = [
+    [31, 119, 180],
+    [255, 127, 14],
+    [44, 160, 44],
+    [214, 39, 40],
+    [148, 103, 189],
+    [140, 86, 75],
+];
+
+/// Colour for the `index`th series of a graph
+pub fn series_color(index: usize) -> [u8; 3] {
+    SERIES_COLORS[index % SERIES_COLORS.len()]
+}
+
+/// User whose dashboards are listed, read when the list is fetched
+static DASHBOARD_USER: Mutex<String> = Mutex::new(String::new());
+
+/// Change to a tile requested from its edit controls
+pub enum TileAction {
+    MoveUp,
+    MoveDown,
+    Remove,
+}
+
+/// Toolbar and per-tile controls for editing the home panel's dashboard,
+/// and for saving and loading dashboards on the server
+pub struct DashboardEditor {
+    pub editing: bool,
+    user: String,
+    selected_layout: String,
+    layouts: PollableValue<Vec<String>>,
+    schema: PollableValue<Vec<TableSchema>>,
+    /// dashboard being loaded or saved, replaces the current one once ready
+    pending: Option<poll_promise::Promise<Result<Dashboard, ApiError>>>,
+    deleting: Option<poll_promise::Promise<Result<(), ApiError>>>,
+    error: Option<String>,
+}
+
+impl DashboardEditor {
+    pub fn new(ctx: &Context) -> Self {
+        let user = load_value(USER_KEY).unwrap_or_else(|| DEFAULT_USER.to_string());
+        *DASHBOARD_USER.lock().unwrap() = user.clone();
+
+        let selected_layout = load_value(LAYOUT_KEY).unwrap_or_default();
+        let pending = if selected_layout.is_empty() {
+            None
+        } else {
+            Some(spawn_repainting(ctx, api::dashboard(&user, &selected_layout)))
+        };
+
+        Self {
+            editing: false,
+            user,
+            selected_layout,
+            layouts: PollableValue::new(ctx, DashboardEditor::req_layouts),
+            schema: PollableValue::new(ctx, api::schema),
+            pending,
+            deleting: None,
+            error: None,
+        }
+    }
+
+    /// Shows the dashboard toolbar, replacing `dashboard` when a saved one is loaded
+    pub fn toolbar(&mut self, ui: &mut Ui, dashboard: &mut Dashboard) {
+        if let Some(result) = self.pending.as_ref().and_then(|p| p.ready()) {
+            match result {
+                Ok(loaded) => {
+                    *dashboard = loaded.clone();
+                    self.selected_layout = loaded.name.clone();
+                    save_value(LAYOUT_KEY, Some(&loaded.name));
+                    self.error = None;
+                    self.layouts.refresh();
+                },
+                // the remembered dashboard was deleted, so stop asking for it
+                Err(ApiError::Status { code: 404, .. }) => {
+                    self.error = Some(format!("There is no dashboard called \"{}\" any more", self.selected_layout));
+                    self.selected_layout.clear();
+                    save_value(LAYOUT_KEY, None);
+                    self.layouts.refresh();
+                },
+                Err(why) => self.error = Some(why.to_string()),
+            }
+            self.pending = None;
+        }
+        if let Some(result) = self.deleting.as_ref().and_then(|p| p.ready()) {
+            if let Err(why) = result {
+                self.error = Some(why.to_string());
+            }
+            self.deleting = None;
+            self.layouts.refresh();
+        }
+
+        let layouts = self.layouts.poll().unwrap_or_default();
+        self.schema.poll();
+
+        ui.horizontal(|ui| {
+            ui.toggle_value(&mut self.editing, "✏ Edit dashboard");
+            if !self.editing {
+                return;
+            }
+
+            ui.label("User");
+            let user_edit = ui.add(TextEdit::singleline(&mut self.user).desired_width(80.0));
+            if user_edit.lost_focus() {
+                match validate_name(&self.user) {
+                    Ok(()) => {
+                        *DASHBOARD_USER.lock().unwrap() = self.user.clone();
+                        save_value(USER_KEY, Some(&self.user));
+                        self.layouts.reset();
+                        self.error = None;
+                    },
+                    Err(why) => self.error = Some(why),
+                }
+            }
+
+            ComboBox::from_id_source("dashboard_layouts")
+                .selected_text(self.selected_layout.as_str())
+                .show_ui(ui, |ui| {
+                    for layout in &layouts {
+                        ui.selectable_value(&mut self.selected_layout, layout.clone(), layout);
+                    }
+                });
+            if ui.add_enabled(layouts.contains(&self.selected_layout), Button::new("Load")).clicked() {
+                self.pending = Some(spawn_repainting(ui.ctx(), api::dashboard(&self.user, &self.selected_layout)));
+            }
+
+            ui.label("Name");
+            ui.add(TextEdit::singleline(&mut dashboard.name).desired_width(80.0));
+            if ui.button("Save").clicked() {
+                match validate_name(&self.user).and(validate_name(&dashboard.name)) {
+                    Ok(()) => {
+                        self.pending = Some(spawn_repainting(ui.ctx(), api::save_dashboard(&self.user, dashboard.clone())));
+                    },
+                    Err(why) => self.error = Some(why),
+                }
+            }
+            if ui.add_enabled(layouts.contains(&dashboard.name), Button::new("Delete")).clicked() {
+                self.deleting = Some(spawn_repainting(ui.ctx(), api::delete_dashboard(&self.user, &dashboard.name)));
+                save_value(LAYOUT_KEY, None);
+                self.selected_layout.clear();
+            }
+        });
+
+        let error = self.error.clone().or_else(|| self.layouts.error().map(|e| e.to_string()));
+        if let Some(why) = error {
+            error_banner(ui, &format!("Dashboard: {}", why));
+        }
+        if self.editing {
+            if let Some(why) = self.schema.error() {
+                error_banner(ui, &format!("Sensor tables: {}", why));
+            }
+        }
+    }
+
+    /// Shows the edit controls for one tile and each of its series
+    ///
+    /// returns: a change to the tile's position in the dashboard, if one was requested
+    pub fn tile_controls(&mut self, ui: &mut Ui, tile: &mut Tile, index: usize, count: usize) -> Option<TileAction> {
+        let schema = self.schema.value.clone().unwrap_or_default();
+        let mut action = None;
+
+        ui.horizontal(|ui| {
+            ui.add(TextEdit::singleline(&mut tile.title).desired_width(140.0));
+            ui.add(DragValue::new(&mut tile.height).speed(1.0).clamp_range(TILE_HEIGHT_RANGE).suffix(" px"));
+
+            if ui.add_enabled(index > 0, Button::new("⬆")).clicked() {
+                action = Some(TileAction::MoveUp);
+            }
+            if ui.add_enabled(index + 1 < count, Button::new("⬇")).clicked() {
+                action = Some(TileAction::MoveDown);
+            }
+            if ui.button("🗑").clicked() {
+                action = Some(TileAction::Remove);
+            }
+        });
+
+        // a graph keeps at least one series
+        let removable = tile.series.len() > 1;
+        let mut removed = None;
+        for (i, series) in tile.series.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.add_space(16.0);
+                ui.color_edit_button_srgb(&mut series.color);
+                source_controls(ui, &schema, &mut series.source, ("series", index, i));
+                ComboBox::from_id_source(("series_axis", index, i))
+                    .selected_text(match series.axis {
+                        YAxis::Left => "left axis",
+                        YAxis::Right => "right axis",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut series.axis, YAxis::Left, "left axis");
+                        ui.selectable_value(&mut series.axis, YAxis::Right, "right axis");
+                    });
+                if ui.add_enabled(removable, Button::new("✖")).clicked() {
+                    removed = Some(i);
+                }
+            });
+        }
+        if let Some(i) = removed {
+            tile.series.remove(i);
+        }
+
+        ui.horizontal(|ui| {
+            ui.add_space(16.0);
+            let can_add = tile.series.len() < MAX_SERIES;
+            if ui.add_enabled(can_add, Button::new("➕ Add series")).clicked() {
+                if let Some(series) = self.new_series(tile.series.len()) {
+                    tile.series.push(series);
+                }
+            }
+        });
+
+        action
+    }
+
+    /// A tile plotting the first column available, for adding to the dashboard
+    pub fn new_tile(&self) -> Option<Tile> {
+        let series = self.new_series(0)?;
+        Some(Tile {
+            title: series.source.column.clone(),
+            series: vec![series],
+            height: NEW_TILE_HEIGHT,
+        })
+    }
+
+    /// A series plotting the first column available, coloured as the `index`th series of its graph
+    fn new_series(&self, index: usize) -> Option<Series> {
+        let schema = self.schema.value.as_ref()?;
+        let table = schema.first()?;
+        let column = table.columns.first()?;
+        Some(Series {
+            source: ColumnRef { table: table.name.clone(), column: column.clone() },
+            color: series_color(index),
+            axis: YAxis::Left,
+        })
+    }
+
+    /// Requests the names of the current user's dashboards
+    async fn req_layouts() -> Result<Vec<String>, ApiError> {
+        let user = DASHBOARD_USER.lock().unwrap().clone();
+        if user.is_empty() {
+            return Ok(vec![]);
+        }
+        api::dashboards(&user).await
+    }
+}
+
+/// Shows the table and column pickers for a column of the sensor tables
+pub fn source_controls(ui: &mut Ui, schema: &[TableSchema], source: &mut ColumnRef, id: impl std::hash::Hash + Copy) {
+    ComboBox::from_id_source(("source_table", id))
+        .selected_text(source.table.as_str())
+        .show_ui(ui, |ui| {
+            for table in schema {
+                if ui.selectable_label(source.table == table.name, &table.name).clicked() {
+                    *source = ColumnRef {
+                        table: table.name.clone(),
+                        column: table.columns.first().cloned().unwrap_or_default(),
+                    };
+                }
+            }
+        });
+    let columns = schema.iter()
+        .find(|table| table.name == source.table)
+        .map(|table| table.columns.clone())
+        .unwrap_or_default();
+    ComboBox::from_id_source(("source_column", id))
+        .selected_text(source.column.as_str())
+        .show_ui(ui, |ui| {
+            for column in columns {
+                ui.selectable_value(&mut source.column, column.clone(), column);
+            }
+        });
+}
@@ -0,0 +1,194 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+use egui::*;
+use poll_promise::Promise;
+use protocol::{distance, validate_geofences, Geofence, LatLon, Shape};
+use crate::{api::{self, ApiError}, line_drawing::{GeofenceShapes, MapClicks}, utils::{error_banner, spawn_repainting, PollableValue}};
+
+/// Geofences change rarely, so they are fetched less often than the data
+const GEOFENCES_REFRESH: Duration = Duration::from_secs(10);
+const GEOFENCE_COLOR: Color32 = Color32::from_rgb(200, 80, 160);
+const DRAFT_COLOR: Color32 = Color32::from_rgb(240, 120, 20);
+
+/// Kinds of shape a geofence can be drawn as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ShapeKind {
+    Polygon,
+    Circle,
+}
+
+impl ShapeKind {
+    const ALL: [ShapeKind; 2] = [ShapeKind::Polygon, ShapeKind::Circle];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            ShapeKind::Polygon => "Polygon",
+            ShapeKind::Circle => "Circle",
+        }
+    }
+}
+
+/// A geofence being drawn by clicking on the map
+struct Draft {
+    name: String,
+    kind: ShapeKind,
+    /// corners of a polygon, or the centre of a circle followed by a point on its edge
+    points: Vec<LatLon>,
+}
+
+impl Draft {
+    /// The geofence drawn so far, which may not have enough points to be valid yet
+    fn geofence(&self) -> Option<Geofence> {
+        let shape = match (self.kind, self.points.as_slice()) {
+            (_, []) => return None,
+            (ShapeKind::Polygon, corners) => Shape::Polygon(corners.to_vec()),
+            (ShapeKind::Circle, [center, ..]) => Shape::Circle {
+                center: *center,
+                radius: self.points.get(1).map_or(0.0, |edge| distance(*center, *edge)),
+            },
+        };
+        Some(Geofence { name: self.name.clone(), shape })
+    }
+}
+
+/// Draws geofences on the map and stores them on the server, which logs every time they're entered or left
+pub struct GeofenceEditor {
+    geofences: PollableValue<Vec<Geofence>>,
+    /// geofences being saved, fetched again once they're stored
+    pending: Option<Promise<Result<Vec<Geofence>, ApiError>>>,
+    error: Option<String>,
+    draft: Option<Draft>,
+    /// position last clicked on the map, taken by the draft
+    clicked: Rc<Cell<Option<LatLon>>>,
+}
+
+impl GeofenceEditor {
+    pub fn new(ctx: &Context) -> Self {
+        Self {
+            geofences: PollableValue::new(ctx, api::geofences),
+            pending: None,
+            error: None,
+            draft: None,
+            clicked: Rc::new(Cell::new(None)),
+        }
+    }
+
+    /// Shows the button to draw a geofence and the list of geofences to delete them from, for the map's toolbar
+    pub fn toolbar(&mut self, ui: &mut Ui) {
+        if let Some(result) = self.pending.as_ref().and_then(|p| p.ready()) {
+            match result {
+                Ok(_) => {
+                    self.error = None;
+                    self.draft = None;
+                    self.geofences.refresh();
+                },
+                Err(why) => self.error = Some(why.to_string()),
+            }
+            self.pending = None;
+        }
+        let geofences = self.geofences.poll_every(GEOFENCES_REFRESH).unwrap_or_default();
+
+        if ui.add_enabled(self.draft.is_none(), Button::new("✏ Geofence")).on_hover_text("Draw a geofence on the map").clicked() {
+            self.clicked.set(None);
+            self.draft = Some(Draft {
+                name: format!("Geofence {}", geofences.len() + 1),
+                kind: ShapeKind::Polygon,
+                points: vec![],
+            });
+        }
+        ui.add_enabled_ui(!geofences.is_empty(), |ui| {
+            ui.menu_button("Geofences", |ui| {
+                for (i, geofence) in geofences.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(&geofence.name);
+                        if ui.add_enabled(self.pending.is_none(), Button::new("🗑")).clicked() {
+                            let mut remaining = geofences.clone();
+                            remaining.remove(i);
+                            self.pending = Some(spawn_repainting(ui.ctx(), api::update_geofences(remaining)));
+                        }
+                    });
+                }
+            });
+        });
+    }
+
+    /// Shows the controls of the geofence being drawn, adding the point clicked on the map since the last frame
+    pub fn draft_controls(&mut self, ui: &mut Ui) {
+        if let Some(why) = &self.error {
+            error_banner(ui, &format!("Geofences: {}", why));
+        }
+        if let Some(why) = self.geofences.error() {
+            error_banner(ui, &format!("Geofences: {}", why));
+        }
+        let Some(draft) = &mut self.draft else {
+            return;
+        };
+        if let Some(point) = self.clicked.take() {
+            match draft.kind {
+                // further clicks on a circle move its edge
+                ShapeKind::Circle if draft.points.len() >= 2 => draft.points[1] = point,
+                _ => draft.points.push(point),
+            }
+        }
+
+        let saving = self.pending.is_some();
+        let mut finished = false;
+        let mut save = None;
+        ui.horizontal(|ui| {
+            ui.add(TextEdit::singleline(&mut draft.name).desired_width(120.0));
+            let kind = draft.kind;
+            ComboBox::from_id_source("geofence_kind")
+                .selected_text(draft.kind.as_str())
+                .show_ui(ui, |ui| {
+                    for kind in ShapeKind::ALL {
+                        ui.selectable_value(&mut draft.kind, kind, kind.as_str());
+                    }
+                });
+            if draft.kind != kind {
+                draft.points.clear();
+            }
+            ui.label(match draft.kind {
+                ShapeKind::Polygon => "Click the map to add corners",
+                ShapeKind::Circle => "Click the centre, then the edge",
+            });
+            if ui.add_enabled(!draft.points.is_empty(), Button::new("↶ Undo")).clicked() {
+                draft.points.pop();
+            }
+            if ui.add_enabled(!saving, Button::new("💾 Save")).clicked() {
+                save = draft.geofence();
+            }
+            if ui.button("✖ Cancel").clicked() {
+                finished = true;
+            }
+        });
+
+        if let Some(geofence) = save {
+            let mut geofences = self.geofences.value.clone().unwrap_or_default();
+            geofences.push(geofence);
+            match validate_geofences(&geofences) {
+                Ok(()) => self.pending = Some(spawn_repainting(ui.ctx(), api::update_geofences(geofences))),
+                Err(why) => self.error = Some(why),
+            }
+        }
+        if finished {
+            self.draft = None;
+            self.error = None;
+        }
+    }
+
+    /// Outlines of the stored geofences
+    pub fn shapes(&self, vert_offset: f32) -> GeofenceShapes {
+        let geofences = self.geofences.value.clone().unwrap_or_default();
+        GeofenceShapes::new(geofences, GEOFENCE_COLOR, false, vert_offset)
+    }
+
+    /// Outline of the geofence being drawn and what records the clicks drawing it, none unless one is being drawn
+    ///
+    /// anchor: any position on the map, which tells where the clicks fall
+    pub fn draft(&self, anchor: LatLon, vert_offset: f32) -> Option<(GeofenceShapes, MapClicks)> {
+        let draft = self.draft.as_ref()?;
+        let shapes = GeofenceShapes::new(draft.geofence().into_iter().collect(), DRAFT_COLOR, true, vert_offset);
+        Some((shapes, MapClicks::new(anchor, self.clicked.clone(), vert_offset)))
+    }
+}
@@ -0,0 +1,138 @@
+use std::ops::RangeInclusive;
+use std::time::Duration;
+use egui::*;
+use egui_plot::{Line, Plot, PlotPoints};
+use protocol::{FixQuality, GpsStatus, PlotPoint, Quantity, Units};
+use crate::{api, graphs::time_tick, utils::{error_banner, format_time, PollableValue}};
+
+const PLOT_HEIGHT: f32 = 90.0;
+/// Time without any GPS row after which the receiver is taken to have stopped sending, in seconds
+const NO_DATA_AFTER: f64 = 10.0;
+/// Gap between rows which breaks the fix quality line, so missing data isn't drawn as a steady fix
+const HISTORY_GAP: f64 = 5.0;
+
+/// Fix type, precision and age of the GPS receiver's data, with a plot of its fix quality over the last minutes
+pub struct GpsStatusView {
+    status: PollableValue<Option<GpsStatus>>,
+}
+
+impl GpsStatusView {
+    pub fn new(ctx: &Context) -> Self {
+        Self {
+            status: PollableValue::new(ctx, api::gps_status),
+        }
+    }
+
+    /// units: units the altitude and geoid separation are shown in
+    pub fn ui(&mut self, ui: &mut Ui, units: &Units, refresh_period: Duration) {
+        let status = self.status.poll_every(refresh_period).flatten();
+        if let Some(why) = self.status.error() {
+            error_banner(ui, &format!("GPS status: {}", why));
+        }
+        let Some(status) = status else {
+            ui.label("GPS: no data recorded");
+            return;
+        };
+
+        let now = js_sys::Date::now() / 1000.0;
+        ui.horizontal_wrapped(|ui| {
+            let (text, color) = if now - status.time > NO_DATA_AFTER {
+                ("No data".to_string(), ui.visuals().warn_fg_color)
+            } else if status.quality == FixQuality::Invalid {
+                (format!("No fix ({})", status.fix_type), ui.visuals().error_fg_color)
+            } else {
+                (status.fix_type.clone(), ui.visuals().text_color())
+            };
+            ui.label("GPS:");
+            ui.colored_label(color, RichText::new(text).strong());
+            ui.separator();
+            ui.label(format!("last row {}", format_age(now - status.time)));
+            ui.separator();
+            ui.label(match status.last_fix {
+                Some(last_fix) => format!("last valid fix {}", format_age(now - last_fix)),
+                None => "never had a valid fix".to_string(),
+            });
+            if let Some(hdop) = status.hdop {
+                ui.separator();
+                ui.label(format!("HDOP {:.1}", hdop));
+            }
+            if let Some(satellites) = status.satellites {
+                ui.separator();
+                ui.label(format!("{} satellites", satellites));
+            }
+            let height = |metres: f64| format!("{:.1} {}", units.convert(Quantity::Length, metres), units.symbol(Quantity::Length));
+            if let Some(altitude) = status.altitude {
+                ui.separator();
+                ui.label(format!("altitude {}", height(altitude)));
+            }
+            if let Some(separation) = status.geoid_separation {
+                ui.separator();
+                ui.label(format!("geoid separation {}", height(separation)));
+            }
+        });
+
+        let color = ui.visuals().selection.stroke.color;
+        Plot::new("gps_fix_quality")
+            .height(PLOT_HEIGHT)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .allow_scroll(false)
+            .allow_boxed_zoom(false)
+            .include_y(FixQuality::Invalid.level() - 0.5)
+            .include_y(FixQuality::Gps.level() + 0.5)
+            .x_axis_formatter(time_tick)
+            .y_axis_formatter(quality_tick)
+            .label_formatter(|_, value| {
+                let quality = FixQuality::from_level(value.y.round()).map_or("", |quality| quality.as_str());
+                format!("{}\n{}", format_time(value.x * 1000.0), quality)
+            })
+            .show(ui, |plot_ui| {
+                for run in runs(&status.history) {
+                    plot_ui.line(Line::new(PlotPoints::from(steps(run))).color(color).name("Fix quality"));
+                }
+            });
+    }
+}
+
+/// How long ago something happened, from the seconds since
+fn format_age(seconds: f64) -> String {
+    let seconds = seconds.max(0.0);
+    if seconds < 1.0 {
+        "just now".to_string()
+    } else if seconds < 60.0 {
+        format!("{:.0} s ago", seconds)
+    } else if seconds < 3600.0 {
+        format!("{:.0} min ago", seconds / 60.0)
+    } else {
+        format!("{:.1} h ago", seconds / 3600.0)
+    }
+}
+
+/// Labels the quality axis with the name of each fix quality
+fn quality_tick(y: f64, _digits: usize, _range: &RangeInclusive<f64>) -> String {
+    FixQuality::from_level(y).map_or(String::new(), |quality| quality.as_str().to_string())
+}
+
+/// Splits `history` wherever no row was recorded for more than `HISTORY_GAP`
+fn runs(history: &[PlotPoint]) -> Vec<&[PlotPoint]> {
+    let mut runs = vec![];
+    let mut start = 0;
+    for i in 1..=history.len() {
+        if i == history.len() || history[i][0] - history[i - 1][0] > HISTORY_GAP {
+            runs.push(&history[start..i]);
+            start = i;
+        }
+    }
+    runs
+}
+
+/// Points of a line holding each quality until the next row, rather than sloping between them
+fn steps(run: &[PlotPoint]) -> Vec<PlotPoint> {
+    let mut points = Vec::with_capacity(run.len() * 2);
+    for pair in run.windows(2) {
+        points.push(pair[0]);
+        points.push([pair[1][0], pair[0][1]]);
+    }
+    points.extend(run.last());
+    points
+}
@@ -0,0 +1,486 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+use std::time::Duration;
+use egui::*;
+use egui_plot::{HLine, Legend, Line, LineStyle, Plot, PlotBounds, PlotPoints, Text, VLine};
+use web_time::Instant;
+use protocol::{AlertEvent, AlertRule, Annotation, ColumnRef, Dashboard, PlotPoint, Tile, Units, YAxis};
+use crate::{api, utils::{error_banner, format_time, PollableValue}};
+
+/// Graphs in this group share their x axis and cursor, so zooming one zooms all
+const LINK_GROUP: &str = "home_panel_graphs";
+/// Number of divisions of the right y axis
+const RIGHT_AXIS_TICKS: usize = 5;
+/// Most decimals a right axis label is shown with, however close its ticks are
+const MAX_TICK_DECIMALS: usize = 6;
+/// How long the graphs must stay still after being moved before the visible window is requested
+const FETCH_DELAY: Duration = Duration::from_millis(300);
+/// Fraction of the plotted values' range left free above and below them
+const Y_MARGIN: f64 = 0.05;
+
+/// Time window requested from the server, in unix seconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Window {
+    from: f64,
+    to: f64,
+    points: usize,
+}
+
+/// Everything drawn over the graphs besides their data
+pub struct Overlays<'a> {
+    pub rules: &'a [AlertRule],
+    pub events: &'a [AlertEvent],
+    pub annotations: &'a [Annotation],
+    /// unix time being replayed, marked on every graph
+    pub cursor: Option<f64>,
+}
+
+/// Data plotted for one column
+struct ColumnData {
+    /// newest points, shown while following live data
+    latest: PollableValue<Vec<PlotPoint>>,
+    /// points within the visible window, shown while browsing history
+    history: PollableValue<Vec<PlotPoint>>,
+}
+
+/// The dashboard's graphs, which follow the newest data until they are panned or zoomed,
+/// then show whatever time window is visible at a resolution matching their width
+pub struct Graphs {
+    data: HashMap<ColumnRef, ColumnData>,
+    pub follow_live: bool,
+    /// window read by every history fetch
+    window: Rc<Cell<Option<Window>>>,
+    /// x range to show this frame while following live data
+    target: Option<[f64; 2]>,
+    /// x range set on the graphs last frame while following live data
+    last_target: Option<[f64; 2]>,
+    /// x range the graphs were showing at the start of this frame
+    observed: Option<[f64; 2]>,
+    /// x range shown while browsing history, and when it last changed
+    shown: Option<[f64; 2]>,
+    changed_at: Option<Instant>,
+    /// when the newest history was received, fitting the y axes to it once it arrives
+    history_at: Option<f64>,
+    fit_y: bool,
+    /// x range every graph is moved to this frame, when asked to show a time range
+    jump_to: Option<[f64; 2]>,
+    width: f32,
+}
+
+impl Graphs {
+    pub fn new() -> Self {
+        Self {
+            data: HashMap::new(),
+            follow_live: true,
+            window: Rc::new(Cell::new(None)),
+            target: None,
+            last_target: None,
+            observed: None,
+            shown: None,
+            changed_at: None,
+            history_at: None,
+            fit_y: false,
+            jump_to: None,
+            width: 0.0,
+        }
+    }
+
+    /// Shows the follow toggle and updates the data of every column plotted on `dashboard`
+    pub fn begin(&mut self, ui: &mut Ui, dashboard: &Dashboard, refresh_period: Duration) {
+        ui.horizontal(|ui| {
+            if ui.toggle_value(&mut self.follow_live, "⏵ Follow live")
+                .on_hover_text("Drag a graph to pan back through time, ctrl + scroll to zoom")
+                .clicked()
+            {
+                self.last_target = None;
+                self.shown = None;
+                self.changed_at = None;
+            }
+        });
+
+        self.sync_data(ui.ctx(), dashboard);
+
+        if self.follow_live {
+            for data in self.data.values_mut() {
+                data.latest.poll_every(refresh_period);
+            }
+            let ranges = self.data.values().filter_map(|data| {
+                let points = data.latest.value.as_ref()?;
+                Some([points.first()?[0], points.last()?[0]])
+            });
+            self.target = ranges.reduce(|[a0, a1], [b0, b1]| [a0.min(b0), a1.max(b1)])
+                .filter(|[from, to]| from < to);
+        } else {
+            for data in self.data.values_mut() {
+                data.history.poll();
+            }
+            let history_at = self.data.values()
+                .filter_map(|data| data.history.updated_at())
+                .reduce(f64::max);
+            self.fit_y = history_at != self.history_at;
+            self.history_at = history_at;
+        }
+    }
+
+    /// Draws one graph of the dashboard, with series on the right axis rescaled to fit the left one,
+    /// the threshold of every alert rule watching one of its series, and a marker for every annotation.
+    /// Values and thresholds are shown in `units`
+    pub fn show(&mut self, ui: &mut Ui, index: usize, tile: &Tile, overlays: &Overlays, units: &Units) {
+        for series in &tile.series {
+            if let Some(why) = self.column(&series.source).and_then(|d| d.error()) {
+                error_banner(ui, &format!("{} ({}): {}", tile.title, series.source.column, why));
+            }
+        }
+        let has_data = tile.series.iter()
+            .any(|series| self.data.get(&series.source).is_some_and(|d| d.latest.value.is_some()));
+        if !has_data {
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            ui.heading(&tile.title);
+            let updated_at = tile.series.iter()
+                .filter_map(|series| self.column(&series.source)?.updated_at())
+                .reduce(f64::min);
+            if let Some(updated_at) = updated_at {
+                ui.label(format!("last updated {}", format_time(updated_at)));
+            }
+        });
+
+        // the server serves values in their stored units, shown here in the chosen ones
+        let converted: Vec<Option<Vec<PlotPoint>>> = tile.series.iter()
+            .map(|series| {
+                let points = self.column(&series.source)?.value.as_ref()?;
+                Some(points.iter().map(|[x, y]| [*x, units.convert_column(&series.source, *y)]).collect())
+            })
+            .collect();
+        let on_axis = |axis: YAxis| tile.series.iter()
+            .zip(&converted)
+            .filter(move |(series, _)| series.axis == axis)
+            .filter_map(|(_, points)| points.as_ref())
+            .flatten();
+        let mapping = AxisMapping::new(value_range(on_axis(YAxis::Left)), value_range(on_axis(YAxis::Right)));
+        let right_color = tile.series.iter()
+            .find(|series| series.axis == YAxis::Right)
+            .map(|series| series.color);
+
+        let lines: Vec<(String, Vec<PlotPoint>, [u8; 3])> = tile.series.iter()
+            .zip(converted)
+            .filter_map(|(series, points)| {
+                let points = points?;
+                Some(match series.axis {
+                    YAxis::Left => (units.label(&series.source), points, series.color),
+                    YAxis::Right => (
+                        format!("{} (right)", units.label(&series.source)),
+                        points.iter().map(|[x, y]| [*x, mapping.to_left(*y)]).collect(),
+                        series.color,
+                    ),
+                })
+            })
+            .collect();
+
+        let thresholds: Vec<(String, f64, Color32)> = tile.series.iter()
+            .flat_map(|series| overlays.rules.iter()
+                .filter(move |rule| rule.source == series.source)
+                .map(move |rule| (series, rule)))
+            .map(|(series, rule)| {
+                let active = overlays.events.iter().any(|event| event.is_active() && event.rule == rule.name);
+                let [r, g, b] = series.color;
+                let color = if active { ui.visuals().error_fg_color } else { Color32::from_rgb(r, g, b) };
+                let value = units.convert_column(&series.source, rule.threshold);
+                let threshold = match series.axis {
+                    YAxis::Left => value,
+                    YAxis::Right => mapping.to_left(value),
+                };
+                (format!("{} ({} {:.2})", rule.name, rule.comparator.as_str(), value), threshold, color)
+            })
+            .collect();
+
+        // while following, show the newest data; once new history arrives, fit it into the visible window
+        let x_range = match (self.follow_live, self.fit_y) {
+            (true, _) => self.target,
+            (false, true) => self.shown,
+            (false, false) => None,
+        }.or(self.jump_to);
+        let y_range = x_range.and_then(|[from, to]| {
+            let visible = lines.iter()
+                .flat_map(|(_, points, _)| points)
+                .filter(|[x, _]| (from..=to).contains(x));
+            value_range(visible)
+        });
+
+        self.width = ui.available_width();
+        let annotation_color = ui.visuals().warn_fg_color;
+        let cursor_color = ui.visuals().selection.stroke.color;
+        let mut observed = None;
+        let plot = Plot::new(index)
+            .legend(Legend::default())
+            .height(tile.height)
+            .allow_scroll(false)
+            .link_axis(LINK_GROUP, true, false)
+            .link_cursor(LINK_GROUP, true, false)
+            .x_axis_formatter(time_tick)
+            .label_formatter(move |name, value| {
+                let y = if name.ends_with("(right)") { mapping.to_right(value.y) } else { value.y };
+                format!("{}\n{}\n{:.3}", name, format_time(value.x * 1000.0), y)
+            });
+        plot.show(ui, |plot_ui| {
+            let bounds = plot_ui.plot_bounds();
+            observed = Some([bounds.min()[0], bounds.max()[0]]);
+            if let Some([from, to]) = x_range {
+                let (bottom, top) = match y_range {
+                    Some((bottom, top)) => {
+                        let margin = ((top - bottom) * Y_MARGIN).max(f64::EPSILON);
+                        (bottom - margin, top + margin)
+                    },
+                    None => (bounds.min()[1], bounds.max()[1]),
+                };
+                plot_ui.set_plot_bounds(PlotBounds::from_min_max([from, bottom], [to, top]));
+            }
+
+            for (name, points, [r, g, b]) in lines {
+                plot_ui.line(Line::new(PlotPoints::from(points)).name(name).color(Color32::from_rgb(r, g, b)));
+            }
+            for (name, threshold, color) in thresholds {
+                plot_ui.hline(HLine::new(threshold).name(name).color(color).style(LineStyle::dashed_loose()));
+            }
+            let top = plot_ui.plot_bounds().max()[1];
+            for annotation in overlays.annotations {
+                plot_ui.vline(VLine::new(annotation.time).color(annotation_color));
+                let label = RichText::new(&annotation.text).color(annotation_color);
+                plot_ui.text(Text::new(egui_plot::PlotPoint::new(annotation.time, top), label).anchor(Align2::LEFT_TOP));
+            }
+
+            if let Some(cursor) = overlays.cursor {
+                plot_ui.vline(VLine::new(cursor).color(cursor_color).width(2.0));
+            }
+
+            // egui_plot only draws the left axis, so label the right one inside the plot's edge
+            if let Some([r, g, b]) = right_color {
+                let bounds = plot_ui.plot_bounds();
+                let (bottom, top) = (bounds.min()[1], bounds.max()[1]);
+                let step = (top - bottom) / RIGHT_AXIS_TICKS as f64;
+                let decimals = tick_decimals(mapping.to_right_span(step));
+                for tick in 1..RIGHT_AXIS_TICKS {
+                    let y = bottom + step * tick as f64;
+                    let label = RichText::new(format!("{:.*}", decimals, mapping.to_right(y))).color(Color32::from_rgb(r, g, b));
+                    plot_ui.text(Text::new(egui_plot::PlotPoint::new(bounds.max()[0], y), label).anchor(Align2::RIGHT_CENTER));
+                }
+            }
+        });
+        // the graphs share their x axis, so the first one tells where they all are
+        self.observed = self.observed.or(observed);
+    }
+
+    /// Stops following live data and moves every graph to show `range`
+    pub fn show_range(&mut self, range: [f64; 2]) {
+        self.follow_live = false;
+        self.last_target = None;
+        self.jump_to = Some(range);
+    }
+
+    /// Time window last requested while browsing history, none while following live data
+    pub fn browsed_range(&self) -> Option<[f64; 2]> {
+        match self.window.get() {
+            Some(window) if !self.follow_live => Some([window.from, window.to]),
+            _ => None,
+        }
+    }
+
+    /// Stops following live data once the graphs have been moved, and requests the visible window
+    /// from the server once they have stayed still for `FETCH_DELAY`
+    pub fn end(&mut self, ctx: &Context) {
+        self.jump_to = None;
+        let observed = self.observed.take();
+        if self.follow_live {
+            if let (Some(observed), Some(last_target)) = (observed, self.last_target) {
+                if !same_range(observed, last_target) {
+                    self.follow_live = false;
+                }
+            }
+            self.last_target = self.target;
+        }
+        if !self.follow_live {
+            if let Some(observed) = observed {
+                if !self.shown.is_some_and(|shown| same_range(shown, observed)) {
+                    self.shown = Some(observed);
+                    self.changed_at = Some(Instant::now());
+                }
+            }
+        }
+
+        if let (Some(changed_at), Some([from, to])) = (self.changed_at, self.shown) {
+            let elapsed = changed_at.elapsed();
+            if elapsed >= FETCH_DELAY {
+                self.changed_at = None;
+                self.window.set(Some(Window { from, to, points: self.width.max(1.0) as usize }));
+                for data in self.data.values_mut() {
+                    data.history.refresh();
+                }
+            } else {
+                ctx.request_repaint_after(FETCH_DELAY - elapsed);
+            }
+        }
+    }
+
+    /// Keeps data for exactly the columns plotted on the dashboard
+    fn sync_data(&mut self, ctx: &Context, dashboard: &Dashboard) {
+        let sources = dashboard.sources();
+        self.data.retain(|source, _| sources.contains(&source));
+        for source in sources {
+            if !self.data.contains_key(source) {
+                let request = source.clone();
+                let latest = PollableValue::new(ctx, move || {
+                    api::data_latest(&request.column, &request.table)
+                });
+                let request = source.clone();
+                let window = self.window.clone();
+                let history = PollableValue::new(ctx, move || {
+                    let fetch = window.get().map(|w| {
+                        api::data_range(&request.column, &request.table, w.from, w.to, w.points)
+                    });
+                    async move {
+                        match fetch {
+                            Some(fetch) => fetch.await,
+                            None => Ok(vec![]),
+                        }
+                    }
+                });
+                self.data.insert(source.clone(), ColumnData { latest, history });
+            }
+        }
+    }
+
+    /// The data of `source` currently being shown
+    fn column(&self, source: &ColumnRef) -> Option<&PollableValue<Vec<PlotPoint>>> {
+        let data = self.data.get(source)?;
+        Some(if self.follow_live { &data.latest } else { &data.history })
+    }
+}
+
+/// Labels the time axis with the local time of day
+pub fn time_tick(x: f64, _digits: usize, _range: &RangeInclusive<f64>) -> String {
+    format_time(x * 1000.0)
+}
+
+/// Whether two x ranges match, ignoring rounding
+fn same_range(a: [f64; 2], b: [f64; 2]) -> bool {
+    let tolerance = (a[1] - a[0]).abs().max(1.0) * 1e-6;
+    (a[0] - b[0]).abs() <= tolerance && (a[1] - b[1]).abs() <= tolerance
+}
+
+/// Smallest and largest values of `points`
+fn value_range<'a>(points: impl Iterator<Item = &'a PlotPoint>) -> Option<(f64, f64)> {
+    points.map(|[_, y]| *y)
+        .filter(|y| y.is_finite())
+        .fold(None, |range, y| match range {
+            None => Some((y, y)),
+            Some((min, max)) => Some((min.min(y), max.max(y))),
+        })
+}
+
+/// Decimals needed to tell apart ticks `span` apart, none for a flat or empty axis
+fn tick_decimals(span: f64) -> usize {
+    if !span.is_finite() || span <= 0.0 {
+        return 0;
+    }
+    (-span.log10()).ceil().clamp(0.0, MAX_TICK_DECIMALS as f64) as usize
+}
+
+/// Linear map from values measured against the right y axis onto the left one
+#[derive(Clone, Copy)]
+struct AxisMapping {
+    left_min: f64,
+    right_min: f64,
+    scale: f64, // left units per right unit
+}
+
+impl AxisMapping {
+    /// Maps the `right` range onto the `left` one, or leaves values unchanged when either is missing
+    fn new(left: Option<(f64, f64)>, right: Option<(f64, f64)>) -> Self {
+        match (left, right) {
+            (Some((left_min, left_max)), Some((right_min, right_max))) => {
+                let span = |min: f64, max: f64| if max > min { max - min } else { 1.0 };
+                Self {
+                    left_min,
+                    right_min,
+                    scale: span(left_min, left_max) / span(right_min, right_max),
+                }
+            },
+            _ => Self { left_min: 0.0, right_min: 0.0, scale: 1.0 },
+        }
+    }
+
+    fn to_left(self, y: f64) -> f64 {
+        self.left_min + (y - self.right_min) * self.scale
+    }
+
+    fn to_right(self, y: f64) -> f64 {
+        self.right_min + (y - self.left_min) / self.scale
+    }
+
+    fn to_right_span(self, span: f64) -> f64 {
+        span / self.scale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn value_range_skips_values_which_cant_be_plotted() {
+        let points = [[0.0, 2.0], [1.0, f64::NAN], [2.0, -1.0], [3.0, 5.0]];
+        assert_eq!(value_range(points.iter()), Some((-1.0, 5.0)));
+        assert_eq!(value_range(std::iter::empty()), None);
+    }
+
+    #[test]
+    fn tick_decimals_tell_ticks_apart() {
+        assert_eq!(tick_decimals(10.0), 0);
+        assert_eq!(tick_decimals(1.0), 0);
+        assert_eq!(tick_decimals(0.5), 1);
+        assert_eq!(tick_decimals(0.01), 2);
+        assert_eq!(tick_decimals(1e-12), MAX_TICK_DECIMALS);
+    }
+
+    #[test]
+    fn tick_decimals_of_flat_or_empty_axes() {
+        assert_eq!(tick_decimals(0.0), 0);
+        assert_eq!(tick_decimals(-1.0), 0);
+        assert_eq!(tick_decimals(f64::NAN), 0);
+        assert_eq!(tick_decimals(f64::INFINITY), 0);
+    }
+
+    #[test]
+    fn right_axis_maps_onto_the_left() {
+        let mapping = AxisMapping::new(Some((0.0, 10.0)), Some((100.0, 200.0)));
+        assert_eq!(mapping.to_left(100.0), 0.0);
+        assert_eq!(mapping.to_left(200.0), 10.0);
+        assert_eq!(mapping.to_right(5.0), 150.0);
+        assert_eq!(mapping.to_right_span(1.0), 10.0);
+        assert_eq!(mapping.to_right(mapping.to_left(123.0)), 123.0);
+    }
+
+    #[test]
+    fn flat_or_missing_axes_are_mapped_without_dividing_by_zero() {
+        let unchanged = AxisMapping::new(None, Some((1.0, 2.0)));
+        assert_eq!(unchanged.to_left(1.5), 1.5);
+        assert_eq!(unchanged.to_right(1.5), 1.5);
+
+        let flat = AxisMapping::new(Some((0.0, 10.0)), Some((3.0, 3.0)));
+        assert_eq!(flat.to_left(3.0), 0.0);
+        assert!(flat.to_right(10.0).is_finite());
+        assert!(flat.to_right_span(1.0).is_finite());
+    }
+
+    #[test]
+    fn ranges_match_despite_rounding() {
+        let now = 1.7e9;
+        assert!(same_range([now, now + 60.0], [now + 1e-5, now + 60.0]));
+        assert!(!same_range([now, now + 60.0], [now, now + 61.0]));
+        assert!(!same_range([0.0, 60.0], [1.0, 60.0]));
+    }
+}
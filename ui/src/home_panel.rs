@@ -1,121 +1,96 @@
 use wasm_bindgen::prelude::*;
-use std::collections::HashMap;
+use std::time::Duration;
 use egui::*;
-use egui_plot::{Legend, Line, Plot, PlotPoints};
-use wasm_bindgen_futures::spawn_local;
-use walkers::{Tiles, Map, MapMemory, Position, TilesManager, HttpOptions};
-use log::debug;
-use crate::{send_update, Config, line_drawing::GpsLine, utils::PollableValue};
+use poll_promise::Promise;
+use protocol::{AlertEvent, AlertRule, Annotation, ColumnRef, Dashboard, NewAnnotation, RecordingUpdate, Series, Tile, YAxis};
+use crate::{alerts::active_alerts, api::{self, ApiError}, Config, dashboard::{series_color, DashboardEditor, TileAction}, graphs::{Graphs, Overlays}, gps_status::GpsStatusView, map::MapView, playback::{point_at, Playback}, trip_summary::TripCard, utils::{error_banner, spawn_repainting, PollableValue}};
 
-const GRAPH_COUNT: usize = 4;
-const GRAPHS: [Graph; GRAPH_COUNT] = [
+/// Graphs shown until the user loads a saved dashboard
+const DEFAULT_GRAPHS: [Graph; 2] = [
     Graph {
-        title: "Acceleration X",
-        column: "accelerometer_x",
+        title: "Acceleration",
         table: "accelerometer_data",
+        columns: &["accelerometer_x", "accelerometer_y", "accelerometer_z"],
     },
     Graph {
-        title: "Acceleration Y",
-        column: "accelerometer_y",
-        table: "accelerometer_data",
-    },
-    Graph {
-        title: "Acceleration Z",
-        column: "accelerometer_z",
-        table: "accelerometer_data",
-    },
-    Graph {
-        title: "Temperature (C)",
-        column: "temperature_celsius",
+        title: "Temperature",
         table: "thermalprobe_data",
+        columns: &["temperature_celsius"],
     },
 ];
 
 #[derive(Clone)]
 struct Graph {
     title: &'static str,
-    column: &'static str,
     table: &'static str,
+    columns: &'static [&'static str],
 }
 
-const MAP_HEIGHT: f32 = 600.0;
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub enum Provider {
-    OpenStreetMap,
-}
-
-fn providers(egui_ctx: Context) -> HashMap<Provider, Box<dyn TilesManager + Send>> {
-    let mut providers: HashMap<Provider, Box<dyn TilesManager + Send>> = HashMap::default();
-
-    providers.insert(
-        Provider::OpenStreetMap,
-        Box::new(Tiles::with_options(
-            walkers::sources::OpenStreetMap,
-            http_options(),
-            egui_ctx.to_owned(),
-        )),
-    );  
-
-    providers
-}
-
-fn http_options() -> HttpOptions {
-    HttpOptions {
-        cache: if std::env::var("NO_HTTP_CACHE").is_ok() {
-            None
-        } else {
-            Some(".cache".into())
-        },
+const DEFAULT_GRAPH_HEIGHT: f32 = 200.0;
+
+fn default_dashboard() -> Dashboard {
+    Dashboard {
+        name: String::new(),
+        tiles: DEFAULT_GRAPHS.iter().map(|graph| Tile {
+            title: graph.title.to_string(),
+            series: graph.columns.iter().enumerate().map(|(i, column)| Series {
+                source: ColumnRef { table: graph.table.to_string(), column: column.to_string() },
+                color: series_color(i),
+                axis: YAxis::Left,
+            }).collect(),
+            height: DEFAULT_GRAPH_HEIGHT,
+        }).collect(),
     }
 }
 
+/// Text of annotations added without any
+const DEFAULT_ANNOTATION: &str = "mark";
+/// Alert rules change rarely, so they are fetched less often than the data
+const ALERT_RULES_REFRESH: Duration = Duration::from_secs(10);
+
 #[wasm_bindgen]
 pub struct HomePanel {
     is_recording: bool,
-    data: HomePanelData,
-    map_memory: MapMemory,
-    providers: HashMap<Provider, Box<dyn TilesManager + Send>>,
-    gps_points: PollableValue<Vec<[f64; 2]>>,
+    /// change of recording state sent to the server, which takes effect once it's accepted
+    recording: Option<Promise<Result<RecordingUpdate, ApiError>>>,
+    recording_error: Option<String>,
+    dashboard: Dashboard,
+    editor: DashboardEditor,
+    graphs: Graphs,
+    alert_rules: PollableValue<Vec<AlertRule>>,
+    alert_events: PollableValue<Vec<AlertEvent>>,
+    annotations: PollableValue<Vec<Annotation>>,
+    annotation_text: String,
+    /// annotation being added, the annotations are fetched again once it's stored
+    annotating: Option<Promise<Result<Annotation, ApiError>>>,
+    annotation_error: Option<String>,
+    gps_status: GpsStatusView,
+    trip: TripCard,
+    map: MapView,
+    playback: Playback,
     scroll_offset: f32,
     lowest_edge: f32,
 }
 
-/// refers to all of the auto-refreshing data on the home panel
-struct HomePanelData {
-    time: u16,
-    pub data: [PollableValue<Vec<[f64; 2]>>; GRAPH_COUNT],
-}
-
-impl HomePanelData {
-    fn new(defaults: [Option<Vec<[f64; 2]>>; GRAPH_COUNT]) -> Self {
-        Self {
-            data: array_init::array_init(|i| {
-                PollableValue::new(
-                    defaults[i].clone(),
-                    poll_promise::Promise::spawn_local(async move {
-                        HomePanel::req_data_latest(&GRAPHS[i].column, &GRAPHS[i].table).await   
-                    })  
-                )
-            }),
-            time: 0,
-        }
-    }
-}
-
 impl HomePanel {
     pub fn new(ctx: Context) -> Self {
         Self {
             is_recording: false,
-            data: HomePanelData::new(array_init::array_init(|_| { None })),
-            map_memory: MapMemory::default(),
-            providers: providers(ctx),
-            gps_points: PollableValue::new(
-                Default::default(),
-                poll_promise::Promise::spawn_local(async {
-                    GpsLine::req_points().await
-                })
-            ),
+            recording: None,
+            recording_error: None,
+            dashboard: default_dashboard(),
+            editor: DashboardEditor::new(&ctx),
+            graphs: Graphs::new(),
+            alert_rules: PollableValue::new(&ctx, api::alert_rules),
+            alert_events: PollableValue::new(&ctx, api::alert_events),
+            annotations: PollableValue::new(&ctx, api::annotations),
+            annotation_text: String::new(),
+            annotating: None,
+            annotation_error: None,
+            gps_status: GpsStatusView::new(&ctx),
+            trip: TripCard::new(&ctx),
+            map: MapView::new(&ctx),
+            playback: Playback::new(),
             scroll_offset: 0.0,
             lowest_edge: 1800.0,
         }
@@ -126,121 +101,109 @@ impl HomePanel {
         .auto_shrink([false, false])
         .stick_to_bottom(false)
         .show(ui, |ui| {
-            let mut ready_count = 0;
+            let refresh_period = Duration::from_secs_f32(config.refresh_time);
+            self.editor.toolbar(ui, &mut self.dashboard);
+
+            let alert_rules = self.alert_rules.poll_every(ALERT_RULES_REFRESH).unwrap_or_default();
+            let alert_events = self.alert_events.poll_every(refresh_period).unwrap_or_default();
+            if let Some(why) = self.alert_events.error() {
+                error_banner(ui, &format!("Alerts: {}", why));
+            }
+            active_alerts(ui, &alert_events, &config.units);
+
+            if let Some(result) = self.annotating.as_ref().and_then(|p| p.ready()) {
+                match result {
+                    Ok(_) => {
+                        self.annotation_error = None;
+                        self.annotations.refresh();
+                    },
+                    Err(why) => self.annotation_error = Some(why.to_string()),
+                }
+                self.annotating = None;
+            }
+            let annotations = self.annotations.poll_every(refresh_period).unwrap_or_default();
+            if let Some(why) = self.annotation_error.clone().or_else(|| self.annotations.error().map(|e| e.to_string())) {
+                error_banner(ui, &format!("Annotations: {}", why));
+            }
+            if let Some(range) = self.playback.ui(ui, self.map.track()) {
+                self.graphs.show_range(range);
+            }
+            let overlays = Overlays {
+                rules: &alert_rules,
+                events: &alert_events,
+                annotations: &annotations,
+                cursor: self.playback.time(),
+            };
+
             // graphs showing auto-refreshing data
-            for i in 0..GRAPH_COUNT {
-                if let Some(res) = self.data.data[i].poll() {
-                    ready_count += 1;
-                    ui.heading(GRAPHS[i].title);
-                    let plot = Plot::new(i)
-                        .legend(Legend::default())
-                        .height(200.0)
-                        .allow_scroll(false);
-                    let line = Line::new(PlotPoints::from(res)).name(&GRAPHS[i].title);
-                    plot.show(ui, |plot_ui| {
-                        plot_ui.line(line);
-                    });
+            self.graphs.begin(ui, &self.dashboard, refresh_period);
+            let tile_count = self.dashboard.tiles.len();
+            let mut action = None;
+            for (i, tile) in self.dashboard.tiles.iter_mut().enumerate() {
+                if self.editor.editing {
+                    if let Some(a) = self.editor.tile_controls(ui, tile, i, tile_count) {
+                        action = Some((i, a));
+                    }
                 }
+                self.graphs.show(ui, i, tile, &overlays, &config.units);
             }
-            // ui.heading(format!("ready count: {ready_count}"));
-            // ui.heading(format!("time: {t}", t=self.data.time));
-            // if all have been recieved, count up to refresh_time to refresh
-            if ready_count == GRAPH_COUNT {
-                self.data.time += 1;
-                if self.data.time == (config.refresh_time * 60.0) as u16 {
-                    self.data = HomePanelData::new(
-                        array_init::array_init(|i| {
-                            self.data.data[i].poll()
-                        })
-                    )
+            self.graphs.end(ui.ctx());
+            match action {
+                Some((i, TileAction::MoveUp)) => self.dashboard.tiles.swap(i, i - 1),
+                Some((i, TileAction::MoveDown)) => self.dashboard.tiles.swap(i, i + 1),
+                Some((i, TileAction::Remove)) => { self.dashboard.tiles.remove(i); },
+                None => {},
+            }
+            if self.editor.editing && ui.button("➕ Add graph").clicked() {
+                if let Some(tile) = self.editor.new_tile() {
+                    self.dashboard.tiles.push(tile);
                 }
             }
 
+            if let Some(result) = self.recording.as_ref().and_then(|p| p.ready()) {
+                match result {
+                    Ok(update) => {
+                        self.is_recording = update.is_recording;
+                        self.recording_error = None;
+                    },
+                    Err(why) => self.recording_error = Some(why.to_string()),
+                }
+                self.recording = None;
+            }
+            if let Some(why) = &self.recording_error {
+                error_banner(ui, &format!("Recording: {}", why));
+            }
             ui.horizontal(|ui| {
-                if !self.is_recording {
-                    if ui.button("Record").clicked() {
-                        self.is_recording = true;
-                        let mut body = HashMap::new();
-                        body.insert("isRecording", "true");
-                        spawn_local(async move {
-                            send_update(&body, "http://127.0.0.1:8000/update").await;
-                        })
-                    }
-                } else {
-                    if ui.button("Stop").clicked() {
-                        self.is_recording = false;
-                        let mut body = HashMap::new();
-                        body.insert("isRecording", "false");
-                        spawn_local(async move {
-                            send_update(&body, "http://127.0.0.1:8000/update").await;
-                        })
-                    }
+                let label = if self.is_recording { "Stop" } else { "Record" };
+                if ui.add_enabled(self.recording.is_none(), Button::new(label)).clicked() {
+                    let body = RecordingUpdate { is_recording: !self.is_recording };
+                    self.recording = Some(spawn_repainting(ui.ctx(), api::update(body)));
                 }
-            });
-
-            let tiles = self.providers.get_mut(&Provider::OpenStreetMap).unwrap().as_mut();
 
-            let mut map = Map::new(
-                Some(tiles),
-                &mut self.map_memory,
-                Position::from_lat_lon(44.56203897286608, -123.28196905234289));
-
-            if let Some(res) = self.gps_points.poll() {
-                map = map.with_plugin(GpsLine::new(res, self.scroll_offset));
-            }
+                let note = ui.add(TextEdit::singleline(&mut self.annotation_text)
+                    .hint_text(DEFAULT_ANNOTATION)
+                    .desired_width(160.0));
+                let submitted = note.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter));
+                let clicked = ui.add_enabled(self.annotating.is_none(), Button::new("📌 Annotate")).clicked();
+                if (clicked || submitted) && self.annotating.is_none() {
+                    let text = match self.annotation_text.trim() {
+                        "" => DEFAULT_ANNOTATION.to_string(),
+                        text => text.to_string(),
+                    };
+                    self.annotation_text.clear();
+                    let annotation = NewAnnotation { text, time: None };
+                    self.annotating = Some(spawn_repainting(ui.ctx(), api::add_annotation(annotation)));
+                }
+            });
 
-            let map_corner = ui.cursor().min + Vec2::new( 8.0,MAP_HEIGHT - 40.0);
-            ui.add_sized([ui.available_width(), MAP_HEIGHT], map);
-            if map_corner[1] <= self.lowest_edge {
-                zoom(ui, &mut self.map_memory, map_corner);
-            }
+            self.gps_status.ui(ui, &config.units, refresh_period);
+            self.trip.ui(ui, self.graphs.browsed_range(), &config.units, refresh_period);
+            let replayed = self.playback.time().and_then(|time| point_at(self.map.track(), time));
+            self.map.ui(ui, &overlays, self.graphs.browsed_range(), replayed, config, refresh_period, self.scroll_offset, self.lowest_edge);
         });
 
         let total_height = scroll.content_size[1] + scroll.inner_rect.min[1];
         self.scroll_offset = ((total_height - scroll.inner_rect.max[1]) - scroll.state.offset.y ) / 2.0;
         self.lowest_edge = scroll.inner_rect.max[1];
     }
-
-    /// Requests data of type `Option<Vec<[f64; 2]>>` from the server
-    async fn req_data_latest(column: &str, table: &str) -> Option<Vec<[f64; 2]>> {
-        let client = reqwest_wasm::Client::new();
-        let url: String = format!("http://127.0.0.1:8000/req/data/latest/{}/{}", column, table);
-        let res = match client.get(url).send().await {
-            Err(why) => {
-                debug!("failed to get: {}", why);
-                return None;
-            },
-            Ok(result) => {
-                result
-            },
-        };
-        return match res.json::<Vec<[f64; 2]>>().await {
-            Err(why) => {
-                debug!("failed to parse json: {}", why);
-                None
-            },
-            Ok(result) => {
-                Some(result)
-            }
-        }
-    }
 }
-
-pub fn zoom(ui: &Ui, map_memory: &mut MapMemory, location: Pos2) {
-    Window::new("Map")
-        .collapsible(false)
-        .resizable(false)
-        .title_bar(false)
-        .fixed_pos(location)
-        .show(ui.ctx(), |ui| {
-            ui.horizontal(|ui| {
-                if ui.button(RichText::new("➕").heading()).clicked() {
-                    let _ = map_memory.zoom_in();
-                }
-
-                if ui.button(RichText::new("➖").heading()).clicked() {
-                    let _ = map_memory.zoom_out();
-                }
-            });
-        });
-}
\ No newline at end of file
@@ -1,15 +1,24 @@
+mod alerts;
+mod api;
+mod calibration;
 mod utils;
 mod line_drawing;
 mod home_panel;
 mod log_panel;
 mod config_panel;
+mod dashboard;
+mod graphs;
+mod geofences;
+mod gps_status;
+mod map;
+mod playback;
+mod trip_summary;
 
 use wasm_bindgen::prelude::*;
-use std::collections::HashMap;
-use log::debug;
 use wasm_bindgen_futures::spawn_local;
 use egui::Context;
 use crate::{home_panel::*, log_panel::*, config_panel::*};
+use protocol::Config;
 
 const TITLE: &str = "Personal Data Acquisition";
 
@@ -42,11 +51,12 @@ pub struct MyApp {
 
 impl MyApp {
     fn new(ctx: Context) -> Self {
+        api::init();
         Self {
             open_panel: Panel::default(),
+            log_panel: LogPanel::new(&ctx),
+            config_panel: ConfigPanel::new(&ctx),
             home_panel: HomePanel::new(ctx),
-            log_panel: LogPanel::default(),
-            config_panel: ConfigPanel::default(),
         }
     }
 }
@@ -54,11 +64,13 @@ impl MyApp {
 impl eframe::App for MyApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         egui::CentralPanel::default().show(ctx, |ui| {
-            ui.ctx().request_repaint();
             ui.horizontal(|ui| {
                 ui.selectable_value(&mut self.open_panel, Panel::Home, "Home");
                 ui.selectable_value(&mut self.open_panel, Panel::Log, "Log");
                 ui.selectable_value(&mut self.open_panel, Panel::Config, "Config");
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    connection_indicator(ui);
+                });
             });
 
             ui.separator();
@@ -78,6 +90,19 @@ impl eframe::App for MyApp {
     }
 }
 
+/// Shows whether the server answered the most recent request
+fn connection_indicator(ui: &mut egui::Ui) {
+    let (color, text, detail) = match api::connection() {
+        api::Connection::Unknown => (egui::Color32::GRAY, "Connecting", api::base_url()),
+        api::Connection::Connected => (egui::Color32::GREEN, "Connected", api::base_url()),
+        api::Connection::Disconnected(why) => (
+            ui.visuals().error_fg_color,
+            "Disconnected",
+            format!("{}: {}", api::base_url(), why)),
+    };
+    ui.colored_label(color, format!("● {}", text)).on_hover_text(detail);
+}
+
 #[derive(PartialEq, Eq)]
 #[wasm_bindgen]
 pub enum Panel {
@@ -91,12 +116,3 @@ impl Default for Panel {
         Self::Home
     }
 }
-
-pub async fn send_update(body: &HashMap<&str, &str>, url: &str) {
-    let client = reqwest_wasm::Client::new();
-    let res = match client.post(url).json(body).send().await {
-            Ok(r) => r.text().await,
-            Err(e) => Err(e)
-        };
-    debug!("res: {:?}", res);
-}
\ No newline at end of file
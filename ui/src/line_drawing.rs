@@ -1,68 +1,492 @@
+use std::cell::Cell;
+use std::rc::Rc;
 use walkers::{Plugin, Projector, Position};
-use egui::{Color32, Painter, Response, Stroke};
-use log::debug;
+use egui::{Align2, Color32, FontId, Painter, Pos2, Rect, Response, Shape, Stroke, Vec2};
+use protocol::{self, CoordinateFormat, Fix, Geofence, LatLon, Quantity, TrackPoint, Units, EARTH_RADIUS};
 
-const NUM_POINTS: usize = 15;
+/// Colours running from the lowest value of a track to the highest
+const GRADIENT: [Color32; 4] = [
+    Color32::from_rgb(40, 90, 220),
+    Color32::from_rgb(30, 170, 90),
+    Color32::from_rgb(230, 200, 30),
+    Color32::from_rgb(210, 40, 30),
+];
+/// Side of a map tile in pixels
+pub const TILE_SIZE: f64 = 256.0;
+const LEGEND_SIZE: Vec2 = Vec2::new(160.0, 10.0);
+const LEGEND_MARGIN: f32 = 10.0;
 
+/// What a GPS track is coloured by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TrackColoring {
+    #[default]
+    Speed,
+    Altitude,
+}
+
+impl TrackColoring {
+    pub const ALL: [TrackColoring; 2] = [TrackColoring::Speed, TrackColoring::Altitude];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrackColoring::Speed => "Speed over ground",
+            TrackColoring::Altitude => "Altitude",
+        }
+    }
+
+    fn quantity(&self) -> Quantity {
+        match self {
+            TrackColoring::Speed => Quantity::Speed,
+            TrackColoring::Altitude => Quantity::Length,
+        }
+    }
+
+    fn value(&self, point: &TrackPoint) -> f64 {
+        match self {
+            TrackColoring::Speed => point.speed,
+            TrackColoring::Altitude => point.altitude,
+        }
+    }
+}
+
+/// A GPS track of any length, coloured by speed or altitude, with a legend in the map's corner
 pub struct GpsLine {
-    points: Vec<[f64; 2]>,
+    points: Vec<TrackPoint>,
+    coloring: TrackColoring,
+    /// units the legend is labelled in
+    units: Units,
     vert_offset: f32,
 }
 
 impl GpsLine {
-    pub async fn req_points() -> Option<Vec<[f64; 2]>> {
-        let client = reqwest_wasm::Client::new();
-        let res = match client.get("http://127.0.0.1:8000/req/data/latest/gps".to_owned()).send().await {
-            Err(why) => {
-                debug!("failed to get: {}", why);
-                return None;
+
+    pub fn new(points: Vec<TrackPoint>, coloring: TrackColoring, units: Units, vert_offset: f32) -> Self {
+        Self {
+            points,
+            coloring,
+            units,
+            vert_offset
+        }
+    }
+
+    /// Lowest and highest value along the track
+    fn value_range(&self) -> Option<(f64, f64)> {
+        self.points.iter()
+            .map(|point| self.coloring.value(point))
+            .filter(|value| value.is_finite())
+            .fold(None, |range, value| match range {
+                None => Some((value, value)),
+                Some((min, max)) => Some((min.min(value), max.max(value))),
+            })
+    }
+}
+
+impl Plugin for GpsLine {
+    fn draw(&self,
+        response: &Response,
+        _gesture_handled: bool,
+        painter: Painter,
+        projector: &Projector,
+    ) {
+        let Some((min, max)) = self.value_range() else {
+            return;
+        };
+        let color = |point: &TrackPoint| {
+            let span = max - min;
+            let t = if span > 0.0 { (self.coloring.value(point) - min) / span } else { 0.0 };
+            gradient(t as f32)
+        };
+        let screen = |point: &TrackPoint| {
+            let [lat, lon] = point.position;
+            projector.project(Position::from_lat_lon(lat, lon)).to_pos2()
+                - Vec2::new(0.0, self.vert_offset)
+        };
+
+        if let [point] = self.points.as_slice() {
+            painter.circle_filled(screen(point), 3.0, color(point));
+        }
+        for pair in self.points.windows(2) {
+            painter.line_segment([screen(&pair[0]), screen(&pair[1])], Stroke::new(3.0, color(&pair[1])));
+        }
+
+        let quantity = self.coloring.quantity();
+        let title = format!("{} ({})", self.coloring.as_str(), self.units.symbol(quantity));
+        legend(&painter, response.rect, &title, self.units.convert(quantity, min), self.units.convert(quantity, max));
+    }
+}
+
+/// Colour at `t` along `GRADIENT`, from 0 to 1
+fn gradient(t: f32) -> Color32 {
+    let t = if t.is_finite() { t.clamp(0.0, 1.0) } else { 0.0 };
+    let scaled = t * (GRADIENT.len() - 1) as f32;
+    let i = (scaled as usize).min(GRADIENT.len() - 2);
+    let [from, to] = [GRADIENT[i], GRADIENT[i + 1]];
+    let f = scaled - i as f32;
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * f).round() as u8;
+    Color32::from_rgb(mix(from.r(), to.r()), mix(from.g(), to.g()), mix(from.b(), to.b()))
+}
+
+/// Draws the gradient with the values at its ends in the bottom right corner of `rect`
+fn legend(painter: &Painter, rect: Rect, title: &str, min: f64, max: f64) {
+    let bar = Rect::from_min_size(
+        rect.right_bottom() - LEGEND_SIZE - Vec2::splat(LEGEND_MARGIN),
+        LEGEND_SIZE);
+    let background = bar.expand2(Vec2::new(6.0, 20.0)).translate(Vec2::new(0.0, -6.0));
+    painter.rect_filled(background, 4.0, Color32::from_white_alpha(200));
+
+    let steps = LEGEND_SIZE.x as usize / 2;
+    for step in 0..steps {
+        let x = bar.left() + bar.width() * step as f32 / steps as f32;
+        let slice = Rect::from_x_y_ranges(x..=x + bar.width() / steps as f32, bar.y_range());
+        painter.rect_filled(slice, 0.0, gradient(step as f32 / (steps - 1) as f32));
+    }
+
+    let font = FontId::proportional(11.0);
+    painter.text(bar.center_top() - Vec2::new(0.0, 2.0), Align2::CENTER_BOTTOM, title,
+                 font.clone(), Color32::BLACK);
+    painter.text(bar.left_bottom() + Vec2::new(0.0, 2.0), Align2::LEFT_TOP, format!("{:.1}", min),
+                 font.clone(), Color32::BLACK);
+    painter.text(bar.right_bottom() + Vec2::new(0.0, 2.0), Align2::RIGHT_TOP, format!("{:.1}", max),
+                 font, Color32::BLACK);
+}
+
+/// The receiver's latest position, as an arrow pointing where it is heading
+/// inside a circle as wide as the position is uncertain
+pub struct PositionMarker {
+    fix: Fix,
+    color: Color32,
+    vert_offset: f32,
+}
+
+impl PositionMarker {
+
+    pub fn new(fix: Fix, color: Color32, vert_offset: f32) -> Self {
+        Self {
+            fix,
+            color,
+            vert_offset
+        }
+    }
+}
+
+impl Plugin for PositionMarker {
+    fn draw(&self,
+        _response: &Response,
+        _gesture_handled: bool,
+        painter: Painter,
+        projector: &Projector,
+    ) {
+        let color = self.color;
+        let [lat, lon] = self.fix.point.position;
+        let screen = |lat: f64, lon: f64| {
+            projector.project(Position::from_lat_lon(lat, lon)).to_pos2()
+                - Vec2::new(0.0, self.vert_offset)
+        };
+        let center = screen(lat, lon);
+
+        if let Some(accuracy) = self.fix.accuracy {
+            // a point `accuracy` metres north tells how many pixels that is at this zoom
+            let north = screen(lat + (accuracy / EARTH_RADIUS).to_degrees(), lon);
+            let radius = center.distance(north);
+            painter.circle(center, radius, color.gamma_multiply(0.15), Stroke::new(1.0, color.gamma_multiply(0.6)));
+        }
+
+        match self.fix.heading {
+            Some(heading) => {
+                let angle = (heading as f32).to_radians();
+                let point = |forward: f32, right: f32| -> Pos2 {
+                    // screen y grows downwards, so north is -y
+                    let (sin, cos) = angle.sin_cos();
+                    center + Vec2::new(forward * sin + right * cos, -forward * cos + right * sin)
+                };
+                let arrow = vec![point(11.0, 0.0), point(-7.0, 7.0), point(-7.0, -7.0)];
+                painter.add(Shape::convex_polygon(arrow, color, Stroke::new(1.5, Color32::WHITE)));
             },
-            Ok(result) => {
-                result
+            None => {
+                painter.circle(center, 6.0, color, Stroke::new(2.0, Color32::WHITE));
             },
+        }
+    }
+}
+
+/// Where the whole web mercator world lies on screen at the map's current zoom, in pixels measured
+/// from a known position so they stay precise however far in the map is zoomed
+struct WorldFrame {
+    /// where the known position is drawn
+    anchor: Pos2,
+    /// the known position's distance from the world's top left corner
+    anchor_world: [f64; 2],
+    /// width of the world
+    world: f64,
+}
+
+impl WorldFrame {
+    /// Works the frame out from where `anchor`, any position on the map, and a point a degree east of it are drawn
+    fn new(projector: &Projector, anchor: LatLon, vert_offset: f32) -> Option<Self> {
+        let [lat, lon] = anchor;
+        let anchor = projector.project(Position::from_lat_lon(lat, lon)).to_pos2()
+            - Vec2::new(0.0, vert_offset);
+        let east = projector.project(Position::from_lat_lon(lat, lon + 1.0)).to_pos2()
+            - Vec2::new(0.0, vert_offset);
+
+        let world = (east.x - anchor.x) as f64 * 360.0;
+        if !(world.is_finite() && world > 0.0) {
+            return None;
+        }
+        let lat = lat.to_radians();
+        let world_x = (lon + 180.0) / 360.0 * world;
+        let world_y = (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / std::f64::consts::PI) / 2.0 * world;
+        Some(Self { anchor, anchor_world: [world_x, world_y], world })
+    }
+
+    /// Distance of `point` on screen from the world's top left corner
+    fn to_world(&self, point: Pos2) -> [f64; 2] {
+        let [x, y] = self.anchor_world;
+        [x + (point.x - self.anchor.x) as f64, y + (point.y - self.anchor.y) as f64]
+    }
+
+    /// Screen position of a point `x`, `y` from the world's top left corner
+    fn to_screen(&self, [x, y]: [f64; 2]) -> Pos2 {
+        let [anchor_x, anchor_y] = self.anchor_world;
+        self.anchor + Vec2::new((x - anchor_x) as f32, (y - anchor_y) as f32)
+    }
+
+    /// Position drawn at `point` on screen
+    fn position(&self, point: Pos2) -> LatLon {
+        let [x, y] = self.to_world(point);
+        let lat = (std::f64::consts::PI * (1.0 - 2.0 * y / self.world)).sinh().atan().to_degrees();
+        [lat, x / self.world * 360.0 - 180.0]
+    }
+}
+
+/// A plain grid of map tiles, drawn in place of the map where there are no tiles to show
+pub struct TileGrid {
+    /// any position on the map, which tells where the tiles fall
+    anchor: LatLon,
+    vert_offset: f32,
+}
+
+impl TileGrid {
+
+    pub fn new(anchor: LatLon, vert_offset: f32) -> Self {
+        Self {
+            anchor,
+            vert_offset
+        }
+    }
+}
+
+impl Plugin for TileGrid {
+    fn draw(&self,
+        response: &Response,
+        _gesture_handled: bool,
+        painter: Painter,
+        projector: &Projector,
+    ) {
+        let Some(frame) = WorldFrame::new(projector, self.anchor, self.vert_offset) else {
+            return;
         };
-        return match res.json::<Vec<[f64; 2]>>().await {
-            Err(why) => {
-                debug!("failed to parse json: {},", why);
-                None
-            },
-            Ok(result) => {
-                Some(result)
-            }
+        // width of one tile at the zoom shown
+        let tile = frame.world / 2f64.powf((frame.world / TILE_SIZE).log2().floor().max(0.0));
+
+        let rect = response.rect;
+        painter.rect_filled(rect, 0.0, Color32::from_gray(235));
+        let stroke = Stroke::new(1.0, Color32::from_gray(200));
+        let [left, top] = frame.to_world(rect.left_top());
+        let mut x = (left / tile).floor() * tile;
+        while frame.to_screen([x, top]).x <= rect.right() {
+            painter.vline(frame.to_screen([x, top]).x, rect.y_range(), stroke);
+            x += tile;
+        }
+        let mut y = (top / tile).floor() * tile;
+        while frame.to_screen([left, y]).y <= rect.bottom() {
+            painter.hline(rect.x_range(), frame.to_screen([left, y]).y, stroke);
+            y += tile;
         }
+        painter.text(rect.left_top() + Vec2::splat(8.0), Align2::LEFT_TOP, "No map tiles stored on the server",
+                     FontId::proportional(13.0), Color32::from_gray(120));
     }
+}
+
+/// Labelled pins, marking where annotations were made or alerts were raised
+pub struct Pins {
+    pins: Vec<(LatLon, String)>,
+    color: Color32,
+    vert_offset: f32,
+}
+
+impl Pins {
 
-    pub fn new(points: Vec<[f64; 2]>, vert_offset: f32) -> Self {
+    pub fn new(pins: Vec<(LatLon, String)>, color: Color32, vert_offset: f32) -> Self {
         Self {
-            points,
+            pins,
+            color,
             vert_offset
         }
     }
 }
 
-impl Plugin for GpsLine {
+impl Plugin for Pins {
     fn draw(&self,
         _response: &Response,
         _gesture_handled: bool,
         painter: Painter,
         projector: &Projector,
     ) {
-        let stroke = Stroke::new(3.0, Color32::DARK_RED);
+        let color = self.color;
+        for ([lat, lon], text) in &self.pins {
+            let point = projector.project(Position::from_lat_lon(*lat, *lon)).to_pos2()
+                                - Vec2::new(0.0, self.vert_offset);
+            let head = point - Vec2::new(0.0, 12.0);
 
-        let mut prev_point = self.points[0];
-        for i in 1..NUM_POINTS {
-            let current_point = self.points[i];
+            painter.line_segment([point, head], Stroke::new(2.0, color));
+            painter.circle(head, 5.0, color, Stroke::new(1.0, Color32::BLACK));
+            painter.text(head + Vec2::new(8.0, 0.0), Align2::LEFT_CENTER, text,
+                         FontId::proportional(13.0), Color32::BLACK);
+        }
+    }
+}
 
-            let mut point = Position::from_lat_lon(prev_point[0], prev_point[1]);
-            let point1 = projector.project(point).to_pos2()
-                                - egui::Vec2::new(0.0, self.vert_offset);
+/// Outlines of geofences labelled with their names, with their corners marked while they're being drawn
+pub struct GeofenceShapes {
+    geofences: Vec<Geofence>,
+    color: Color32,
+    show_corners: bool,
+    vert_offset: f32,
+}
 
-            point = Position::from_lat_lon(current_point[0], current_point[1]);
-            let point2 = projector.project(point).to_pos2()
-                                - egui::Vec2::new(0.0, self.vert_offset);
+impl GeofenceShapes {
 
-            painter.line_segment([point1, point2], stroke);
-            prev_point = current_point;
+    pub fn new(geofences: Vec<Geofence>, color: Color32, show_corners: bool, vert_offset: f32) -> Self {
+        Self {
+            geofences,
+            color,
+            show_corners,
+            vert_offset
         }
     }
-}
\ No newline at end of file
+}
+
+impl Plugin for GeofenceShapes {
+    fn draw(&self,
+        _response: &Response,
+        _gesture_handled: bool,
+        painter: Painter,
+        projector: &Projector,
+    ) {
+        let color = self.color;
+        let stroke = Stroke::new(2.0, color);
+        let screen = |[lat, lon]: LatLon| {
+            projector.project(Position::from_lat_lon(lat, lon)).to_pos2()
+                - Vec2::new(0.0, self.vert_offset)
+        };
+
+        for geofence in &self.geofences {
+            let label = match &geofence.shape {
+                protocol::Shape::Circle { center, radius } => {
+                    let [lat, lon] = *center;
+                    let center = screen(*center);
+                    // a point `radius` metres north tells how many pixels that is at this zoom
+                    let north = screen([lat + (radius / EARTH_RADIUS).to_degrees(), lon]);
+                    painter.circle(center, center.distance(north), color.gamma_multiply(0.15), stroke);
+                    if self.show_corners {
+                        painter.circle_filled(center, 4.0, color);
+                    }
+                    center
+                },
+                protocol::Shape::Polygon(corners) => {
+                    let points: Vec<Pos2> = corners.iter().map(|&corner| screen(corner)).collect();
+                    if points.len() > 2 {
+                        painter.add(Shape::closed_line(points.clone(), stroke));
+                    } else {
+                        painter.add(Shape::line(points.clone(), stroke));
+                    }
+                    if self.show_corners {
+                        for &point in &points {
+                            painter.circle_filled(point, 4.0, color);
+                        }
+                    }
+                    let sum = points.iter().fold(Vec2::ZERO, |sum, point| sum + point.to_vec2());
+                    (sum / points.len().max(1) as f32).to_pos2()
+                },
+            };
+            painter.text(label, Align2::CENTER_CENTER, &geofence.name,
+                         FontId::proportional(13.0), Color32::BLACK);
+        }
+    }
+}
+
+/// Records where the map is clicked, for drawing geofences
+pub struct MapClicks {
+    /// any position on the map, which tells where the clicks fall
+    anchor: LatLon,
+    clicked: Rc<Cell<Option<LatLon>>>,
+    vert_offset: f32,
+}
+
+impl MapClicks {
+
+    pub fn new(anchor: LatLon, clicked: Rc<Cell<Option<LatLon>>>, vert_offset: f32) -> Self {
+        Self {
+            anchor,
+            clicked,
+            vert_offset
+        }
+    }
+}
+
+impl Plugin for MapClicks {
+    fn draw(&self,
+        response: &Response,
+        _gesture_handled: bool,
+        _painter: Painter,
+        projector: &Projector,
+    ) {
+        if !response.clicked() {
+            return;
+        }
+        let (Some(point), Some(frame)) = (response.interact_pointer_pos(), WorldFrame::new(projector, self.anchor, self.vert_offset)) else {
+            return;
+        };
+        self.clicked.set(Some(frame.position(point)));
+    }
+}
+
+/// Position under the mouse pointer, shown in the map's top right corner
+pub struct CursorPosition {
+    /// any position on the map, which tells where the pointer falls
+    anchor: LatLon,
+    format: CoordinateFormat,
+    vert_offset: f32,
+}
+
+impl CursorPosition {
+
+    pub fn new(anchor: LatLon, format: CoordinateFormat, vert_offset: f32) -> Self {
+        Self {
+            anchor,
+            format,
+            vert_offset
+        }
+    }
+}
+
+impl Plugin for CursorPosition {
+    fn draw(&self,
+        response: &Response,
+        _gesture_handled: bool,
+        painter: Painter,
+        projector: &Projector,
+    ) {
+        let (Some(point), Some(frame)) = (response.hover_pos(), WorldFrame::new(projector, self.anchor, self.vert_offset)) else {
+            return;
+        };
+        let text = self.format.format(frame.position(point));
+        let corner = response.rect.right_top() + Vec2::new(-LEGEND_MARGIN, LEGEND_MARGIN);
+        let font = FontId::monospace(12.0);
+        let size = painter.layout_no_wrap(text.clone(), font.clone(), Color32::BLACK).size();
+        let background = Align2::RIGHT_TOP.anchor_rect(Rect::from_min_size(corner, size));
+        painter.rect_filled(background.expand(4.0), 4.0, Color32::from_white_alpha(200));
+        painter.text(corner, Align2::RIGHT_TOP, text, font, Color32::BLACK);
+    }
+}
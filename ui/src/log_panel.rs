@@ -1,43 +1,27 @@
 use wasm_bindgen::prelude::*;
 use egui::*;
-use log::debug;
-use crate::utils::PollableValue;
-use crate::Config;
+use crate::utils::{download, error_banner, format_time, PollableValue};
+use crate::{api::{self, ApiError}, Config};
+use protocol::{CoordinateFormat, DataTable, Quantity, Row, Units};
 use std::sync::Mutex;
-
-const MAX_WIDTH: usize = 12;
+use std::future::Future;
+use std::time::Duration;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
-enum DataBases {Acceleration, GPS, Temperature}
+enum DataBases {Acceleration, GPS, Temperature, Geofences}
 static DATA_BASE: Mutex<DataBases> = Mutex::new(DataBases::Acceleration);
 
-struct LogPanelData {
-    data: PollableValue<Vec<[String; MAX_WIDTH]>>,   
-    time: u16,
-}
-
-impl LogPanelData {
-    fn new(default: Option<Vec<[String; MAX_WIDTH]>>) -> Self {
-        Self {
-            data: PollableValue::new(
-                default,
-                poll_promise::Promise::spawn_local(async move {
-                    LogPanel::req_data_full().await // TEMP, TODO genericize this
-                })),
-            time: 0,
-        }
-    }
-}
-
 #[wasm_bindgen]
 pub struct LogPanel {
-    data: LogPanelData,
+    data: PollableValue<Vec<Row>>,
+    export_error: Option<String>,
 }
 
-impl Default for LogPanel {
-    fn default() -> Self {
+impl LogPanel {
+    pub fn new(ctx: &Context) -> Self {
         Self {
-            data: LogPanelData::new(None),
+            data: PollableValue::new(ctx, LogPanel::req_data_full),
+            export_error: None,
         }
     }
 }
@@ -48,6 +32,7 @@ impl LogPanel {
         
         let headers: Vec<String>;
         let changed_base: bool;
+        let data_base_shown: DataBases;
         
         {
             let mut data_base = DATA_BASE.lock().unwrap();
@@ -58,15 +43,42 @@ impl LogPanel {
                 .show_ui(ui, |ui| {
                     ui.selectable_value(&mut *data_base, DataBases::Acceleration, "Acceleration");
                     ui.selectable_value(&mut *data_base, DataBases::GPS, "GPS");
-                    ui.selectable_value(&mut *data_base, DataBases::Temperature, "Temperature (C)");
+                    ui.selectable_value(&mut *data_base, DataBases::Temperature, "Temperature");
+                    ui.selectable_value(&mut *data_base, DataBases::Geofences, "Geofences");
                 }
             );
             changed_base = old_base != *data_base;
             
-            headers = LogPanel::generate_headers(*data_base);
+            headers = LogPanel::generate_headers(*data_base, &config.units);
+            data_base_shown = *data_base;
         }
-        
-        let items = headers.len();
+
+        let refresh_period = Duration::from_secs_f32(config.refresh_time);
+        // switching data base cancels the request for the old one
+        if changed_base {
+            self.data.reset();
+        }
+        let table_data = self.data.poll_every(refresh_period);
+        if let Some(why) = self.data.error() {
+            error_banner(ui, &why.to_string());
+        }
+        if let Some(why) = &self.export_error {
+            error_banner(ui, why);
+        }
+        ui.horizontal(|ui| {
+            if let Some(updated_at) = self.data.updated_at() {
+                ui.label(format!("last updated {}", format_time(updated_at)));
+            }
+            let export = ui.add_enabled(table_data.is_some(), egui::Button::new("⬇ Export CSV"))
+                .on_hover_text("Download the rows shown, with values in the chosen units");
+            if let (true, Some(rows)) = (export.clicked(), &table_data) {
+                let rows: Vec<Vec<String>> = rows.iter()
+                    .map(|entry| LogPanel::cells(entry, data_base_shown, config.coordinate_format, &config.units))
+                    .collect();
+                let file_name = format!("{}.csv", LogPanel::table(data_base_shown).as_str());
+                self.export_error = download(&file_name, &LogPanel::csv(&headers, &rows), "text/csv").err();
+            }
+        });
 
         let table = TableBuilder::new(ui)
             .striped(true)
@@ -75,6 +87,7 @@ impl LogPanel {
             .columns(Column::auto(), headers.len())
             .min_scrolled_height(0.0);
 
+        let headers_len = headers.len();
         table
             .header(20.0, |mut header| {
                 for label in headers {
@@ -86,93 +99,193 @@ impl LogPanel {
             .body(|mut body| {
                 let row_height = 18.0;
 
-                if let Some(table_data) = self.data.data.poll() {
+                if let Some(table_data) = table_data {
                     for entry in table_data {
+                        let cells = LogPanel::cells(&entry, data_base_shown, config.coordinate_format, &config.units);
                         body.row(row_height, |mut row| {
                             row.col(|ui| {
-                                ui.label(entry[0].to_string());
+                                ui.label(cells[0].to_string());
                             });
-                            for i in 1..items {
+                            for cell in cells.iter().take(headers_len).skip(1) {
                                 row.col(|ui| {
                                     ui.add(
-                                        egui::Label::new(entry[i].to_string()).wrap(false),
+                                        egui::Label::new(cell.to_string()).wrap(false),
                                     );
                                 });
                             }
                         });
                     }
-                    // update timer
-                    self.data.time += 1;
-                    if self.data.time == (config.refresh_time * 60.0) as u16 || changed_base {
-                        self.data = LogPanelData::new(
-                            self.data.data.value.clone()
-                        )
-                    }
                 }
             });
     }
 
-    /// Requests data of type `Option<Vec<[String; 5]>>` from the server
-    async fn req_data_full() -> Option<Vec<[String; MAX_WIDTH]>> {
-        let client = reqwest_wasm::Client::new();
+    /// Requests the rows of the selected data base from the server
+    fn req_data_full() -> impl Future<Output = Result<Vec<Row>, ApiError>> {
+        let data_base = *DATA_BASE.lock().unwrap();
+        api::data_full(LogPanel::table(data_base))
+    }
 
-        let param: &str;
-        {
-            let data_base = DATA_BASE.lock().unwrap();
-            param = match *data_base {
-                DataBases::Acceleration => "acceleration",
-                DataBases::GPS => "gps",
-                DataBases::Temperature => "temperature",
-            };
+    /// Table the server serves the rows of `choice` from
+    fn table(choice: DataBases) -> DataTable {
+        match choice {
+            DataBases::Acceleration => DataTable::Acceleration,
+            DataBases::GPS => DataTable::Gps,
+            DataBases::Temperature => DataTable::Temperature,
+            DataBases::Geofences => DataTable::Geofences,
         }
+    }
 
-        let res = match client.get("http://127.0.0.1:8000/req/data/full/".to_owned() + param).send().await {
-            Err(why) => {
-                debug!("failed to get: {}", why);
-                return None;
-            },
-            Ok(result) => {
-                result
-            },
+    /// `rows` under `headers` as comma separated values, quoting those holding commas, quotes or line breaks
+    fn csv(headers: &[String], rows: &[Vec<String>]) -> String {
+        let field = |text: &String| if text.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", text.replace('"', "\"\""))
+        } else {
+            text.clone()
         };
-        return match res.json::<Vec<[String; MAX_WIDTH]>>().await {
-            Err(why) => {
-                debug!("failed to parse json: {},", why);
-                None
-            },
-            Ok(result) => {
-                Some(result)
+        let line = |cells: &[String]| cells.iter().take(headers.len()).map(field).collect::<Vec<_>>().join(",");
+        let mut csv = line(headers);
+        for row in rows {
+            csv.push_str("\r\n");
+            csv.push_str(&line(row));
+        }
+        csv.push_str("\r\n");
+        csv
+    }
+
+    /// Column of the latitude in the rows of `choice`, which the longitude follows
+    fn position_column(choice: DataBases) -> Option<usize> {
+        match choice {
+            DataBases::GPS => Some(3),
+            DataBases::Geofences => Some(4),
+            DataBases::Acceleration | DataBases::Temperature => None,
+        }
+    }
+
+    /// Columns of the rows of `choice` holding values with a unit, and what they measure
+    fn quantity_columns(choice: DataBases) -> &'static [(usize, Quantity)] {
+        match choice {
+            DataBases::Acceleration => &[(2, Quantity::Acceleration), (3, Quantity::Acceleration), (4, Quantity::Acceleration)],
+            DataBases::GPS => &[(5, Quantity::Length), (6, Quantity::Speed), (7, Quantity::Length)],
+            DataBases::Temperature => &[(2, Quantity::Temperature)],
+            DataBases::Geofences => &[],
+        }
+    }
+
+    /// Text of each column of `entry`, with its values in `units` and its latitude and longitude joined into one position in `format`
+    fn cells(entry: &Row, choice: DataBases, format: CoordinateFormat, units: &Units) -> Vec<String> {
+        let mut cells = entry.to_vec();
+        for &(i, quantity) in LogPanel::quantity_columns(choice) {
+            // values already in the chosen unit are shown as stored
+            if let Ok(value) = entry[i].parse::<f64>() {
+                let converted = units.convert(quantity, value);
+                if converted != value {
+                    cells[i] = format!("{:.3}", converted);
+                }
             }
         }
+        if let Some(i) = LogPanel::position_column(choice) {
+            let position = match (entry[i].parse::<f64>(), entry[i + 1].parse::<f64>()) {
+                (Ok(lat), Ok(lon)) => format.format([lat, lon]),
+                _ => format!("{} {}", entry[i], entry[i + 1]).trim().to_string(),
+            };
+            cells[i] = position;
+            cells.remove(i + 1);
+        }
+        cells
     }
-    fn generate_headers(choice: DataBases) -> Vec<String> {
+
+    fn generate_headers(choice: DataBases, units: &Units) -> Vec<String> {
+        let unit = |name: &str, quantity: Quantity| format!("{} ({})", name, units.symbol(quantity));
         let mut headers: Vec<String> = vec![];
 
         match choice {
             DataBases::Acceleration => {
                 headers.push("Row".to_string());
                 headers.push("Time".to_string());
-                headers.push("Acceleration X".to_string());
-                headers.push("Acceleration Y".to_string());
-                headers.push("Acceleration Z".to_string());
+                headers.push(unit("Acceleration X", Quantity::Acceleration));
+                headers.push(unit("Acceleration Y", Quantity::Acceleration));
+                headers.push(unit("Acceleration Z", Quantity::Acceleration));
             }
             DataBases::GPS => {
                 headers.push("Fix Type".to_string());
                 headers.push("Fix Time".to_string());
                 headers.push("Fix Date".to_string());
-                headers.push("Latitude".to_string());
-                headers.push("Longitude".to_string());
-                headers.push("Altitude".to_string());
-                headers.push("Ground Speed".to_string());
-                headers.push("Geoid Separation".to_string());
+                headers.push("Position".to_string());
+                headers.push(unit("Altitude", Quantity::Length));
+                headers.push(unit("Ground Speed", Quantity::Speed));
+                headers.push(unit("Geoid Separation", Quantity::Length));
             }
             DataBases::Temperature => {
                 headers.push("Row".to_string());
                 headers.push("Timestamp".to_string());
-                headers.push("Temperature (C)".to_string());
+                headers.push(unit("Temperature", Quantity::Temperature));
+            }
+            DataBases::Geofences => {
+                headers.push("Row".to_string());
+                headers.push("Time".to_string());
+                headers.push("Geofence".to_string());
+                headers.push("Event".to_string());
+                headers.push("Position".to_string());
             }
         }
 
         return headers
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::TemperatureUnit;
+
+    fn row(cells: &[&str]) -> Row {
+        let mut row = Row::default();
+        for (i, cell) in cells.iter().enumerate() {
+            row[i] = cell.to_string();
+        }
+        row
+    }
+
+    #[test]
+    fn latitude_and_longitude_are_joined_into_a_position() {
+        let gps = row(&["GPS", "21:00:00", "2024-03-12", "44.5919", "-123.26705", "70", "1.5", "-21"]);
+        let cells = LogPanel::cells(&gps, DataBases::GPS, CoordinateFormat::Dms, &Units::default());
+        assert_eq!(cells[3], CoordinateFormat::Dms.format([44.5919, -123.26705]));
+        assert_eq!(cells[4..7], ["70", "1.5", "-21"]);
+        assert_eq!(cells.len(), gps.len() - 1);
+    }
+
+    #[test]
+    fn positions_which_arent_numbers_are_shown_as_recorded() {
+        let event = row(&["1", "2024-03-12 21:00:00", "Home", "Entered", "", ""]);
+        let cells = LogPanel::cells(&event, DataBases::Geofences, CoordinateFormat::Decimal, &Units::default());
+        assert_eq!(cells[4], "");
+        assert_eq!(cells[5], "");
+    }
+
+    #[test]
+    fn values_are_shown_in_the_chosen_units() {
+        let temperature = row(&["1", "2024-03-12 21:00:00", "100"]);
+        let units = Units { temperature: TemperatureUnit::Fahrenheit, ..Units::default() };
+        let cells = LogPanel::cells(&temperature, DataBases::Temperature, CoordinateFormat::Decimal, &units);
+        assert_eq!(cells[2], "212.000");
+
+        // values already in the chosen unit are shown as stored
+        let cells = LogPanel::cells(&temperature, DataBases::Temperature, CoordinateFormat::Decimal, &Units::default());
+        assert_eq!(cells[2], "100");
+        assert_eq!(cells[1], "2024-03-12 21:00:00");
+    }
+
+    #[test]
+    fn csv_quotes_fields_which_need_it() {
+        let headers = ["Name".to_string(), "Note".to_string()];
+        let rows = [
+            vec!["plain".to_string(), "a, b".to_string(), "beyond the headers".to_string()],
+            vec!["say \"hi\"".to_string(), "two\nlines".to_string()],
+        ];
+        assert_eq!(
+            LogPanel::csv(&headers, &rows),
+            "Name,Note\r\nplain,\"a, b\"\r\n\"say \"\"hi\"\"\",\"two\nlines\"\r\n",
+        );
+        assert_eq!(LogPanel::csv(&headers, &[]), "Name,Note\r\n");
+    }
+}
@@ -0,0 +1,444 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Duration;
+use egui::*;
+use walkers::{sources::Attribution, HttpOptions, Map, MapMemory, Position, TileId, TileSource, Tiles, TilesManager};
+use protocol::{routes, Config, Fix, LatLon, TileSet, TrackPoint};
+use crate::{api, geofences::GeofenceEditor, graphs::Overlays, line_drawing::{CursorPosition, GpsLine, Pins, PositionMarker, TileGrid, TrackColoring, TILE_SIZE}, utils::{error_banner, load_value, save_value, PollableValue}};
+
+pub const MAP_HEIGHT: f32 = 600.0;
+/// Where the map is centred until the first GPS fix arrives
+const DEFAULT_POSITION: LatLon = [44.56203897286608, -123.28196905234289];
+/// Fraction of the map taken up by a fitted track
+const FIT_MARGIN: f64 = 0.9;
+/// Closest zoom a track is fitted at, so a short one isn't shown at street level
+const MAX_FIT_ZOOM: f64 = 18.0;
+/// How often the server is asked whether it has map tiles, which change only when they're seeded
+const TILES_REFRESH: Duration = Duration::from_secs(30);
+const PROVIDER_KEY: &str = "pda_map_provider";
+const ZOOM_KEY: &str = "pda_map_zoom";
+const LAYERS_KEY: &str = "pda_map_layers";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Provider {
+    /// tiles stored on the server, which work without connectivity
+    Local,
+    OpenStreetMap,
+    OpenTopoMap,
+    Satellite,
+}
+
+impl Provider {
+    pub const ALL: [Provider; 4] = [Provider::Local, Provider::OpenStreetMap, Provider::OpenTopoMap, Provider::Satellite];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Provider::Local => "Local tiles",
+            Provider::OpenStreetMap => "OpenStreetMap",
+            Provider::OpenTopoMap => "OpenTopoMap",
+            Provider::Satellite => "Satellite",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|provider| provider.as_str() == name)
+    }
+}
+
+/// Map tiles served by the server this UI talks to
+struct LocalTiles;
+
+impl TileSource for LocalTiles {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        format!("{}{}", api::base_url(), routes::tile(tile_id.zoom, tile_id.x, tile_id.y))
+    }
+
+    fn attribution(&self) -> Attribution {
+        Attribution {
+            text: "OpenStreetMap contributors",
+            url: "https://www.openstreetmap.org/copyright",
+            logo_light: None,
+            logo_dark: None,
+        }
+    }
+}
+
+/// Topographic map with contour lines, handy off road
+struct OpenTopoMap;
+
+impl TileSource for OpenTopoMap {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        format!("https://tile.opentopomap.org/{}/{}/{}.png", tile_id.zoom, tile_id.x, tile_id.y)
+    }
+
+    fn attribution(&self) -> Attribution {
+        Attribution {
+            text: "OpenStreetMap contributors, SRTM, OpenTopoMap (CC-BY-SA)",
+            url: "https://opentopomap.org/about",
+            logo_light: None,
+            logo_dark: None,
+        }
+    }
+}
+
+/// Esri's aerial imagery
+struct Satellite;
+
+impl TileSource for Satellite {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        format!("https://server.arcgisonline.com/ArcGIS/rest/services/World_Imagery/MapServer/tile/{}/{}/{}",
+            tile_id.zoom, tile_id.y, tile_id.x)
+    }
+
+    fn attribution(&self) -> Attribution {
+        Attribution {
+            text: "Esri, Maxar, Earthstar Geographics",
+            url: "https://www.esri.com",
+            logo_light: None,
+            logo_dark: None,
+        }
+    }
+}
+
+fn providers(egui_ctx: Context) -> HashMap<Provider, Box<dyn TilesManager + Send>> {
+    let mut providers: HashMap<Provider, Box<dyn TilesManager + Send>> = HashMap::default();
+
+    providers.insert(
+        Provider::Local,
+        Box::new(Tiles::with_options(LocalTiles, http_options(), egui_ctx.to_owned())),
+    );
+    providers.insert(
+        Provider::OpenStreetMap,
+        Box::new(Tiles::with_options(walkers::sources::OpenStreetMap, http_options(), egui_ctx.to_owned())),
+    );
+    providers.insert(
+        Provider::OpenTopoMap,
+        Box::new(Tiles::with_options(OpenTopoMap, http_options(), egui_ctx.to_owned())),
+    );
+    providers.insert(
+        Provider::Satellite,
+        Box::new(Tiles::with_options(Satellite, http_options(), egui_ctx.to_owned())),
+    );
+
+    providers
+}
+
+fn http_options() -> HttpOptions {
+    HttpOptions {
+        cache: if std::env::var("NO_HTTP_CACHE").is_ok() {
+            None
+        } else {
+            Some(".cache".into())
+        },
+    }
+}
+
+/// What can be drawn over the map tiles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layer {
+    Track,
+    Position,
+    Annotations,
+    Alerts,
+    Geofences,
+}
+
+impl Layer {
+    pub const ALL: [Layer; 5] = [Layer::Track, Layer::Position, Layer::Annotations, Layer::Alerts, Layer::Geofences];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Layer::Track => "GPS track",
+            Layer::Position => "Position",
+            Layer::Annotations => "Annotations",
+            Layer::Alerts => "Alert locations",
+            Layer::Geofences => "Geofences",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|layer| layer.as_str() == name)
+    }
+}
+
+/// The map, drawn from the chosen provider with the chosen layers over it.
+/// The provider, zoom and layers are kept in the browser's local storage.
+pub struct MapView {
+    memory: MapMemory,
+    providers: HashMap<Provider, Box<dyn TilesManager + Send>>,
+    provider: Provider,
+    /// local tiles on the server, the local provider shows a plain grid when there are none
+    tile_set: PollableValue<Option<TileSet>>,
+    layers: Vec<Layer>,
+    /// zoom last saved, so it's only saved again once it changes
+    saved_zoom: f64,
+    /// track of the time range browsed on the graphs, or of the current session while they follow live data
+    track: PollableValue<Vec<TrackPoint>>,
+    track_range: Rc<Cell<Option<[f64; 2]>>>,
+    track_coloring: TrackColoring,
+    fix: PollableValue<Option<Fix>>,
+    geofences: GeofenceEditor,
+}
+
+impl MapView {
+    pub fn new(ctx: &Context) -> Self {
+        let mut memory = MapMemory::default();
+        if let Some(zoom) = load_value(ZOOM_KEY).and_then(|zoom| zoom.parse().ok()) {
+            let _ = memory.set_zoom(zoom);
+        }
+        // local tiles are the default, as the map is mostly used in the field without connectivity
+        let provider = load_value(PROVIDER_KEY)
+            .and_then(|name| Provider::from_name(&name))
+            .unwrap_or(Provider::Local);
+        let layers = match load_value(LAYERS_KEY) {
+            Some(names) => names.split(',').filter_map(Layer::from_name).collect(),
+            None => Layer::ALL.to_vec(),
+        };
+        let track_range = Rc::new(Cell::new(None));
+        let range = track_range.clone();
+        Self {
+            saved_zoom: memory.zoom(),
+            memory,
+            providers: providers(ctx.clone()),
+            provider,
+            tile_set: PollableValue::new(ctx, api::tile_set),
+            layers,
+            track: PollableValue::new(ctx, move || api::gps_track(range.get())),
+            track_range,
+            track_coloring: TrackColoring::default(),
+            fix: PollableValue::new(ctx, api::gps_fix),
+            geofences: GeofenceEditor::new(ctx),
+        }
+    }
+
+    /// The track drawn on the map, empty until it has been received
+    pub fn track(&self) -> &[TrackPoint] {
+        self.track.value.as_deref().unwrap_or_default()
+    }
+
+    /// Shows the map controls and the map
+    ///
+    /// browsed: time range browsed on the graphs, whose track is shown instead of the current session's
+    ///
+    /// replayed: where the track is being replayed, drawn as a second marker
+    ///
+    /// config: how the position under the mouse pointer and the track's legend are shown
+    ///
+    /// scroll_offset, lowest_edge: where the panel has been scrolled, which the map's overlays are drawn off by
+    #[allow(clippy::too_many_arguments)]
+    pub fn ui(&mut self, ui: &mut Ui, overlays: &Overlays, browsed: Option<[f64; 2]>, replayed: Option<Fix>, config: &Config, refresh_period: Duration, scroll_offset: f32, lowest_edge: f32) {
+        if browsed != self.track_range.get() {
+            self.track_range.set(browsed);
+            self.track.refresh();
+        }
+        // a browsed range doesn't grow, unlike the current session
+        let track = match browsed {
+            Some(_) => self.track.poll(),
+            None => self.track.poll_every(refresh_period),
+        };
+        if let Some(why) = self.track.error() {
+            error_banner(ui, &format!("GPS: {}", why));
+        }
+        let fix = self.fix.poll_every(refresh_period).flatten();
+        if let Some(why) = self.fix.error() {
+            error_banner(ui, &format!("GPS fix: {}", why));
+        }
+        let has_local_tiles = matches!(self.tile_set.poll_every(TILES_REFRESH), Some(Some(_)));
+        if let Some(why) = self.tile_set.error() {
+            error_banner(ui, &format!("Map tiles: {}", why));
+        }
+        let [lat, lon] = fix.map_or(DEFAULT_POSITION, |fix| fix.point.position);
+        let position = Position::from_lat_lon(lat, lon);
+
+        ui.horizontal(|ui| {
+            let provider = self.provider;
+            ComboBox::from_id_source("map_provider")
+                .selected_text(self.provider.as_str())
+                .show_ui(ui, |ui| {
+                    for provider in Provider::ALL {
+                        ui.selectable_value(&mut self.provider, provider, provider.as_str());
+                    }
+                });
+            if self.provider != provider {
+                save_value(PROVIDER_KEY, Some(self.provider.as_str()));
+            }
+
+            let mut layers_changed = false;
+            ui.menu_button("Layers", |ui| {
+                for layer in Layer::ALL {
+                    let mut shown = self.layers.contains(&layer);
+                    if ui.checkbox(&mut shown, layer.as_str()).changed() {
+                        self.layers.retain(|l| *l != layer);
+                        if shown {
+                            self.layers.push(layer);
+                        }
+                        layers_changed = true;
+                    }
+                }
+            });
+            if layers_changed {
+                let names: Vec<&str> = self.layers.iter().map(|layer| layer.as_str()).collect();
+                save_value(LAYERS_KEY, Some(&names.join(",")));
+            }
+
+            ComboBox::from_id_source("track_coloring")
+                .selected_text(self.track_coloring.as_str())
+                .show_ui(ui, |ui| {
+                    for coloring in TrackColoring::ALL {
+                        ui.selectable_value(&mut self.track_coloring, coloring, coloring.as_str());
+                    }
+                });
+
+            // the map stays centred on the latest fix until it is dragged away
+            let mut following = self.memory.detached().is_none();
+            if ui.toggle_value(&mut following, "⌖ Follow position").changed() {
+                if following {
+                    self.memory.follow_my_position();
+                } else {
+                    self.memory.center_at(position);
+                }
+            }
+
+            let fit = track.as_deref().and_then(|points| fit_track(points, Vec2::new(ui.available_width(), MAP_HEIGHT)));
+            if ui.add_enabled(fit.is_some(), Button::new("⛶ Fit track")).clicked() {
+                if let Some((center, zoom)) = fit {
+                    self.memory.center_at(center);
+                    let _ = self.memory.set_zoom(zoom);
+                }
+            }
+
+            self.geofences.toolbar(ui);
+        });
+        self.geofences.draft_controls(ui);
+
+        let grid = self.provider == Provider::Local && !has_local_tiles;
+        let tiles: Option<&mut dyn TilesManager> = match self.providers.get_mut(&self.provider) {
+            Some(tiles) if !grid => Some(tiles.as_mut()),
+            _ => None,
+        };
+        let mut map = Map::new(tiles, &mut self.memory, position);
+        if grid {
+            map = map.with_plugin(TileGrid::new([lat, lon], scroll_offset));
+        }
+
+        if let (Some(points), true) = (track, self.layers.contains(&Layer::Track)) {
+            map = map.with_plugin(GpsLine::new(points, self.track_coloring, config.units, scroll_offset));
+        }
+        if self.layers.contains(&Layer::Annotations) {
+            let pins: Vec<(LatLon, String)> = overlays.annotations.iter()
+                .filter_map(|annotation| Some((annotation.position?, annotation.text.clone())))
+                .collect();
+            map = map.with_plugin(Pins::new(pins, Color32::from_rgb(230, 160, 0), scroll_offset));
+        }
+        if self.layers.contains(&Layer::Alerts) {
+            let pins: Vec<(LatLon, String)> = overlays.events.iter()
+                .filter_map(|event| Some((event.position?, format!("⚠ {}", event.rule))))
+                .collect();
+            map = map.with_plugin(Pins::new(pins, ui.visuals().error_fg_color, scroll_offset));
+        }
+        if self.layers.contains(&Layer::Geofences) {
+            map = map.with_plugin(self.geofences.shapes(scroll_offset));
+        }
+        if let Some((shapes, clicks)) = self.geofences.draft([lat, lon], scroll_offset) {
+            map = map.with_plugin(shapes).with_plugin(clicks);
+        }
+        if let (Some(fix), true) = (fix, self.layers.contains(&Layer::Position)) {
+            map = map.with_plugin(PositionMarker::new(fix, Color32::from_rgb(20, 110, 230), scroll_offset));
+        }
+        if let Some(replayed) = replayed {
+            map = map.with_plugin(PositionMarker::new(replayed, Color32::from_rgb(150, 60, 200), scroll_offset));
+        }
+
+        map = map.with_plugin(CursorPosition::new([lat, lon], config.coordinate_format, scroll_offset));
+
+        let map_corner = ui.cursor().min + Vec2::new( 8.0,MAP_HEIGHT - 40.0);
+        ui.add_sized([ui.available_width(), MAP_HEIGHT], map);
+        if map_corner[1] <= lowest_edge {
+            zoom(ui, &mut self.memory, map_corner);
+        }
+
+        let zoom = self.memory.zoom();
+        if zoom != self.saved_zoom {
+            self.saved_zoom = zoom;
+            save_value(ZOOM_KEY, Some(&zoom.to_string()));
+        }
+    }
+}
+
+/// Centre and zoom showing every point of a track on a map of `size`, none for an empty track
+fn fit_track(points: &[TrackPoint], size: Vec2) -> Option<(Position, f64)> {
+    let first = points.first()?.position;
+    let [south, west, north, east] = points.iter().fold([first[0], first[1], first[0], first[1]], |[s, w, n, e], point| {
+        let [lat, lon] = point.position;
+        [s.min(lat), w.min(lon), n.max(lat), e.max(lon)]
+    });
+
+    // web mercator, as the map tiles are, where the whole world spans 1 across at zoom 0
+    let mercator = |lat: f64| (std::f64::consts::FRAC_PI_4 + lat.to_radians() / 2.0).tan().ln() / std::f64::consts::TAU;
+    let latitude = |y: f64| (2.0 * (y * std::f64::consts::TAU).exp().atan() - std::f64::consts::FRAC_PI_2).to_degrees();
+    let width = (east - west) / 360.0;
+    let height = mercator(north) - mercator(south);
+    let center = Position::from_lat_lon(latitude((mercator(north) + mercator(south)) / 2.0), (west + east) / 2.0);
+
+    let scale = |pixels: f32, span: f64| (pixels as f64 * FIT_MARGIN / (TILE_SIZE * span)).log2();
+    let zoom = scale(size.x, width).min(scale(size.y, height)).clamp(0.0, MAX_FIT_ZOOM);
+    Some((center, zoom))
+}
+
+pub fn zoom(ui: &Ui, map_memory: &mut MapMemory, location: Pos2) {
+    Window::new("Map")
+        .collapsible(false)
+        .resizable(false)
+        .title_bar(false)
+        .fixed_pos(location)
+        .show(ui.ctx(), |ui| {
+            ui.horizontal(|ui| {
+                if ui.button(RichText::new("➕").heading()).clicked() {
+                    let _ = map_memory.zoom_in();
+                }
+
+                if ui.button(RichText::new("➖").heading()).clicked() {
+                    let _ = map_memory.zoom_out();
+                }
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(position: LatLon) -> TrackPoint {
+        TrackPoint { time: 0.0, position, speed: 0.0, altitude: 0.0 }
+    }
+
+    #[test]
+    fn nothing_to_fit_without_a_track() {
+        assert!(fit_track(&[], vec2(400.0, 300.0)).is_none());
+    }
+
+    #[test]
+    fn a_single_fix_is_shown_close_up() {
+        let (center, zoom) = fit_track(&[point([44.5, -123.0])], vec2(400.0, 300.0)).unwrap();
+        assert!((center.lat() - 44.5).abs() < 1e-9);
+        assert!((center.lon() - -123.0).abs() < 1e-9);
+        assert_eq!(zoom, MAX_FIT_ZOOM);
+    }
+
+    #[test]
+    fn the_whole_track_fits_the_map() {
+        let track = [point([44.0, -123.0]), point([44.5, -122.2]), point([45.0, -122.0])];
+        let (center, zoom) = fit_track(&track, vec2(400.0, 400.0)).unwrap();
+        assert!((center.lon() - -122.5).abs() < 1e-9);
+        // the middle of the map in web mercator is north of the middle latitude
+        assert!(center.lat() > 44.5 && center.lat() < 44.51);
+        // a degree of latitude is taller than a degree of longitude is wide this far north, so it sets the zoom
+        assert!((zoom - 8.496).abs() < 0.01, "{}", zoom);
+
+        let (_, wider) = fit_track(&track, vec2(800.0, 400.0)).unwrap();
+        assert_eq!(wider, zoom);
+        let (_, taller) = fit_track(&track, vec2(400.0, 800.0)).unwrap();
+        assert!(taller > zoom);
+    }
+}
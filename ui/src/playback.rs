@@ -0,0 +1,169 @@
+use egui::*;
+use web_time::Instant;
+use protocol::{bearing, Fix, TrackPoint};
+use crate::utils::format_time;
+
+/// Speeds playback can run at, as multiples of real time
+const SPEEDS: [f64; 6] = [0.5, 1.0, 2.0, 5.0, 10.0, 30.0];
+
+/// Replays a recorded GPS track, moving a marker along it on the map and a cursor across the graphs
+pub struct Playback {
+    /// unix time being replayed, none while playback is off
+    time: Option<f64>,
+    playing: bool,
+    speed: f64,
+    /// when playback last advanced, while playing
+    advanced_at: Option<Instant>,
+}
+
+impl Playback {
+    pub fn new() -> Self {
+        Self {
+            time: None,
+            playing: false,
+            speed: 1.0,
+            advanced_at: None,
+        }
+    }
+
+    /// Shows the timeline of `track` with its controls, and advances the time being replayed
+    ///
+    /// returns: the time range of the track when playback has just been turned on, for the graphs to show it
+    pub fn ui(&mut self, ui: &mut Ui, track: &[TrackPoint]) -> Option<[f64; 2]> {
+        let range = match (track.first(), track.last()) {
+            (Some(first), Some(last)) if first.time < last.time => [first.time, last.time],
+            _ => {
+                self.stop();
+                return None;
+            },
+        };
+        let [start, end] = range;
+
+        if let (true, Some(time), Some(advanced_at)) = (self.playing, self.time, self.advanced_at) {
+            let time = time + advanced_at.elapsed().as_secs_f64() * self.speed;
+            self.time = Some(time.min(end));
+            self.playing = time < end;
+            ui.ctx().request_repaint();
+        }
+        self.advanced_at = self.playing.then(Instant::now);
+
+        let mut started = None;
+        ui.horizontal(|ui| {
+            let Some(time) = &mut self.time else {
+                if ui.button("⏯ Replay track").on_hover_text("Replay the track on the map and graphs").clicked() {
+                    self.time = Some(start);
+                    started = Some(range);
+                }
+                return;
+            };
+            *time = time.clamp(start, end);
+
+            let play = if self.playing { "⏸" } else { "▶" };
+            if ui.button(play).clicked() {
+                if !self.playing && *time >= end {
+                    *time = start;
+                }
+                self.playing = !self.playing;
+                self.advanced_at = self.playing.then(Instant::now);
+            }
+            let selected = &mut self.speed;
+            ComboBox::from_id_source("playback_speed")
+                .width(60.0)
+                .selected_text(format!("{}×", selected))
+                .show_ui(ui, |ui| {
+                    for speed in SPEEDS {
+                        ui.selectable_value(selected, speed, format!("{}×", speed));
+                    }
+                });
+            ui.label(format_time(*time * 1000.0));
+
+            let close = ui.button("✖").on_hover_text("Stop replaying");
+            ui.spacing_mut().slider_width = ui.available_width() - 8.0;
+            ui.add(Slider::new(time, start..=end).show_value(false));
+            if close.clicked() {
+                self.stop();
+            }
+        });
+        started
+    }
+
+    /// Unix time being replayed, none while playback is off
+    pub fn time(&self) -> Option<f64> {
+        self.time
+    }
+
+    fn stop(&mut self) {
+        self.time = None;
+        self.playing = false;
+        self.advanced_at = None;
+    }
+}
+
+/// Where the track was at `time`, between the fixes either side of it and heading from one to the other
+pub fn point_at(track: &[TrackPoint], time: f64) -> Option<Fix> {
+    let next = track.partition_point(|point| point.time < time);
+    let (before, after) = match (next.checked_sub(1).and_then(|i| track.get(i)), track.get(next)) {
+        (Some(before), Some(after)) => (before, after),
+        (None, Some(only)) | (Some(only), None) => (only, only),
+        (None, None) => return None,
+    };
+
+    let span = after.time - before.time;
+    let f = if span > 0.0 { ((time - before.time) / span).clamp(0.0, 1.0) } else { 0.0 };
+    let lerp = |a: f64, b: f64| a + (b - a) * f;
+    let point = TrackPoint {
+        time,
+        position: [lerp(before.position[0], after.position[0]), lerp(before.position[1], after.position[1])],
+        speed: lerp(before.speed, after.speed),
+        altitude: lerp(before.altitude, after.altitude),
+    };
+    let heading = (before.position != after.position).then(|| bearing(before.position, after.position));
+    Some(Fix { point, heading, accuracy: None })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track() -> Vec<TrackPoint> {
+        vec![
+            TrackPoint { time: 0.0, position: [0.0, 0.0], speed: 0.0, altitude: 100.0 },
+            TrackPoint { time: 10.0, position: [0.0, 0.001], speed: 10.0, altitude: 110.0 },
+            TrackPoint { time: 20.0, position: [0.001, 0.001], speed: 4.0, altitude: 110.0 },
+        ]
+    }
+
+    #[test]
+    fn nowhere_without_a_track() {
+        assert_eq!(point_at(&[], 5.0), None);
+    }
+
+    #[test]
+    fn between_fixes_the_track_is_interpolated() {
+        let fix = point_at(&track(), 5.0).unwrap();
+        assert_eq!(fix.point.time, 5.0);
+        assert_eq!(fix.point.position, [0.0, 0.0005]);
+        assert_eq!(fix.point.speed, 5.0);
+        assert_eq!(fix.point.altitude, 105.0);
+        // heading east, then north
+        assert!((fix.heading.unwrap() - 90.0).abs() < 1e-6);
+        assert!(point_at(&track(), 15.0).unwrap().heading.unwrap().abs() < 1e-6);
+    }
+
+    #[test]
+    fn at_a_fix_the_track_is_there() {
+        let fix = point_at(&track(), 10.0).unwrap();
+        assert_eq!(fix.point.position, [0.0, 0.001]);
+        assert_eq!(fix.point.speed, 10.0);
+    }
+
+    #[test]
+    fn outside_the_track_it_stays_at_its_ends() {
+        let before = point_at(&track(), -5.0).unwrap();
+        assert_eq!(before.point.position, [0.0, 0.0]);
+        assert_eq!(before.heading, None);
+        let after = point_at(&track(), 25.0).unwrap();
+        assert_eq!(after.point.position, [0.001, 0.001]);
+        assert_eq!(after.heading, None);
+    }
+}
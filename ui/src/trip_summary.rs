@@ -0,0 +1,104 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+use egui::*;
+use protocol::{Quantity, TripSummary, Units};
+use crate::{api, utils::{error_banner, format_time, PollableValue}};
+
+/// Distance, time, speed and elevation of the trip shown on the map, as a card
+pub struct TripCard {
+    /// summary of the time range browsed on the graphs, or of the current session while they follow live data
+    summary: PollableValue<Option<TripSummary>>,
+    range: Rc<Cell<Option<[f64; 2]>>>,
+}
+
+impl TripCard {
+    pub fn new(ctx: &Context) -> Self {
+        let range = Rc::new(Cell::new(None));
+        let requested = range.clone();
+        Self {
+            summary: PollableValue::new(ctx, move || api::trip_summary(requested.get())),
+            range,
+        }
+    }
+
+    /// browsed: time range browsed on the graphs, which is summarised instead of the current session
+    /// units: units the distances, speeds and elevations are shown in
+    pub fn ui(&mut self, ui: &mut Ui, browsed: Option<[f64; 2]>, units: &Units, refresh_period: Duration) {
+        if browsed != self.range.get() {
+            self.range.set(browsed);
+            self.summary.refresh();
+        }
+        // a browsed range doesn't grow, unlike the current session
+        let summary = match browsed {
+            Some(_) => self.summary.poll(),
+            None => self.summary.poll_every(refresh_period),
+        }.flatten();
+        if let Some(why) = self.summary.error() {
+            error_banner(ui, &format!("Trip summary: {}", why));
+        }
+        let Some(summary) = summary else {
+            return;
+        };
+
+        Frame::group(ui.style()).show(ui, |ui| {
+            let title = if browsed.is_some() { "Browsed trip" } else { "Current trip" };
+            ui.horizontal(|ui| {
+                ui.strong(title);
+                ui.label(format!("{} to {}", format_time(summary.start * 1000.0), format_time(summary.end * 1000.0)));
+            });
+            let [[south, west], [north, east]] = summary.bounds;
+            Grid::new("trip_summary").num_columns(4).spacing([24.0, 4.0]).show(ui, |ui| {
+                ui.label("Distance");
+                ui.strong(units.format_distance(summary.distance));
+                ui.label("Moving time");
+                ui.strong(format_duration(summary.moving_time));
+                ui.end_row();
+
+                ui.label("Max speed");
+                let speed = |speed: f64| format!("{:.1} {}", units.convert(Quantity::Speed, speed), units.symbol(Quantity::Speed));
+                ui.strong(speed(summary.max_speed));
+                ui.label("Average speed");
+                ui.strong(speed(summary.average_speed));
+                ui.end_row();
+
+                ui.label("Elevation gain");
+                let height = |height: f64| format!("{:.0} {}", units.convert_difference(Quantity::Length, height), units.symbol(Quantity::Length));
+                ui.strong(height(summary.elevation_gain));
+                ui.label("Elevation loss");
+                ui.strong(height(summary.elevation_loss));
+                ui.end_row();
+
+                ui.label("South west");
+                ui.strong(format!("{:.5}, {:.5}", south, west));
+                ui.label("North east");
+                ui.strong(format!("{:.5}, {:.5}", north, east));
+                ui.end_row();
+            });
+        });
+    }
+}
+
+/// Seconds as hours, minutes and seconds
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.max(0.0).round() as u64;
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_are_shown_as_hours_minutes_and_seconds() {
+        assert_eq!(format_duration(0.0), "0:00:00");
+        assert_eq!(format_duration(59.6), "0:01:00");
+        assert_eq!(format_duration(3725.0), "1:02:05");
+        assert_eq!(format_duration(90000.0), "25:00:00");
+    }
+
+    #[test]
+    fn negative_durations_are_none() {
+        assert_eq!(format_duration(-5.0), "0:00:00");
+    }
+}
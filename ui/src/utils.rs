@@ -1,3 +1,10 @@
+use futures::future::{AbortHandle, Abortable};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+use web_time::Instant;
+use crate::api::ApiError;
+
 pub fn set_panic_hook() {
     // When the `console_error_panic_hook` feature is enabled, we can call the
     // `set_panic_hook` function at least once during initialization, and then
@@ -9,31 +16,205 @@ pub fn set_panic_hook() {
     console_error_panic_hook::set_once();
 }
 
-/// Wrapper for a Promise. Can be polled to fill in its value.
+/// Spawns `future` as a Promise, waking egui up once it completes so the result is drawn
+/// without having to repaint every frame while waiting.
+pub fn spawn_repainting<T: Send + 'static>(
+    ctx: &egui::Context,
+    future: impl Future<Output = T> + 'static,
+) -> poll_promise::Promise<T> {
+    let ctx = ctx.clone();
+    poll_promise::Promise::spawn_local(async move {
+        let result = future.await;
+        ctx.request_repaint();
+        result
+    })
+}
+
+const RETRY_MIN: Duration = Duration::from_secs(1);
+const RETRY_MAX: Duration = Duration::from_secs(60);
+
+type Fetch<T> = Box<dyn Fn() -> Pin<Box<dyn Future<Output = Result<T, ApiError>>>>>;
+
+/// Wrapper for a value provided by the `api` client. Can be polled to fill in its value,
+/// and fetched again on a wall-clock schedule. Failed fetches are retried with an
+/// exponential backoff, keeping the last good value in the meantime.
 pub struct PollableValue<T: 'static + std::marker::Send + Clone> {
     pub value: Option<T>,
-    promise: poll_promise::Promise<Option<T>>,
+    promise: Option<poll_promise::Promise<Result<T, ApiError>>>,
+    fetch: Fetch<T>,
+    abort: Option<AbortHandle>,
+    fetched_at: Option<Instant>,
+    error: Option<ApiError>,
+    failures: u32,
+    updated_at: Option<f64>,
+    ctx: egui::Context,
 }
 
 impl<T: std::marker::Send + 'static + Clone> PollableValue<T> {
 
-    /// default_value: value to be used before poll is complete
-    /// 
-    /// promise: a Promise that will be polled
-    pub fn new(default_value: Option<T>, promise: poll_promise::Promise<Option<T>>) -> PollableValue<T> {
-        Self {
-            value: default_value,
-            promise,
-        }
+    /// ctx: context to repaint once a fetch completes
+    ///
+    /// fetch: creates the future requesting the value, the first request is sent immediately
+    pub fn new<F, Fut>(ctx: &egui::Context, fetch: F) -> PollableValue<T>
+    where
+        F: Fn() -> Fut + 'static,
+        Fut: Future<Output = Result<T, ApiError>> + 'static,
+    {
+        let mut value = Self {
+            value: None,
+            promise: None,
+            fetch: Box::new(move || Box::pin(fetch())),
+            abort: None,
+            fetched_at: None,
+            error: None,
+            failures: 0,
+            updated_at: None,
+            ctx: ctx.clone(),
+        };
+        value.refresh();
+        value
     }
 
     /// Polls the promise and returns it's value if ready
     ///
-    /// returns: value if ready or default exists, none otherwise
+    /// returns: latest value received, none if nothing has been received yet
     pub fn poll(&mut self) -> Option<T> {
-        if let Some(result) = self.promise.ready() {
-            self.value = <Option<T> as Clone>::clone(&result);
+        if let Some(result) = self.promise.as_ref().and_then(|p| p.ready()) {
+            match result {
+                Ok(value) => {
+                    self.value = Some(value.clone());
+                    self.error = None;
+                    self.failures = 0;
+                    self.updated_at = Some(js_sys::Date::now());
+                },
+                Err(ApiError::Cancelled) => {},
+                Err(why) => {
+                    self.error = Some(why.clone());
+                    self.failures += 1;
+                },
+            }
+            self.promise = None;
+            self.fetched_at = Some(Instant::now());
         }
         self.value.clone()
     }
-}
\ No newline at end of file
+
+    /// Polls the value, fetching it again once `period` has passed since the last fetch completed,
+    /// or sooner if it failed. Schedules a repaint for when the next fetch is due, so egui can idle in between.
+    pub fn poll_every(&mut self, period: Duration) -> Option<T> {
+        let value = self.poll();
+        if let Some(fetched_at) = self.fetched_at {
+            let wait = match self.failures {
+                0 => period,
+                n => RETRY_MIN.saturating_mul(1 << (n - 1).min(6)).min(RETRY_MAX),
+            };
+            let elapsed = fetched_at.elapsed();
+            if elapsed >= wait {
+                self.refresh();
+            } else {
+                self.ctx.request_repaint_after(wait - elapsed);
+            }
+        }
+        value
+    }
+
+    /// Sends a new request now, cancelling one still in flight
+    pub fn refresh(&mut self) {
+        if let Some(abort) = self.abort.take() {
+            abort.abort();
+        }
+        let (abort, registration) = AbortHandle::new_pair();
+        let fetch = Abortable::new((self.fetch)(), registration);
+        self.abort = Some(abort);
+        self.promise = Some(spawn_repainting(&self.ctx, async move {
+            fetch.await.unwrap_or(Err(ApiError::Cancelled))
+        }));
+    }
+
+    /// Forgets the current value and fetches it again, for when what is being fetched has changed
+    pub fn reset(&mut self) {
+        self.value = None;
+        self.error = None;
+        self.failures = 0;
+        self.updated_at = None;
+        self.refresh();
+    }
+
+    /// The most recent failed fetch, cleared once a fetch succeeds
+    pub fn error(&self) -> Option<&ApiError> {
+        self.error.as_ref()
+    }
+
+    /// When the value was last received, in milliseconds since the unix epoch
+    pub fn updated_at(&self) -> Option<f64> {
+        self.updated_at
+    }
+}
+
+/// Reads a value saved in the browser's local storage
+pub fn load_value(key: &str) -> Option<String> {
+    local_storage()?.get_item(key).ok().flatten()
+}
+
+/// Saves a value in the browser's local storage, or removes it if `None`
+pub fn save_value(key: &str, value: Option<&str>) {
+    if let Some(storage) = local_storage() {
+        let _ = match value {
+            Some(v) => storage.set_item(key, v),
+            None => storage.remove_item(key),
+        };
+    }
+}
+
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok().flatten()
+}
+
+/// Offers `contents` to the user as a file called `file_name`, of the MIME type `mime`
+pub fn download(file_name: &str, contents: &str, mime: &str) -> Result<(), String> {
+    use wasm_bindgen::JsCast;
+    let fail = |why: wasm_bindgen::JsValue| format!("couldn't download {}: {:?}", file_name, why);
+
+    let options = web_sys::BlobPropertyBag::new();
+    options.set_type(mime);
+    let blob = web_sys::Blob::new_with_str_sequence_and_options(&js_sys::Array::of1(&contents.into()), &options)
+        .map_err(fail)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(fail)?;
+
+    let window = web_sys::window().ok_or("couldn't download, there is no window")?;
+    let link: web_sys::HtmlAnchorElement = window.document()
+        .ok_or("couldn't download, there is no document")?
+        .create_element("a")
+        .map_err(fail)?
+        .dyn_into()
+        .map_err(|_| "couldn't download, links can't be made".to_string())?;
+    link.set_href(&url);
+    link.set_download(file_name);
+    link.click();
+
+    // the browser reads the file once the click is handled, after which it's no longer needed
+    let revoke = wasm_bindgen::closure::Closure::once_into_js(move || {
+        let _ = web_sys::Url::revoke_object_url(&url);
+    });
+    window.set_timeout_with_callback_and_timeout_and_arguments_0(revoke.unchecked_ref(), 1000)
+        .map(|_| ())
+        .map_err(fail)
+}
+
+/// Shows `message` in a red frame spanning the panel
+pub fn error_banner(ui: &mut egui::Ui, message: &str) {
+    egui::Frame::none()
+        .fill(ui.visuals().extreme_bg_color)
+        .stroke(egui::Stroke::new(1.0, ui.visuals().error_fg_color))
+        .inner_margin(6.0)
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            ui.colored_label(ui.visuals().error_fg_color, message);
+        });
+}
+
+/// Formats a time in milliseconds since the unix epoch as a local time of day
+pub fn format_time(millis: f64) -> String {
+    let date = js_sys::Date::new(&millis.into());
+    format!("{:02}:{:02}:{:02}", date.get_hours(), date.get_minutes(), date.get_seconds())
+}